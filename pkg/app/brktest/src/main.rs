@@ -18,7 +18,7 @@ fn main() -> isize {
 
     println!("heap_start = {:#x}, heap_end = {:#x}, ret = {:#x}", heap_start, heap_end, ret);
     println!("Heap allocated successfully, ret = {:#x}", ret);
-    // heap_start = 0x200000000000, heap_end = 0x200000001ff8, ret = 0x200000001ff8
+    // the heap of the allocator is before heap_start
 
     let ptr = heap_start as *mut u8;
 
@@ -42,6 +42,12 @@ fn main() -> isize {
     assert!(ret == heap_start, "Failed to deallocate heap");
     println!("Heap deallocated successfully, ret = {:#x}", ret);
 
+    // the allocator grows the heap as needed
+    let mut buf = vec![0u8; 1024 * 1024];
+    buf.iter_mut().enumerate().for_each(|(i, b)| *b = i as u8);
+    assert!(buf.iter().enumerate().all(|(i, b)| *b == i as u8));
+    println!("Allocated {:#x} bytes, heap end = {:#x}", buf.len(), sys_brk(None).unwrap());
+
     233
 }
 
@@ -14,13 +14,16 @@ static SEM: Semaphore = Semaphore::new(0);
 static SPIN: SpinLock = SpinLock::new();
 
 fn main() -> isize {
-    let pid = sys_fork();
-
-    if pid == 0 {
-        test_semaphore();
-    } else {
-        test_spin();
-        sys_wait_pid(pid);
+    match sys_fork() {
+        Some(0) => test_semaphore(),
+        Some(pid) => {
+            test_spin();
+            sys_wait_pid(pid);
+        }
+        None => {
+            println!("Failed to fork");
+            return 1;
+        }
     }
     0
 }
@@ -29,22 +32,14 @@ fn test_semaphore() {
     let mut pids = [0u16; THREAD_COUNT];
     SEM.init(1);
 
-    for i in 0..THREAD_COUNT {
-        let pid = sys_fork();
-        if pid == 0 {
-            do_counter_inc_semaphore();
-            sys_exit(0);
-        } else {
-            pids[i] = pid;
-        }
-    }
+    let count = fork_threads(&mut pids, do_counter_inc_semaphore);
 
     let cpid = sys_get_pid();
-    println!("[Semaphore] process #{} holds threads: {:?}", cpid, &pids);
+    println!("[Semaphore] process #{} holds threads: {:?}", cpid, &pids[..count]);
     sys_stat(); // 保留系统调用
-    for i in 0..THREAD_COUNT {
-        println!("[Semaphore] #{} waiting for #{}...", cpid, pids[i]);
-        sys_wait_pid(pids[i]);
+    for &pid in &pids[..count] {
+        println!("[Semaphore] #{} waiting for #{}...", cpid, pid);
+        sys_wait_pid(pid);
     }
     println!("[Semaphore] COUNTER result: {}", unsafe { COUNTER_SEM });
 }
@@ -52,26 +47,36 @@ fn test_semaphore() {
 fn test_spin() {
     let mut pids = [0u16; THREAD_COUNT];
 
-    for i in 0..THREAD_COUNT {
-        let pid = sys_fork();
-        if pid == 0 {
-            do_counter_inc_spinlock();
-            sys_exit(0);
-        } else {
-            pids[i] = pid;
-        }
-    }
+    let count = fork_threads(&mut pids, do_counter_inc_spinlock);
 
     let cpid = sys_get_pid();
-    println!("[SpinLock] process #{} holds threads: {:?}", cpid, &pids);
+    println!("[SpinLock] process #{} holds threads: {:?}", cpid, &pids[..count]);
     sys_stat(); // 保留系统调用
-    for i in 0..THREAD_COUNT {
-        println!("[SpinLock] #{} waiting for #{}...", cpid, pids[i]);
-        sys_wait_pid(pids[i]);
+    for &pid in &pids[..count] {
+        println!("[SpinLock] #{} waiting for #{}...", cpid, pid);
+        sys_wait_pid(pid);
     }
     println!("[SpinLock] COUNTER result: {}", unsafe { COUNTER_SPIN });
 }
 
+/// Fork a child running `f` for each of `pids`, returns how many are forked.
+fn fork_threads(pids: &mut [u16], f: fn()) -> usize {
+    for (i, slot) in pids.iter_mut().enumerate() {
+        match sys_fork() {
+            Some(0) => {
+                f();
+                sys_exit(0);
+            }
+            Some(pid) => *slot = pid,
+            None => {
+                println!("Failed to fork thread {}", i);
+                return i;
+            }
+        }
+    }
+    pids.len()
+}
+
 fn do_counter_inc_semaphore() {
     for _ in 0..100 {
         SEM.wait();
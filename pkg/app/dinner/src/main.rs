@@ -19,19 +19,28 @@ fn main() -> isize {
     WAITER.init(N - 1);
 
     let mut pids = [0u16; N];
+    let mut forked = 0;
     for i in 0..N {
-        let pid = sys_fork();
-        if pid == 0 {
-            philosopher(i);
-            sys_exit(0);
-        } else {
-            pids[i] = pid;
+        match sys_fork() {
+            Some(0) => {
+                philosopher(i);
+                sys_exit(0);
+            }
+            Some(pid) => {
+                pids[i] = pid;
+                forked += 1;
+            }
+            // the others can still eat, a chopstick is left unused
+            None => {
+                println!("Failed to seat philosopher {}", i);
+                break;
+            }
         }
     }
-    for pid in pids {
+    for &pid in &pids[..forked] {
         sys_wait_pid(pid);
     }
-    0
+    (forked != N) as isize
 }
 
 fn philosopher(idx: usize) {
@@ -41,10 +50,10 @@ fn philosopher(idx: usize) {
         // 固定时间思考
         let think_time = 150;
         println!(
-            "Philosopher {} is thinking (pid {}, round {}, {} ticks)",
+            "Philosopher {} is thinking (pid {}, round {}, {} ms)",
             idx, pid, round, think_time
         );
-        delay(think_time);
+        sys_sleep(think_time);
 
         // println!("Philosopher {} is hungry (pid {}, round {})", idx, pid, round);
         let left = idx;
@@ -55,16 +64,16 @@ fn philosopher(idx: usize) {
 
         CHOPSTICK[left].wait();
         // 拿筷子之间可加一点延迟模拟动作
-        delay(5);
+        sys_sleep(5);
         CHOPSTICK[right].wait();
 
         // 固定时间吃饭
         let eat_time = 80;
         println!(
-            "Philosopher {} is eating (pid {}, round {}, {} ticks)",
+            "Philosopher {} is eating (pid {}, round {}, {} ms)",
             idx, pid, round, eat_time
         );
-        delay(eat_time);
+        sys_sleep(eat_time);
 
         CHOPSTICK[left].signal();
         CHOPSTICK[right].signal();
@@ -74,11 +83,4 @@ fn philosopher(idx: usize) {
     }
 }
 
-#[inline(never)]
-fn delay(times: u32) {
-    for _ in 0..(times * 100) {
-        core::hint::spin_loop();
-    }
-}
-
 entry!(main);
\ No newline at end of file
@@ -12,9 +12,8 @@ fn main() -> isize {
     let mut c = 32;
     let m_ptr = &raw mut M;
 
-    // do not alloc heap before `fork`
-    // which may cause unexpected behavior since we won't copy the heap in `fork`
-    let pid = sys_fork();
+    // the child has its own copy of the memory, written pages are copied
+    let pid = sys_fork().expect("Failed to fork");
 
     if pid == 0 {
         println!("I am the child process");
@@ -45,7 +44,7 @@ fn main() -> isize {
 
         unsafe {
             println!("parent read value of M: {:#x}", *m_ptr);
-            assert_eq!(*m_ptr, 0x2333);
+            assert_eq!(*m_ptr, 0xdeadbeef);
         }
 
         c += 1024;
@@ -2,7 +2,7 @@
 #![no_main]
 
 extern crate lib;
-use lib::{sync::Semaphore, *};
+use lib::{signal::SIGKILL, sync::Semaphore, *};
 
 const NPROC: usize = 16;          // 总进程数
 const NMSG: usize = 10;           // 每个生产者/消费者消息数
@@ -23,16 +23,25 @@ fn main() -> isize {
     let mut pids = [0u16; NPROC];
 
     for i in 0..NPROC {
-        let pid = sys_fork();
-        if pid == 0 {
-            if i < NPROC / 2 {
-                producer(i as u16);
-            } else {
-                consumer(i as u16);
+        match sys_fork() {
+            Some(0) => {
+                if i < NPROC / 2 {
+                    producer(i as u16);
+                } else {
+                    consumer(i as u16);
+                }
+                sys_exit(0);
+            }
+            Some(pid) => pids[i] = pid,
+            None => {
+                // the messages would not match without every child
+                println!("Parent: failed to fork child {}", i);
+                for &pid in &pids[..i] {
+                    sys_kill(pid, SIGKILL);
+                    sys_wait_pid(pid);
+                }
+                return -1;
             }
-            sys_exit(0);
-        } else {
-            pids[i] = pid;
         }
     }
 
@@ -0,0 +1,65 @@
+#![no_std]
+#![no_main]
+
+extern crate lib;
+
+use lib::mman::*;
+use lib::signal::SIGSEGV;
+use lib::*;
+
+/// `mov eax, 233; ret`
+const CODE: [u8; 6] = [0xb8, 0xe9, 0x00, 0x00, 0x00, 0xc3];
+
+/// Run `f` in a child process, which must be killed by SIGSEGV
+fn expect_segv(name: &str, f: fn() -> isize) {
+    let pid = sys_fork().expect("Failed to fork");
+
+    if pid == 0 {
+        let ret = f();
+        println!("{}: executed, returned {}", name, ret);
+        sys_exit(0);
+    }
+
+    let ret = sys_wait_pid(pid);
+    assert_eq!(ret, 128 + SIGSEGV as isize, "{} was executed", name);
+    println!("{}: killed by SIGSEGV", name);
+}
+
+fn call(code: *const u8) -> isize {
+    let f: extern "C" fn() -> u32 = unsafe { core::mem::transmute(code) };
+    f() as isize
+}
+
+fn exec_stack() -> isize {
+    let code = CODE;
+    call(core::hint::black_box(&code).as_ptr())
+}
+
+fn exec_mmap() -> isize {
+    let ptr = map_anonymous(4096, PROT_READ | PROT_WRITE).expect("Failed to map memory");
+    unsafe { core::ptr::copy_nonoverlapping(CODE.as_ptr(), ptr, CODE.len()) };
+    call(ptr)
+}
+
+fn main() -> isize {
+    expect_segv("stack", exec_stack);
+    expect_segv("mmap", exec_mmap);
+
+    // W^X, a page can not be both writable and executable
+    assert!(map_anonymous(4096, PROT_READ | PROT_WRITE | PROT_EXEC).is_none());
+
+    let ptr = map_anonymous(4096, PROT_READ | PROT_WRITE).expect("Failed to map memory");
+    assert!(!sys_mprotect(ptr as usize, 4096, PROT_WRITE | PROT_EXEC));
+    println!("W^X mappings are rejected");
+
+    // code is read-only
+    expect_segv("code", || {
+        let code = main as *const () as *mut u8;
+        unsafe { code.write_volatile(0xc3) };
+        0
+    });
+
+    0
+}
+
+entry!(main);
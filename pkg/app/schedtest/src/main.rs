@@ -0,0 +1,102 @@
+#![no_std]
+#![no_main]
+
+extern crate lib;
+use lib::{signal::SIGKILL, sync::Semaphore, *};
+
+const NWORKERS: usize = 8; // 传递令牌的进程数
+const NSPINNERS: usize = 4; // 不阻塞的计算进程数
+const ROUNDS: usize = 200; // 每个进程拿到令牌的次数
+const SPIN_ITERS: u64 = 0x20_0000;
+
+const KEY_BASE: u32 = 0x3000;
+
+fn sem(i: usize) -> Semaphore {
+    Semaphore::new(KEY_BASE + i as u32)
+}
+
+fn main() -> isize {
+    // 令牌从 0 号进程开始，每个进程等自己的信号量再唤醒下一个，
+    // 所以任何时候只有一个进程就绪，其余都处于阻塞状态
+    for i in 0..NWORKERS {
+        sem(i).init(if i == 0 { 1 } else { 0 });
+    }
+
+    let mut pids = [0u16; NWORKERS + NSPINNERS];
+
+    for i in 0..pids.len() {
+        match sys_fork() {
+            Some(0) => {
+                let code = if i < NWORKERS { worker(i) } else { spinner(i) };
+                sys_exit(code);
+            }
+            Some(pid) => pids[i] = pid,
+            None => {
+                // the token would never go round without every worker
+                println!("schedtest: failed to fork #{}", i);
+                for &pid in &pids[..i] {
+                    sys_kill(pid, SIGKILL);
+                    sys_wait_pid(pid);
+                }
+                remove_sems();
+                return 1;
+            }
+        }
+    }
+
+    println!("schedtest: {} workers x {} rounds, {} spinners", NWORKERS, ROUNDS, NSPINNERS);
+    sys_stat();
+
+    let mut failed = 0;
+    for (i, &pid) in pids.iter().enumerate() {
+        let ret = sys_wait_pid(pid);
+        let expected = if i < NWORKERS { ROUNDS as isize } else { i as isize };
+        if ret != expected {
+            println!("schedtest: #{} exited with {}, expected {}", pid, ret, expected);
+            failed += 1;
+        }
+    }
+
+    remove_sems();
+
+    if failed == 0 {
+        println!("schedtest: passed, no process was lost.");
+    } else {
+        println!("schedtest: {} process(es) failed.", failed);
+    }
+
+    failed
+}
+
+fn remove_sems() {
+    for i in 0..NWORKERS {
+        sem(i).remove();
+    }
+}
+
+/// Waits for the token and passes it to the next worker, returns the rounds done.
+fn worker(i: usize) -> isize {
+    let next = sem((i + 1) % NWORKERS);
+
+    let mut rounds = 0;
+    for _ in 0..ROUNDS {
+        sem(i).wait();
+        rounds += 1;
+        next.signal();
+    }
+
+    rounds
+}
+
+/// Keeps the CPUs busy so the workers are also preempted, returns its index.
+fn spinner(i: usize) -> isize {
+    let mut x = i as u64;
+    for _ in 0..SPIN_ITERS {
+        x = core::hint::black_box(x.wrapping_mul(6364136223846793005).wrapping_add(1));
+    }
+    core::hint::black_box(x);
+
+    i as isize
+}
+
+entry!(main);
@@ -57,6 +57,8 @@ fn normalize_path(path: &str, current_dir: &str) -> String {
 fn main() -> isize {
     println!("YatSenOS Shell 启动");
     loop {
+        // 上一个程序可能把终端留在了 raw 模式
+        tty::set_mode(tty::DEFAULT_MODE);
         // print!("shell> ");
         print!("\x1b[32m{}[>]\x1b[0m ", unsafe { CURRENT_DIR });
         let input = stdin().read_line();
@@ -68,6 +70,11 @@ fn main() -> isize {
             ["lsapp"] => sys_list_app(),
             ["ps"] => sys_stat(),
             ["clear"] => print!("\x1b[2J\x1b[H"),
+            ["date"] => println!("{}", now().format("%Y-%m-%d %H:%M:%S UTC")),
+            ["uptime"] => {
+                let up = uptime();
+                println!("up {}.{:03} s", up.as_secs(), up.subsec_millis());
+            }
             ["help"] => show_help(),
             ["ls"] => sys_list_dir(unsafe{CURRENT_DIR}),
             ["ls", path] => sys_list_dir(&normalize_path(path, unsafe { CURRENT_DIR })),
@@ -100,7 +107,45 @@ fn main() -> isize {
                 if pid == 0 {
                     println!("shell: 无法启动程序: {}", normalized_path);
                 } else {
-                    sys_wait_pid(pid);
+                    wait_foreground(pid);
+                }
+            }
+            ["fg", pid] => match pid.parse::<u16>() {
+                Ok(pid) if sys_kill(pid, signal::SIGCONT) => wait_foreground(pid),
+                _ => println!("fg: 无效的进程: {}", pid),
+            },
+            ["taskset", pid] => match pid.parse::<u16>().ok().and_then(sys_get_affinity) {
+                Some(mask) => println!("pid {}'s affinity mask: {:#x}", pid, mask),
+                None => println!("taskset: 无效的进程: {}", pid),
+            },
+            ["taskset", pid, mask] => {
+                let pid = pid.parse::<u16>().ok();
+                let mask = u64::from_str_radix(mask.trim_start_matches("0x"), 16).ok();
+                match pid.zip(mask) {
+                    Some((pid, mask)) if sys_set_affinity(pid, mask) => {}
+                    _ => println!("taskset: 设置失败"),
+                }
+            }
+            ["nice", pid] => match pid.parse::<u16>().ok().and_then(sys_get_priority) {
+                Some(nice) => println!("pid {}'s nice value: {}", pid, nice),
+                None => println!("nice: 无效的进程: {}", pid),
+            },
+            ["nice", pid, nice] => {
+                let pid = pid.parse::<u16>().ok();
+                let nice = nice.parse::<i8>().ok();
+                match pid.zip(nice) {
+                    Some((pid, nice)) if sys_set_priority(pid, nice) => {}
+                    _ => println!("nice: 设置失败"),
+                }
+            }
+            ["kill", pid] | ["kill", pid, _] => {
+                let sig = match args.get(2) {
+                    Some(sig) => sig.trim_start_matches('-').parse::<usize>().ok(),
+                    None => Some(signal::SIGTERM),
+                };
+                match pid.parse::<u16>().ok().zip(sig) {
+                    Some((pid, sig)) if sys_kill(pid, sig) => {}
+                    _ => println!("kill: 发送信号失败"),
                 }
             }
             [] => continue,
@@ -109,6 +154,15 @@ fn main() -> isize {
     }
 }
 
+/// Waits for the process to exit or stop, Ctrl-C and Ctrl-Z are sent to it meanwhile.
+fn wait_foreground(pid: u16) {
+    tty::set_foreground(Some(pid));
+    if sys_wait_pid_untraced(pid).is_none() {
+        println!("[{}] 已暂停, 使用 fg {} 继续运行", pid, pid);
+    }
+    tty::set_foreground(None);
+}
+
 fn show_help() {
     println!("\x1b[33m============== YatSenOS Shell 帮助 ==============\x1b[0m");
     println!("作者: 黄镇邦 23342035");
@@ -118,11 +172,19 @@ fn show_help() {
     println!("  ls <path>   - 列出指定路径下的文件和目录");
     println!("  cat <file>  - 显示文件内容");
     println!("  ps           - 显示当前所有进程");
+    println!("  taskset <pid> [mask] - 查看或设置进程的 CPU 亲和性 (十六进制掩码)");
+    println!("  nice <pid> [value]   - 查看或设置进程的 nice 值 (-20 ~ 19)");
+    println!("  kill <pid> [sig]     - 向进程发送信号 (默认 15, SIGTERM)");
+    println!("  fg <pid>     - 继续运行被 Ctrl-Z 暂停的进程");
     println!("  run hello    - 运行 hello 程序");
     println!("  run fac      - 运行阶乘计算程序");
     println!("  run forktest - 运行 fork 测试程序");
+    println!("  run schedtest - 运行调度测试程序");
+    println!("  date         - 显示当前日期和时间");
+    println!("  uptime       - 显示系统运行时间");
     println!("  clear        - 清空屏幕");
     println!("  help         - 显示此帮助信息");
+    println!("\x1b[36m运行程序时:\x1b[0m Ctrl-C 终止, Ctrl-Z 暂停");
     println!("\x1b[33m================================================\x1b[0m");
 }
 
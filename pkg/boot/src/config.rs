@@ -17,6 +17,12 @@ pub struct Config<'a> {
     pub cmdline: &'a str,
     /// Load apps into memory, when no fs implemented in kernel
     pub load_apps: bool,
+    /// The horizontal resolution of the GOP mode, 0 means keep the current mode
+    pub framebuffer_width: usize,
+    /// The vertical resolution of the GOP mode, 0 means keep the current mode
+    pub framebuffer_height: usize,
+    /// The frequency of the scheduler tick in Hz
+    pub tick_frequency: u64,
 }
 
 const DEFAULT_CONFIG: Config = Config {
@@ -27,6 +33,9 @@ const DEFAULT_CONFIG: Config = Config {
     kernel_path: "\\KERNEL.ELF",
     cmdline: "",
     load_apps: false,
+    framebuffer_width: 0,
+    framebuffer_height: 0,
+    tick_frequency: 100,
 };
 
 impl<'a> Config<'a> {
@@ -65,6 +74,9 @@ impl<'a> Config<'a> {
             "kernel_stack_auto_grow" => self.kernel_stack_auto_grow = r10,
             "cmdline" => self.cmdline = value,
             "load_apps" => self.load_apps = r10 != 0,
+            "framebuffer_width" => self.framebuffer_width = r10 as usize,
+            "framebuffer_height" => self.framebuffer_height = r10 as usize,
+            "tick_frequency" => self.tick_frequency = r10,
             _ => warn!("undefined config key: {}", key),
         }
     }
@@ -3,7 +3,7 @@
 pub use uefi::boot::{MemoryAttribute, MemoryDescriptor, MemoryType};
 pub use uefi::data_types::chars::*;
 pub use uefi::data_types::*;
-pub use uefi::proto::console::gop::{GraphicsOutput, ModeInfo};
+pub use uefi::proto::console::gop::{GraphicsOutput, ModeInfo, PixelFormat};
 pub use uefi::Status;
 
 use arrayvec::{ArrayString, ArrayVec};
@@ -41,6 +41,27 @@ pub type AppList = ArrayVec<App<'static>, MAX_APPS>;
 pub type AppListRef = Option<&'static AppList>;
 pub type KernelPages = ArrayVec<PageRangeInclusive, 8>;
 
+/// 内核命令行的最大长度
+pub const MAX_CMDLINE: usize = 256;
+pub type Cmdline = ArrayString<MAX_CMDLINE>;
+
+/// The framebuffer set up by the bootloader through GOP.
+#[derive(Debug, Clone, Copy)]
+pub struct FrameBufferInfo {
+    /// The physical address of the framebuffer
+    pub base: u64,
+    /// The size of the framebuffer in bytes
+    pub size: usize,
+    /// The horizontal resolution in pixels
+    pub width: usize,
+    /// The vertical resolution in pixels
+    pub height: usize,
+    /// The number of pixels per scan line, may be larger than `width`
+    pub stride: usize,
+    /// The layout of a 32-bit pixel
+    pub format: PixelFormat,
+}
+
 /// This structure represents the information that the bootloader passes to the kernel.
 pub struct BootInfo {
     /// The memory map
@@ -57,6 +78,18 @@ pub struct BootInfo {
 
     // Kernel pages
     pub kernel_pages: KernelPages,
+
+    /// The framebuffer, if GOP is available
+    pub framebuffer: Option<FrameBufferInfo>,
+
+    /// The frequency of the scheduler tick in Hz
+    pub tick_frequency: u64,
+
+    /// The physical address of the ACPI RSDP, if found in the config table
+    pub rsdp_addr: Option<u64>,
+
+    /// The kernel command line, space separated `key=value` options
+    pub cmdline: Cmdline,
 }
 
 /// Get current page table from CR3
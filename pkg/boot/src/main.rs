@@ -10,8 +10,11 @@ use alloc::boxed::Box;
 use alloc::vec;
 use uefi::{entry, Status};
 use x86_64::registers::control::*;
+use x86_64::registers::model_specific::{Efer, EferFlags};
 use ysos_boot::*;
 use uefi::mem::memory_map::MemoryMap;
+use uefi::proto::console::gop::{GraphicsOutput, PixelFormat};
+use uefi::table::cfg::{ACPI2_GUID, ACPI_GUID};
 
 mod config;
 
@@ -62,6 +65,11 @@ fn efi_main() -> Status {
         Cr0::update(|f| f.remove(Cr0Flags::WRITE_PROTECT));
     }
 
+    // the NO_EXECUTE bit of the mappings below is reserved until NXE is set
+    unsafe {
+        Efer::update(|f| f.insert(EferFlags::NO_EXECUTE_ENABLE));
+    }
+
     // FIXME: map physical memory to specific virtual address offset
     elf::map_physical_memory(
         config.physical_memory_offset,
@@ -113,10 +121,15 @@ fn efi_main() -> Status {
         None
     };
 
+    // Setup framebuffer
+    let framebuffer = init_graphics(&config);
+
     // 5. Pass system table to kernel
     let ptr = uefi::table::system_table_raw().expect("Failed to get system table");
     let system_table = ptr.cast::<core::ffi::c_void>();
 
+    let rsdp_addr = find_rsdp();
+
 
     // 6. Exit boot and jump to ELF entry
     info!("Exiting boot services...");
@@ -135,7 +148,14 @@ fn efi_main() -> Status {
         physical_memory_offset: config.physical_memory_offset,
         system_table,
         loaded_apps: apps,
-        kernel_pages
+        kernel_pages,
+        framebuffer,
+        tick_frequency: config.tick_frequency,
+        rsdp_addr,
+        cmdline: Cmdline::from(config.cmdline).unwrap_or_else(|_| {
+            warn!("Kernel cmdline is longer than {} bytes, ignored.", MAX_CMDLINE);
+            Cmdline::new()
+        }),
     };
 
     // align stack to 8 bytes
@@ -143,3 +163,69 @@ fn efi_main() -> Status {
 
     jump_to_entry(&bootinfo, stacktop);
 }
+
+/// Find the ACPI RSDP in the UEFI config table, prefer the ACPI 2.0 one
+fn find_rsdp() -> Option<u64> {
+    let rsdp = uefi::system::with_config_table(|entries| {
+        let find = |guid| {
+            entries
+                .iter()
+                .find(|entry| entry.guid == guid)
+                .map(|entry| entry.address as u64)
+        };
+        find(ACPI2_GUID).or_else(|| find(ACPI_GUID))
+    });
+
+    match rsdp {
+        Some(addr) => info!("ACPI RSDP: {:#x}", addr),
+        None => warn!("ACPI RSDP not found"),
+    }
+
+    rsdp
+}
+
+/// Select the GOP mode from config and collect the framebuffer information
+fn init_graphics(config: &config::Config) -> Option<FrameBufferInfo> {
+    let Ok(handle) = uefi::boot::get_handle_for_protocol::<GraphicsOutput>() else {
+        warn!("GOP not found, framebuffer disabled");
+        return None;
+    };
+
+    let mut gop = uefi::boot::open_protocol_exclusive::<GraphicsOutput>(handle).ok()?;
+
+    if config.framebuffer_width > 0 && config.framebuffer_height > 0 {
+        let target = (config.framebuffer_width, config.framebuffer_height);
+        let mode = gop.modes().find(|mode| {
+            let info = mode.info();
+            info.resolution() == target
+                && matches!(info.pixel_format(), PixelFormat::Rgb | PixelFormat::Bgr)
+        });
+
+        match mode {
+            Some(mode) => gop.set_mode(&mode).expect("Failed to set GOP mode"),
+            None => warn!("GOP mode {}x{} not found, keep current mode", target.0, target.1),
+        }
+    }
+
+    let info = gop.current_mode_info();
+    let (width, height) = info.resolution();
+
+    if !matches!(info.pixel_format(), PixelFormat::Rgb | PixelFormat::Bgr) {
+        warn!("Unsupported pixel format {:?}, framebuffer disabled", info.pixel_format());
+        return None;
+    }
+
+    let mut fb = gop.frame_buffer();
+    let framebuffer = FrameBufferInfo {
+        base: fb.as_mut_ptr() as u64,
+        size: fb.size(),
+        width,
+        height,
+        stride: info.stride(),
+        format: info.pixel_format(),
+    };
+
+    info!("Framebuffer: {:#x?}", framebuffer);
+
+    Some(framebuffer)
+}
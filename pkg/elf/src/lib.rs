@@ -15,7 +15,8 @@ use xmas_elf::{program, ElfFile};
 
 /// Map physical memory
 ///
-/// map [0, max_addr) to virtual space [offset, offset + max_addr)
+/// map [0, max_addr) to virtual space [offset, offset + max_addr),
+/// which is only used as data and never executed
 pub fn map_physical_memory(
     offset: u64,
     max_addr: u64,
@@ -28,7 +29,8 @@ pub fn map_physical_memory(
 
     for frame in PhysFrame::range_inclusive(start_frame, end_frame) {
         let page = Page::containing_address(VirtAddr::new(frame.start_address().as_u64() + offset));
-        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+        let flags =
+            PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE;
         unsafe {
             page_table
                 .map_to(page, frame, flags, frame_allocator)
@@ -57,12 +59,11 @@ pub fn map_range(
         count
     );
 
-    // default flags for stack
-    let mut flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+    // stacks and heaps are data, never code
+    let mut flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE;
 
     if user_access {
         flags |= PageTableFlags::USER_ACCESSIBLE;
-        // flags |= PageTableFlags::NO_EXECUTE; problem
     }
 
     for page in Page::range(range_start, range_end) {
@@ -136,6 +137,30 @@ pub fn load_elf(
         .collect()
 }
 
+/// Page table flags of a segment, derived from its permissions only
+///
+/// code is read-only, everything else is non-executable, and a segment
+/// asking for both write and execute is mapped non-executable (W^X)
+pub fn segment_flags(flags: program::Flags, user_access: bool) -> PageTableFlags {
+    let mut page_table_flags = PageTableFlags::PRESENT;
+
+    if flags.is_write() && flags.is_execute() {
+        warn!("Segment is writable and executable, mapping it non-executable.");
+    }
+
+    if flags.is_write() {
+        page_table_flags |= PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE;
+    } else if !flags.is_execute() {
+        page_table_flags |= PageTableFlags::NO_EXECUTE;
+    }
+
+    if user_access {
+        page_table_flags |= PageTableFlags::USER_ACCESSIBLE;
+    }
+
+    page_table_flags
+}
+
 /// Load & Map ELF segment
 ///
 /// load segment to new frame and set page table
@@ -154,28 +179,7 @@ fn load_segment(
     let file_offset = segment.offset() & !0xfff;
     let virt_start_addr = VirtAddr::new(segment.virtual_addr());
 
-    let mut page_table_flags = PageTableFlags::PRESENT;
-
-    // FIXME: handle page table flags with segment flags
-    // unimplemented!("Handle page table flags with segment flags!");
-
-    let flags = segment.flags();
-    if !flags.is_execute() {
-        page_table_flags |= PageTableFlags::NO_EXECUTE;
-    }
-    
-    if flags.is_read() {
-        page_table_flags |= PageTableFlags::USER_ACCESSIBLE;
-    }
-
-    if flags.is_write() {
-        page_table_flags |= PageTableFlags::WRITABLE;
-    }
-
-    if user_access {
-        page_table_flags |= PageTableFlags::USER_ACCESSIBLE;
-    }
-
+    let page_table_flags = segment_flags(segment.flags(), user_access);
     trace!("Segment page table flag: {:?}", page_table_flags);
 
     let start_page = Page::containing_address(virt_start_addr);
@@ -0,0 +1,206 @@
+//! Fixed ACPI Description Table
+//!
+//! Provides the PM1 control registers to enter the S5 (soft off) sleep state
+//! and the reset register. The sleep type values of S5 are defined by the
+//! `\_S5_` object in the DSDT, which is found by a simple AML scan.
+//!
+//! reference: https://wiki.osdev.org/Shutdown
+//! reference: https://uefi.org/specs/ACPI/6.5/05_ACPI_Software_Programming_Model.html#fixed-acpi-description-table-fadt
+
+use super::sdt::{GenericAddress, Sdt, GAS_SYSTEM_IO, GAS_SYSTEM_MEMORY};
+use crate::memory::physical_to_virtual;
+use x86_64::instructions::port::Port;
+
+/// Field offsets
+const DSDT: usize = 40;
+const SMI_CMD: usize = 48;
+const ACPI_ENABLE: usize = 52;
+const PM1A_CNT_BLK: usize = 64;
+const PM1B_CNT_BLK: usize = 68;
+const FLAGS: usize = 112;
+const RESET_REG: usize = 116;
+const RESET_VALUE: usize = 128;
+const X_DSDT: usize = 140;
+const X_PM1A_CNT_BLK: usize = 172;
+const X_PM1B_CNT_BLK: usize = 184;
+
+bitflags! {
+    #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+    struct FadtFlags: u32 {
+        const RESET_REG_SUP = 1 << 10;
+    }
+}
+
+/// PM1 control register bits
+const SCI_EN: u16 = 1 << 0;
+const SLP_TYP_SHIFT: u16 = 10;
+const SLP_TYP_MASK: u16 = 0b111 << SLP_TYP_SHIFT;
+const SLP_EN: u16 = 1 << 13;
+
+/// AML opcodes used to find `\_S5_`
+const AML_NAME_OP: u8 = 0x08;
+const AML_PACKAGE_OP: u8 = 0x12;
+const AML_BYTE_PREFIX: u8 = 0x0A;
+const AML_ROOT_CHAR: u8 = b'\\';
+
+/// How many times to poll SCI_EN after enabling ACPI mode
+const ACPI_ENABLE_TIMEOUT: usize = 100_000;
+
+#[derive(Debug, Clone, Copy)]
+pub struct FadtInfo {
+    pm1a_cnt: u16,
+    pm1b_cnt: u16,
+    smi_cmd: u16,
+    acpi_enable: u8,
+    /// SLP_TYPa and SLP_TYPb of the S5 state
+    s5: Option<(u16, u16)>,
+    /// The reset register and the value to write
+    reset: Option<(GenericAddress, u8)>,
+}
+
+/// Returns the I/O port of a PM1 control block, the extended field takes precedence.
+fn pm1_port(fadt: &Sdt, legacy: usize, extended: usize) -> u16 {
+    match fadt.read::<GenericAddress>(extended) {
+        Some(gas) if gas.address != 0 && gas.address_space == GAS_SYSTEM_IO => gas.address as u16,
+        _ => fadt.read::<u32>(legacy).unwrap_or_default() as u16,
+    }
+}
+
+pub fn parse(fadt: &Sdt) -> FadtInfo {
+    let dsdt = match fadt.read::<u64>(X_DSDT) {
+        Some(addr) if addr != 0 => addr,
+        _ => fadt.read::<u32>(DSDT).unwrap_or_default() as u64,
+    };
+
+    let s5 = if dsdt != 0 {
+        Sdt::new(dsdt).and_then(|dsdt| find_s5(dsdt.data()))
+    } else {
+        None
+    };
+
+    if s5.is_none() {
+        warn!("ACPI \\_S5_ object not found, shutdown disabled.");
+    }
+
+    let flags = FadtFlags::from_bits_truncate(fadt.read(FLAGS).unwrap_or_default());
+    let reset = if flags.contains(FadtFlags::RESET_REG_SUP) {
+        fadt.read::<GenericAddress>(RESET_REG)
+            .zip(fadt.read::<u8>(RESET_VALUE))
+            .filter(|(gas, _)| gas.address != 0)
+    } else {
+        None
+    };
+
+    FadtInfo {
+        pm1a_cnt: pm1_port(fadt, PM1A_CNT_BLK, X_PM1A_CNT_BLK),
+        pm1b_cnt: pm1_port(fadt, PM1B_CNT_BLK, X_PM1B_CNT_BLK),
+        smi_cmd: fadt.read::<u32>(SMI_CMD).unwrap_or_default() as u16,
+        acpi_enable: fadt.read(ACPI_ENABLE).unwrap_or_default(),
+        s5,
+        reset,
+    }
+}
+
+/// Finds the `\_S5_` package in the AML code and returns the first two elements.
+///
+/// The object is expected in the form of
+/// `NameOp [\] _S5_ PackageOp PkgLength NumElements (BytePrefix? value){2}`.
+fn find_s5(aml: &[u8]) -> Option<(u16, u16)> {
+    let pos = aml.windows(4).position(|window| window == b"_S5_")?;
+
+    let is_name = match pos {
+        1.. if aml[pos - 1] == AML_NAME_OP => true,
+        2.. => aml[pos - 2] == AML_NAME_OP && aml[pos - 1] == AML_ROOT_CHAR,
+        _ => false,
+    };
+
+    if !is_name {
+        return None;
+    }
+
+    let mut iter = aml[pos + 4..].iter().copied();
+    if iter.next()? != AML_PACKAGE_OP {
+        return None;
+    }
+
+    // bits 6-7 of the lead byte is the number of extra PkgLength bytes
+    let pkg_length = iter.next()?;
+    for _ in 0..(pkg_length >> 6) {
+        iter.next()?;
+    }
+
+    // NumElements
+    iter.next()?;
+
+    let mut element = || match iter.next()? {
+        AML_BYTE_PREFIX => iter.next(),
+        value => Some(value),
+    };
+
+    let slp_typ_a = element()? as u16;
+    let slp_typ_b = element()? as u16;
+
+    Some((slp_typ_a, slp_typ_b))
+}
+
+impl FadtInfo {
+    /// Switches from legacy mode to ACPI mode if the firmware did not.
+    fn enable_acpi(&self) {
+        let mut pm1a = Port::<u16>::new(self.pm1a_cnt);
+        if unsafe { pm1a.read() } & SCI_EN != 0 || self.smi_cmd == 0 || self.acpi_enable == 0 {
+            return;
+        }
+
+        unsafe { Port::<u8>::new(self.smi_cmd).write(self.acpi_enable) };
+
+        for _ in 0..ACPI_ENABLE_TIMEOUT {
+            if unsafe { pm1a.read() } & SCI_EN != 0 {
+                return;
+            }
+            core::hint::spin_loop();
+        }
+
+        warn!("Failed to enable ACPI mode.");
+    }
+
+    /// Enters the S5 sleep state, returns only if it failed.
+    pub fn shutdown(&self) {
+        let Some((slp_typ_a, slp_typ_b)) = self.s5 else {
+            return;
+        };
+
+        if self.pm1a_cnt == 0 {
+            return;
+        }
+
+        self.enable_acpi();
+
+        let write_sleep = |port: u16, slp_typ: u16| {
+            let mut port = Port::<u16>::new(port);
+            unsafe {
+                let value = port.read() & !(SLP_TYP_MASK | SLP_EN);
+                port.write(value | (slp_typ << SLP_TYP_SHIFT) | SLP_EN);
+            }
+        };
+
+        write_sleep(self.pm1a_cnt, slp_typ_a);
+        if self.pm1b_cnt != 0 {
+            write_sleep(self.pm1b_cnt, slp_typ_b);
+        }
+    }
+
+    /// Writes the reset register, returns only if it failed.
+    pub fn reset(&self) {
+        let Some((gas, value)) = self.reset else {
+            return;
+        };
+
+        match gas.address_space {
+            GAS_SYSTEM_IO => unsafe { Port::<u8>::new(gas.address as u16).write(value) },
+            GAS_SYSTEM_MEMORY => unsafe {
+                (physical_to_virtual(gas.address) as *mut u8).write_volatile(value)
+            },
+            space => warn!("Unsupported reset register address space: {}", space),
+        }
+    }
+}
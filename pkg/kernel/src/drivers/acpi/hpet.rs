@@ -0,0 +1,53 @@
+//! High Precision Event Timer description table
+//!
+//! reference: https://wiki.osdev.org/HPET
+
+use super::sdt::{GenericAddress, Sdt, GAS_SYSTEM_MEMORY};
+use crate::memory::physical_to_virtual;
+
+/// Field offsets
+const EVENT_TIMER_BLOCK_ID: usize = 36;
+const BASE_ADDRESS: usize = 40;
+const HPET_NUMBER: usize = 52;
+const MINIMUM_TICK: usize = 53;
+
+/// The upper 32 bits of the capabilities register is the counter period
+const CAPABILITIES_REG: u64 = 0x0;
+
+#[derive(Debug, Clone, Copy)]
+pub struct HpetInfo {
+    /// Physical address of the register block
+    pub base: u64,
+    pub number: u8,
+    /// Minimum clock ticks for the periodic mode without losing interrupts
+    pub minimum_tick: u16,
+    /// Number of comparators, from the event timer block id
+    pub comparators: u8,
+}
+
+pub fn parse(hpet: &Sdt) -> Option<HpetInfo> {
+    let base = hpet.read::<GenericAddress>(BASE_ADDRESS)?;
+    if base.address_space != GAS_SYSTEM_MEMORY || base.address == 0 {
+        warn!("Unsupported HPET address space: {}", { base.address_space });
+        return None;
+    }
+
+    let block_id = hpet.read::<u32>(EVENT_TIMER_BLOCK_ID)?;
+
+    Some(HpetInfo {
+        base: base.address,
+        number: hpet.read(HPET_NUMBER)?,
+        minimum_tick: hpet.read(MINIMUM_TICK)?,
+        comparators: ((block_id >> 8) & 0x1F) as u8 + 1,
+    })
+}
+
+impl HpetInfo {
+    /// The main counter period in femtoseconds
+    pub fn period_fs(&self) -> u32 {
+        let caps = unsafe {
+            (physical_to_virtual(self.base + CAPABILITIES_REG) as *const u64).read_volatile()
+        };
+        (caps >> 32) as u32
+    }
+}
@@ -0,0 +1,142 @@
+//! Multiple APIC Description Table
+//!
+//! Lists the local APIC of every CPU, the I/O APICs
+//! and how ISA IRQs are routed to the global system interrupts.
+
+use super::sdt::Sdt;
+use alloc::vec::Vec;
+
+/// The local APIC address field, followed by the flags
+const LAPIC_ADDR_OFFSET: usize = 36;
+const ENTRIES_OFFSET: usize = 44;
+
+/// Entry types
+const ENTRY_LAPIC: u8 = 0;
+const ENTRY_IOAPIC: u8 = 1;
+const ENTRY_ISO: u8 = 2;
+const ENTRY_LAPIC_ADDR_OVERRIDE: u8 = 5;
+const ENTRY_X2APIC: u8 = 9;
+
+bitflags! {
+    #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+    struct LapicFlags: u32 {
+        const ENABLED        = 1 << 0;
+        const ONLINE_CAPABLE = 1 << 1;
+    }
+}
+
+bitflags! {
+    /// MPS INTI flags of an interrupt source override
+    #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+    pub struct IsoFlags: u16 {
+        const ACTIVE_HIGH = 0b01;
+        const ACTIVE_LOW  = 0b11;
+        const EDGE        = 0b01 << 2;
+        const LEVEL       = 0b11 << 2;
+    }
+}
+
+const POLARITY_MASK: u16 = 0b11;
+const TRIGGER_MASK: u16 = 0b11 << 2;
+
+#[derive(Debug, Clone, Copy)]
+pub struct CpuInfo {
+    pub processor_id: u32,
+    pub apic_id: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct IoApicInfo {
+    pub id: u8,
+    pub addr: u64,
+    /// The first global system interrupt handled by this I/O APIC
+    pub gsi_base: u32,
+}
+
+/// An ISA IRQ routed to a different global system interrupt
+/// or with non-default polarity and trigger mode.
+#[derive(Debug, Clone, Copy)]
+pub struct InterruptOverride {
+    pub source: u8,
+    pub gsi: u32,
+    pub flags: IsoFlags,
+}
+
+impl InterruptOverride {
+    /// ISA interrupts default to active high
+    #[inline]
+    pub fn active_low(&self) -> bool {
+        self.flags.bits() & POLARITY_MASK == IsoFlags::ACTIVE_LOW.bits()
+    }
+
+    /// ISA interrupts default to edge triggered
+    #[inline]
+    pub fn level_triggered(&self) -> bool {
+        self.flags.bits() & TRIGGER_MASK == IsoFlags::LEVEL.bits()
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct MadtInfo {
+    pub lapic_addr: u64,
+    pub cpus: Vec<CpuInfo>,
+    pub ioapics: Vec<IoApicInfo>,
+    pub overrides: Vec<InterruptOverride>,
+}
+
+pub fn parse(madt: &Sdt) -> MadtInfo {
+    let mut info = MadtInfo {
+        lapic_addr: madt.read::<u32>(LAPIC_ADDR_OFFSET).unwrap_or_default() as u64,
+        ..Default::default()
+    };
+
+    let mut offset = ENTRIES_OFFSET;
+    while let (Some(kind), Some(len)) = (madt.read::<u8>(offset), madt.read::<u8>(offset + 1)) {
+        if len < 2 {
+            warn!("Invalid MADT entry at offset {}", offset);
+            break;
+        }
+
+        let field = |at: usize| offset + at;
+
+        match kind {
+            ENTRY_LAPIC => {
+                let flags = LapicFlags::from_bits_truncate(madt.read(field(4)).unwrap_or_default());
+                if flags.intersects(LapicFlags::ENABLED | LapicFlags::ONLINE_CAPABLE) {
+                    info.cpus.push(CpuInfo {
+                        processor_id: madt.read::<u8>(field(2)).unwrap_or_default() as u32,
+                        apic_id: madt.read::<u8>(field(3)).unwrap_or_default() as u32,
+                    });
+                }
+            }
+            ENTRY_X2APIC => {
+                let flags = LapicFlags::from_bits_truncate(madt.read(field(8)).unwrap_or_default());
+                if flags.intersects(LapicFlags::ENABLED | LapicFlags::ONLINE_CAPABLE) {
+                    info.cpus.push(CpuInfo {
+                        processor_id: madt.read(field(12)).unwrap_or_default(),
+                        apic_id: madt.read(field(4)).unwrap_or_default(),
+                    });
+                }
+            }
+            ENTRY_IOAPIC => info.ioapics.push(IoApicInfo {
+                id: madt.read(field(2)).unwrap_or_default(),
+                addr: madt.read::<u32>(field(4)).unwrap_or_default() as u64,
+                gsi_base: madt.read(field(8)).unwrap_or_default(),
+            }),
+            ENTRY_ISO => info.overrides.push(InterruptOverride {
+                source: madt.read(field(3)).unwrap_or_default(),
+                gsi: madt.read(field(4)).unwrap_or_default(),
+                flags: IsoFlags::from_bits_retain(madt.read(field(8)).unwrap_or_default()),
+            }),
+            ENTRY_LAPIC_ADDR_OVERRIDE => {
+                info.lapic_addr = madt.read(field(4)).unwrap_or(info.lapic_addr);
+            }
+            // NMI sources and local APIC NMIs are not used
+            _ => {}
+        }
+
+        offset += len as usize;
+    }
+
+    info
+}
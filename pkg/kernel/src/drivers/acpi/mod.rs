@@ -0,0 +1,173 @@
+//! ACPI tables
+//!
+//! The bootloader passes the RSDP found in the UEFI config table,
+//! the tables are parsed once at boot for the APIC topology, power
+//! management registers and the HPET. Without ACPI the legacy defaults
+//! of a single CPU with the APICs at the standard addresses are used.
+//!
+//! reference: https://wiki.osdev.org/RSDP
+//! reference: https://wiki.osdev.org/MADT
+
+mod fadt;
+mod hpet;
+mod madt;
+mod sdt;
+
+pub use fadt::FadtInfo;
+pub use hpet::HpetInfo;
+pub use madt::{CpuInfo, InterruptOverride, IoApicInfo, MadtInfo};
+
+use alloc::vec;
+use boot::BootInfo;
+use spin::Once;
+use x86::cpuid::CpuId;
+
+/// Default physical address of xAPIC
+const DEFAULT_LAPIC_ADDR: u64 = 0xFEE00000;
+/// Default physical address of IO APIC
+const DEFAULT_IOAPIC_ADDR: u64 = 0xFEC00000;
+
+#[derive(Debug)]
+pub struct AcpiInfo {
+    pub madt: MadtInfo,
+    pub fadt: Option<FadtInfo>,
+    pub hpet: Option<HpetInfo>,
+}
+
+/// Routing of an ISA IRQ on the I/O APIC
+#[derive(Debug, Clone, Copy)]
+pub struct IrqRoute {
+    pub gsi: u32,
+    pub active_low: bool,
+    pub level_triggered: bool,
+}
+
+static ACPI: Once<AcpiInfo> = Once::new();
+
+fn legacy_madt() -> MadtInfo {
+    let apic_id = CpuId::new()
+        .get_feature_info()
+        .map(|info| info.initial_local_apic_id() as u32)
+        .unwrap_or_default();
+
+    MadtInfo {
+        lapic_addr: DEFAULT_LAPIC_ADDR,
+        cpus: vec![CpuInfo {
+            processor_id: 0,
+            apic_id,
+        }],
+        ioapics: vec![IoApicInfo {
+            id: 0,
+            addr: DEFAULT_IOAPIC_ADDR,
+            gsi_base: 0,
+        }],
+        overrides: vec![],
+    }
+}
+
+fn parse(rsdp_addr: u64) -> Option<AcpiInfo> {
+    let tables = sdt::parse_root(rsdp_addr)?;
+    let find = |signature: &[u8; 4]| tables.iter().find(|table| table.signature() == signature);
+
+    let mut madt = find(b"APIC").map(madt::parse).unwrap_or_else(|| {
+        warn!("ACPI MADT not found, assume a single CPU.");
+        legacy_madt()
+    });
+
+    if madt.cpus.is_empty() {
+        madt.cpus = legacy_madt().cpus;
+    }
+    if madt.ioapics.is_empty() {
+        madt.ioapics = legacy_madt().ioapics;
+    }
+
+    Some(AcpiInfo {
+        madt,
+        fadt: find(b"FACP").map(fadt::parse),
+        hpet: find(b"HPET").and_then(hpet::parse),
+    })
+}
+
+pub fn init(boot_info: &'static BootInfo) {
+    let info = boot_info.rsdp_addr.and_then(parse).unwrap_or_else(|| {
+        warn!("ACPI not available, fallback to legacy configuration.");
+        AcpiInfo {
+            madt: legacy_madt(),
+            fadt: None,
+            hpet: None,
+        }
+    });
+
+    trace!("ACPI: {:#x?}", info);
+
+    info!(
+        "ACPI Initialized: {} CPU(s), {} IOAPIC(s), LAPIC at {:#x}",
+        info.madt.cpus.len(),
+        info.madt.ioapics.len(),
+        info.madt.lapic_addr
+    );
+
+    if let Some(hpet) = &info.hpet {
+        info!("HPET at {:#x}, period {} fs", hpet.base, hpet.period_fs());
+    }
+
+    ACPI.call_once(|| info);
+}
+
+#[inline]
+pub fn info() -> &'static AcpiInfo {
+    ACPI.get().expect("ACPI has not been initialized")
+}
+
+/// Physical address of the local APIC
+#[inline]
+pub fn lapic_addr() -> u64 {
+    info().madt.lapic_addr
+}
+
+#[inline]
+pub fn ioapics() -> &'static [IoApicInfo] {
+    &info().madt.ioapics
+}
+
+/// The usable CPUs, the first one is not necessarily the BSP
+#[inline]
+pub fn cpus() -> &'static [CpuInfo] {
+    &info().madt.cpus
+}
+
+/// The largest APIC id, used to size the per-CPU data
+pub fn max_apic_id() -> u32 {
+    cpus().iter().map(|cpu| cpu.apic_id).max().unwrap_or_default()
+}
+
+/// Routes an ISA IRQ through the interrupt source overrides,
+/// identity mapped and edge triggered active high by default.
+pub fn isa_irq(irq: u8) -> IrqRoute {
+    match info().madt.overrides.iter().find(|iso| iso.source == irq) {
+        Some(iso) => IrqRoute {
+            gsi: iso.gsi,
+            active_low: iso.active_low(),
+            level_triggered: iso.level_triggered(),
+        },
+        None => IrqRoute {
+            gsi: irq as u32,
+            active_low: false,
+            level_triggered: false,
+        },
+    }
+}
+
+/// Enters S5 through the FADT, returns only if it is not supported.
+pub fn shutdown() {
+    if let Some(fadt) = ACPI.get().and_then(|info| info.fadt.as_ref()) {
+        fadt.shutdown();
+    }
+}
+
+/// Writes the FADT reset register, returns only if it is not supported.
+pub fn reset() {
+    if let Some(fadt) = ACPI.get().and_then(|info| info.fadt.as_ref()) {
+        fadt.reset();
+    }
+}
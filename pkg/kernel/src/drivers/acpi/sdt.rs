@@ -0,0 +1,153 @@
+//! Root System Description Pointer and System Description Table headers
+
+use crate::memory::physical_to_virtual;
+use alloc::vec::Vec;
+use core::mem::size_of;
+
+/// Root System Description Pointer, the `length` and later fields
+/// are only valid since ACPI 2.0 (revision 2).
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy)]
+pub struct Rsdp {
+    pub signature: [u8; 8],
+    pub checksum: u8,
+    pub oem_id: [u8; 6],
+    pub revision: u8,
+    pub rsdt_address: u32,
+    pub length: u32,
+    pub xsdt_address: u64,
+    pub extended_checksum: u8,
+    pub reserved: [u8; 3],
+}
+
+/// Size of the RSDP in ACPI 1.0
+const RSDP_V1_SIZE: usize = 20;
+
+/// Common header of all System Description Tables
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy)]
+pub struct SdtHeader {
+    pub signature: [u8; 4],
+    pub length: u32,
+    pub revision: u8,
+    pub checksum: u8,
+    pub oem_id: [u8; 6],
+    pub oem_table_id: [u8; 8],
+    pub oem_revision: u32,
+    pub creator_id: u32,
+    pub creator_revision: u32,
+}
+
+/// Address space ids of the Generic Address Structure
+pub const GAS_SYSTEM_MEMORY: u8 = 0;
+pub const GAS_SYSTEM_IO: u8 = 1;
+
+/// Generic Address Structure, describes registers in FADT and HPET
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy)]
+pub struct GenericAddress {
+    pub address_space: u8,
+    pub bit_width: u8,
+    pub bit_offset: u8,
+    pub access_size: u8,
+    pub address: u64,
+}
+
+/// The bytes of a structure must sum to zero.
+fn checksum(addr: u64, len: usize) -> bool {
+    let bytes = unsafe { core::slice::from_raw_parts(physical_to_virtual(addr) as *const u8, len) };
+    bytes.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte)) == 0
+}
+
+/// Reads a structure from physical memory.
+///
+/// # Safety
+///
+/// `addr + size_of::<T>()` must be valid physical memory.
+#[inline]
+pub unsafe fn read_phys<T: Copy>(addr: u64) -> T {
+    unsafe { core::ptr::read_unaligned(physical_to_virtual(addr) as *const T) }
+}
+
+/// A validated System Description Table in physical memory
+#[derive(Debug, Clone, Copy)]
+pub struct Sdt {
+    pub addr: u64,
+    pub header: SdtHeader,
+}
+
+impl Sdt {
+    /// Reads the header at `addr` and verifies the checksum.
+    pub fn new(addr: u64) -> Option<Self> {
+        let header: SdtHeader = unsafe { read_phys(addr) };
+        let length = header.length as usize;
+
+        if length < size_of::<SdtHeader>() || !checksum(addr, length) {
+            warn!(
+                "ACPI table {} at {:#x} is invalid",
+                core::str::from_utf8(&header.signature).unwrap_or("????"),
+                addr
+            );
+            return None;
+        }
+
+        Some(Self { addr, header })
+    }
+
+    #[inline]
+    pub fn signature(&self) -> &[u8; 4] {
+        &self.header.signature
+    }
+
+    #[inline]
+    pub fn length(&self) -> usize {
+        self.header.length as usize
+    }
+
+    /// Reads a value at `offset` from the start of the table,
+    /// returns `None` if it does not fit in the table.
+    pub fn read<T: Copy>(&self, offset: usize) -> Option<T> {
+        (offset + size_of::<T>() <= self.length()).then(|| unsafe { read_phys(self.addr + offset as u64) })
+    }
+
+    /// The bytes after the header
+    pub fn data(&self) -> &'static [u8] {
+        let start = physical_to_virtual(self.addr) as usize + size_of::<SdtHeader>();
+        unsafe { core::slice::from_raw_parts(start as *const u8, self.length() - size_of::<SdtHeader>()) }
+    }
+}
+
+/// Finds all tables listed in the RSDT or XSDT.
+pub fn parse_root(rsdp_addr: u64) -> Option<Vec<Sdt>> {
+    let rsdp: Rsdp = unsafe { read_phys(rsdp_addr) };
+
+    if &rsdp.signature != b"RSD PTR " || !checksum(rsdp_addr, RSDP_V1_SIZE) {
+        warn!("Invalid ACPI RSDP at {:#x}", rsdp_addr);
+        return None;
+    }
+
+    // the XSDT holds 64-bit pointers and takes precedence since ACPI 2.0
+    let (root, entry_size) = if rsdp.revision >= 2
+        && rsdp.xsdt_address != 0
+        && checksum(rsdp_addr, rsdp.length as usize)
+    {
+        (Sdt::new(rsdp.xsdt_address)?, size_of::<u64>())
+    } else {
+        (Sdt::new(rsdp.rsdt_address as u64)?, size_of::<u32>())
+    };
+
+    let count = (root.length() - size_of::<SdtHeader>()) / entry_size;
+    let tables = (0..count)
+        .filter_map(|i| {
+            let offset = size_of::<SdtHeader>() + i * entry_size;
+            let addr = if entry_size == size_of::<u64>() {
+                root.read::<u64>(offset)?
+            } else {
+                root.read::<u32>(offset)? as u64
+            };
+            Sdt::new(addr)
+        })
+        .collect();
+
+    Some(tables)
+}
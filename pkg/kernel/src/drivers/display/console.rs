@@ -0,0 +1,298 @@
+//! Text console on the framebuffer
+//!
+//! Supports a subset of ANSI escape sequences, enough for the logger and the shell:
+//!
+//! - `ESC[<n>m`: SGR, reset / bold / 8 and 16 foreground and background colors
+//! - `ESC[<row>;<col>H`: cursor position
+//! - `ESC[<n>A/B/C/D`: cursor movement
+//! - `ESC[<n>J`: erase in display
+//! - `ESC[<n>K`: erase in line
+//!
+//! reference: https://en.wikipedia.org/wiki/ANSI_escape_code
+
+use super::font::*;
+use super::framebuffer::{FrameBuffer, Rgb};
+use core::fmt;
+
+/// VGA-like palette, the second half is the bright variant
+const PALETTE: [Rgb; 16] = [
+    0x000000, 0xAA0000, 0x00AA00, 0xAA5500, 0x0000AA, 0xAA00AA, 0x00AAAA, 0xAAAAAA,
+    0x555555, 0xFF5555, 0x55FF55, 0xFFFF55, 0x5555FF, 0xFF55FF, 0x55FFFF, 0xFFFFFF,
+];
+
+const DEFAULT_FG: usize = 7;
+const DEFAULT_BG: usize = 0;
+
+/// Maximum number of parameters in a control sequence
+const MAX_PARAMS: usize = 8;
+const TAB_WIDTH: usize = 8;
+
+/// The cursor is an underline on the last two rows of the cell
+const CURSOR_HEIGHT: usize = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Normal,
+    Escape,
+    Csi,
+}
+
+pub struct Console {
+    fb: FrameBuffer,
+    cols: usize,
+    rows: usize,
+    col: usize,
+    row: usize,
+    fg: usize,
+    bg: usize,
+    bold: bool,
+    state: State,
+    params: [u16; MAX_PARAMS],
+    param_count: usize,
+}
+
+impl Console {
+    pub fn new(fb: FrameBuffer) -> Self {
+        let cols = fb.width() / FONT_WIDTH;
+        let rows = fb.height() / FONT_HEIGHT;
+
+        let mut console = Self {
+            fb,
+            cols,
+            rows,
+            col: 0,
+            row: 0,
+            fg: DEFAULT_FG,
+            bg: DEFAULT_BG,
+            bold: false,
+            state: State::Normal,
+            params: [0; MAX_PARAMS],
+            param_count: 0,
+        };
+
+        console.clear();
+        console
+    }
+
+    #[inline]
+    pub fn size(&self) -> (usize, usize) {
+        (self.cols, self.rows)
+    }
+
+    #[inline]
+    fn fg_color(&self) -> Rgb {
+        // bold makes the basic colors bright
+        if self.bold && self.fg < 8 {
+            PALETTE[self.fg + 8]
+        } else {
+            PALETTE[self.fg]
+        }
+    }
+
+    #[inline]
+    fn bg_color(&self) -> Rgb {
+        PALETTE[self.bg]
+    }
+
+    fn clear(&mut self) {
+        let bg = self.bg_color();
+        self.fb.clear(bg);
+        self.col = 0;
+        self.row = 0;
+    }
+
+    fn toggle_cursor(&mut self) {
+        let x = self.col.min(self.cols - 1) * FONT_WIDTH;
+        let y = (self.row + 1) * FONT_HEIGHT - CURSOR_HEIGHT;
+
+        for dy in 0..CURSOR_HEIGHT {
+            for dx in 0..FONT_WIDTH {
+                self.fb.xor_pixel(x + dx, y + dy, 0xFFFFFF);
+            }
+        }
+    }
+
+    fn draw_char(&mut self, col: usize, row: usize, ch: u8) {
+        let glyph = if (FIRST_CHAR..=LAST_CHAR).contains(&ch) {
+            &FONT[(ch - FIRST_CHAR) as usize]
+        } else {
+            // unknown characters are drawn as '?'
+            &FONT[(b'?' - FIRST_CHAR) as usize]
+        };
+
+        let (fg, bg) = (self.fg_color(), self.bg_color());
+        let (x, y) = (col * FONT_WIDTH, row * FONT_HEIGHT);
+
+        for (dy, bits) in glyph.iter().enumerate() {
+            for dx in 0..FONT_WIDTH {
+                let color = if bits & (0x80 >> dx) != 0 { fg } else { bg };
+                self.fb.put_pixel(x + dx, y + dy, color);
+            }
+        }
+    }
+
+    /// Fills cells `[from, to)` of the row with the background color.
+    fn erase_cells(&mut self, row: usize, from: usize, to: usize) {
+        if from >= to {
+            return;
+        }
+
+        let bg = self.bg_color();
+        self.fb.fill_rect(
+            from * FONT_WIDTH,
+            row * FONT_HEIGHT,
+            (to - from) * FONT_WIDTH,
+            FONT_HEIGHT,
+            bg,
+        );
+    }
+
+    fn new_line(&mut self) {
+        if self.row + 1 < self.rows {
+            self.row += 1;
+        } else {
+            let bg = self.bg_color();
+            self.fb.scroll_up(FONT_HEIGHT, bg);
+        }
+    }
+
+    fn put_char(&mut self, ch: u8) {
+        // wrap lazily so that writing the last column does not scroll
+        if self.col >= self.cols {
+            self.col = 0;
+            self.new_line();
+        }
+
+        self.draw_char(self.col, self.row, ch);
+        self.col += 1;
+    }
+
+    /// Returns the `index`-th parameter, missing or zero parameters take the default.
+    fn param(&self, index: usize, default: u16) -> usize {
+        match self.params.get(index) {
+            Some(&value) if index < self.param_count && value != 0 => value as usize,
+            _ => default as usize,
+        }
+    }
+
+    /// Select Graphic Rendition
+    fn sgr(&mut self) {
+        for i in 0..self.param_count.max(1) {
+            match self.params[i] {
+                0 => {
+                    self.fg = DEFAULT_FG;
+                    self.bg = DEFAULT_BG;
+                    self.bold = false;
+                }
+                1 => self.bold = true,
+                22 => self.bold = false,
+                code @ 30..=37 => self.fg = (code - 30) as usize,
+                39 => self.fg = DEFAULT_FG,
+                code @ 40..=47 => self.bg = (code - 40) as usize,
+                49 => self.bg = DEFAULT_BG,
+                code @ 90..=97 => self.fg = (code - 90) as usize + 8,
+                code @ 100..=107 => self.bg = (code - 100) as usize + 8,
+                _ => {}
+            }
+        }
+    }
+
+    fn execute_csi(&mut self, action: u8) {
+        match action {
+            b'm' => self.sgr(),
+            b'H' | b'f' => {
+                self.row = (self.param(0, 1) - 1).min(self.rows - 1);
+                self.col = (self.param(1, 1) - 1).min(self.cols - 1);
+            }
+            b'A' => self.row = self.row.saturating_sub(self.param(0, 1)),
+            b'B' => self.row = (self.row + self.param(0, 1)).min(self.rows - 1),
+            b'C' => self.col = (self.col + self.param(0, 1)).min(self.cols - 1),
+            b'D' => self.col = self.col.saturating_sub(self.param(0, 1)),
+            b'J' => match self.param(0, 0) {
+                0 => {
+                    self.erase_cells(self.row, self.col, self.cols);
+                    for row in self.row + 1..self.rows {
+                        self.erase_cells(row, 0, self.cols);
+                    }
+                }
+                1 => {
+                    for row in 0..self.row {
+                        self.erase_cells(row, 0, self.cols);
+                    }
+                    self.erase_cells(self.row, 0, self.col + 1);
+                }
+                _ => {
+                    let bg = self.bg_color();
+                    self.fb.clear(bg);
+                }
+            },
+            b'K' => match self.param(0, 0) {
+                0 => self.erase_cells(self.row, self.col, self.cols),
+                1 => self.erase_cells(self.row, 0, self.col + 1),
+                _ => self.erase_cells(self.row, 0, self.cols),
+            },
+            // unsupported sequences are ignored
+            _ => {}
+        }
+    }
+
+    fn write_byte(&mut self, byte: u8) {
+        match self.state {
+            State::Normal => match byte {
+                0x1B => self.state = State::Escape,
+                b'\n' => self.new_line(),
+                b'\r' => self.col = 0,
+                0x08 => self.col = self.col.min(self.cols).saturating_sub(1),
+                b'\t' => {
+                    let next = (self.col / TAB_WIDTH + 1) * TAB_WIDTH;
+                    while self.col < next.min(self.cols) {
+                        self.put_char(b' ');
+                    }
+                }
+                0x07 => {} // bell
+                // utf-8 continuation bytes, the leading byte is drawn as '?'
+                0x80..=0xBF => {}
+                _ => self.put_char(byte),
+            },
+            State::Escape => match byte {
+                b'[' => {
+                    self.params = [0; MAX_PARAMS];
+                    self.param_count = 0;
+                    self.state = State::Csi;
+                }
+                _ => self.state = State::Normal,
+            },
+            State::Csi => match byte {
+                b'0'..=b'9' => {
+                    if self.param_count == 0 {
+                        self.param_count = 1;
+                    }
+                    if let Some(param) = self.params.get_mut(self.param_count - 1) {
+                        *param = param.saturating_mul(10).saturating_add((byte - b'0') as u16);
+                    }
+                }
+                b';' => {
+                    self.param_count = (self.param_count.max(1) + 1).min(MAX_PARAMS);
+                }
+                // private mode prefix and intermediate bytes are ignored
+                b'?' | b' '..=b'/' => {}
+                0x40..=0x7E => {
+                    self.execute_csi(byte);
+                    self.state = State::Normal;
+                }
+                _ => self.state = State::Normal,
+            },
+        }
+    }
+}
+
+impl fmt::Write for Console {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.toggle_cursor();
+        for byte in s.bytes() {
+            self.write_byte(byte);
+        }
+        self.toggle_cursor();
+        Ok(())
+    }
+}
@@ -0,0 +1,102 @@
+use boot::{FrameBufferInfo, PixelFormat};
+
+/// A color in 0xRRGGBB
+pub type Rgb = u32;
+
+/// A linear 32-bit framebuffer, accessed through the physical offset mapping.
+pub struct FrameBuffer {
+    base: u64,
+    width: usize,
+    height: usize,
+    stride: usize,
+    format: PixelFormat,
+}
+
+impl FrameBuffer {
+    pub fn new(info: &FrameBufferInfo) -> Self {
+        Self {
+            base: crate::memory::physical_to_virtual(info.base),
+            width: info.width,
+            height: info.height,
+            stride: info.stride,
+            format: info.format,
+        }
+    }
+
+    #[inline]
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    #[inline]
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    #[inline]
+    fn pixel_ptr(&self, x: usize, y: usize) -> *mut u32 {
+        (self.base as *mut u32).wrapping_add(y * self.stride + x)
+    }
+
+    /// Converts 0xRRGGBB into the pixel layout of the framebuffer.
+    #[inline]
+    fn encode(&self, color: Rgb) -> u32 {
+        match self.format {
+            PixelFormat::Rgb => {
+                let (r, g, b) = (color >> 16 & 0xFF, color >> 8 & 0xFF, color & 0xFF);
+                b << 16 | g << 8 | r
+            }
+            // Bgr is the same as 0xRRGGBB in little endian
+            _ => color & 0xFF_FFFF,
+        }
+    }
+
+    #[inline]
+    pub fn put_pixel(&mut self, x: usize, y: usize, color: Rgb) {
+        if x < self.width && y < self.height {
+            let pixel = self.encode(color);
+            unsafe { self.pixel_ptr(x, y).write_volatile(pixel) };
+        }
+    }
+
+    /// XOR the pixel with the given color, applying it twice restores the pixel.
+    #[inline]
+    pub fn xor_pixel(&mut self, x: usize, y: usize, color: Rgb) {
+        if x < self.width && y < self.height {
+            let mask = self.encode(color);
+            let ptr = self.pixel_ptr(x, y);
+            unsafe { ptr.write_volatile(ptr.read_volatile() ^ mask) };
+        }
+    }
+
+    pub fn fill_rect(&mut self, x: usize, y: usize, width: usize, height: usize, color: Rgb) {
+        let pixel = self.encode(color);
+        let x_end = (x + width).min(self.width);
+        let y_end = (y + height).min(self.height);
+
+        for y in y..y_end {
+            for x in x..x_end {
+                unsafe { self.pixel_ptr(x, y).write_volatile(pixel) };
+            }
+        }
+    }
+
+    pub fn clear(&mut self, color: Rgb) {
+        self.fill_rect(0, 0, self.width, self.height, color);
+    }
+
+    /// Moves the content up by `lines` pixel rows and fills the bottom with `color`.
+    pub fn scroll_up(&mut self, lines: usize, color: Rgb) {
+        if lines >= self.height {
+            self.clear(color);
+            return;
+        }
+
+        let count = (self.height - lines) * self.stride;
+        unsafe {
+            core::ptr::copy(self.pixel_ptr(0, lines), self.pixel_ptr(0, 0), count);
+        }
+
+        self.fill_rect(0, self.height - lines, self.width, lines, color);
+    }
+}
@@ -0,0 +1,30 @@
+//! Framebuffer console
+//!
+//! Draws text with a built-in bitmap font on the GOP framebuffer passed by
+//! the bootloader, all output to the serial port is mirrored here.
+
+mod console;
+mod font;
+mod framebuffer;
+
+pub use console::Console;
+pub use framebuffer::{FrameBuffer, Rgb};
+
+once_mutex!(pub CONSOLE: Console);
+
+guard_access_fn!(pub get_console(CONSOLE: Console));
+
+pub fn init(boot_info: &'static boot::BootInfo) {
+    let Some(info) = boot_info.framebuffer.as_ref() else {
+        warn!("No framebuffer available, console disabled.");
+        return;
+    };
+
+    init_CONSOLE(Console::new(FrameBuffer::new(info)));
+
+    let (cols, rows) = get_console_for_sure().size();
+    info!(
+        "Framebuffer Console Initialized: {}x{} pixels, {}x{} characters.",
+        info.width, info.height, cols, rows
+    );
+}
@@ -1,6 +1,8 @@
 use super::ata::*;
+use super::virtio::*;
 use alloc::boxed::Box;
 use alloc::format;
+use crate::memory::swap::{self, SWAP_PARTITION_TYPE};
 use chrono::DateTime;
 use storage::fat16::Fat16;
 use storage::mbr::*;
@@ -15,22 +17,57 @@ pub fn get_rootfs() -> &'static Mount {
 pub fn init() {
     info!("Opening disk device...");
 
-    let drive = AtaDrive::open(0, 0).expect("Failed to open disk device");
+    // prefer the VirtIO block device, fallback to the IDE disk
+    if let Some(drive) = VirtioBlk::open() {
+        mount(drive);
+    } else {
+        mount(AtaDrive::open(0, 0).expect("Failed to open disk device"));
+    }
 
-    // only get the first partition
+    // the swap partition is on the second IDE disk, if there is one
+    if let Some(drive) = AtaDrive::open(0, 1) {
+        open_swap(drive);
+    }
+
+    info!("Initialized Filesystem.");
+}
+
+fn open_swap<T>(drive: T)
+where
+    T: BlockDevice<Block512> + Clone,
+{
     let part = MbrTable::parse(drive)
+        .ok()
+        .and_then(|table| table.find_partition(SWAP_PARTITION_TYPE));
+
+    match part {
+        Some(part) => {
+            trace!("Swap partition: {:#?}", part);
+            swap::init(Box::new(part));
+        }
+        None => warn!("No swap partition found."),
+    }
+}
+
+fn mount<T>(drive: T)
+where
+    T: BlockDevice<Block512> + Clone,
+{
+    let mut partitions = MbrTable::parse(drive)
         .expect("Failed to parse MBR")
         .partitions()
-        .expect("Failed to get partitions")
-        .remove(0);
+        .expect("Failed to get partitions");
+
+    trace!("MBR Partitions: {:#?}", partitions);
+
+    // only get the first partition
+    let part = partitions.remove(0);
 
     info!("Mounting filesystem...");
 
     ROOTFS.call_once(|| Mount::new(Box::new(Fat16::new(part)), "/".into()));
 
     trace!("Root filesystem: {:#?}", ROOTFS.get().unwrap());
-
-    info!("Initialized Filesystem.");
 }
 
 pub fn ls(root_path: &str) {
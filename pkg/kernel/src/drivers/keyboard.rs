@@ -0,0 +1,353 @@
+//! PS/2 Keyboard over the i8042 controller
+//!
+//! The controller translates the keyboard's scancode set 2 into set 1,
+//! which is decoded here and pushed into the input buffer as bytes,
+//! special keys are encoded as VT100 escape sequences like a serial terminal.
+//!
+//! reference: https://wiki.osdev.org/I8042_PS/2_Controller
+//! reference: https://wiki.osdev.org/PS/2_Keyboard
+//! reference: https://www.win.tue.nl/~aeb/linux/kbd/scancodes-1.html
+
+use super::input::push_key;
+use spin::Mutex;
+use x86_64::instructions::port::Port;
+
+const DATA_PORT: u16 = 0x60;
+const STATUS_PORT: u16 = 0x64;
+const COMMAND_PORT: u16 = 0x64;
+
+/// How many times to poll the status register before giving up
+const TIMEOUT: usize = 100_000;
+
+bitflags! {
+    #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+    struct ControllerStatus: u8 {
+        const OUTPUT_FULL = 1 << 0;
+        const INPUT_FULL  = 1 << 1;
+    }
+}
+
+bitflags! {
+    /// Controller configuration byte
+    #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+    struct ControllerConfig: u8 {
+        const PORT1_INTERRUPT   = 1 << 0;
+        const PORT2_INTERRUPT   = 1 << 1;
+        const PORT1_CLOCK_OFF   = 1 << 4;
+        const PORT2_CLOCK_OFF   = 1 << 5;
+        const PORT1_TRANSLATION = 1 << 6;
+    }
+}
+
+bitflags! {
+    #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+    struct Modifiers: u8 {
+        const LSHIFT    = 1 << 0;
+        const RSHIFT    = 1 << 1;
+        const LCTRL     = 1 << 2;
+        const RCTRL     = 1 << 3;
+        const CAPS_LOCK = 1 << 4;
+    }
+}
+
+#[repr(u8)]
+enum ControllerCommand {
+    ReadConfig = 0x20,
+    WriteConfig = 0x60,
+    DisablePort2 = 0xA7,
+    SelfTest = 0xAA,
+    DisablePort1 = 0xAD,
+    EnablePort1 = 0xAE,
+}
+
+/// Commands sent to the keyboard itself
+const KEYBOARD_SET_LEDS: u8 = 0xED;
+const KEYBOARD_ACK: u8 = 0xFA;
+const KEYBOARD_RESEND: u8 = 0xFE;
+const SELF_TEST_PASSED: u8 = 0x55;
+
+const EXTENDED_PREFIX: u8 = 0xE0;
+const RELEASE_BIT: u8 = 0x80;
+
+/// Scancode set 1 make codes
+const SC_LCTRL: u8 = 0x1D;
+const SC_LSHIFT: u8 = 0x2A;
+const SC_RSHIFT: u8 = 0x36;
+const SC_CAPS_LOCK: u8 = 0x3A;
+
+/// Scancode set 1 to ASCII, indexed by make code, 0 means no character.
+#[rustfmt::skip]
+const KEYMAP: [u8; 0x3A] = [
+    0,    0x1B, b'1', b'2', b'3', b'4', b'5', b'6', // 0x00
+    b'7', b'8', b'9', b'0', b'-', b'=', 0x7F, b'\t', // 0x08
+    b'q', b'w', b'e', b'r', b't', b'y', b'u', b'i', // 0x10
+    b'o', b'p', b'[', b']', b'\r', 0,   b'a', b's', // 0x18
+    b'd', b'f', b'g', b'h', b'j', b'k', b'l', b';', // 0x20
+    b'\'', b'`', 0,   b'\\', b'z', b'x', b'c', b'v', // 0x28
+    b'b', b'n', b'm', b',', b'.', b'/', 0,    b'*', // 0x30
+    0,    b' ', // 0x38
+];
+
+/// Same as `KEYMAP` with shift held.
+#[rustfmt::skip]
+const KEYMAP_SHIFT: [u8; 0x3A] = [
+    0,    0x1B, b'!', b'@', b'#', b'$', b'%', b'^', // 0x00
+    b'&', b'*', b'(', b')', b'_', b'+', 0x7F, b'\t', // 0x08
+    b'Q', b'W', b'E', b'R', b'T', b'Y', b'U', b'I', // 0x10
+    b'O', b'P', b'{', b'}', b'\r', 0,   b'A', b'S', // 0x18
+    b'D', b'F', b'G', b'H', b'J', b'K', b'L', b':', // 0x20
+    b'"', b'~', 0,    b'|', b'Z', b'X', b'C', b'V', // 0x28
+    b'B', b'N', b'M', b'<', b'>', b'?', 0,    b'*', // 0x30
+    0,    b' ', // 0x38
+];
+
+/// Extended (0xE0 prefixed) keys that produce escape sequences.
+fn extended_sequence(code: u8) -> Option<&'static [u8]> {
+    Some(match code {
+        0x48 => b"\x1b[A", // up
+        0x50 => b"\x1b[B", // down
+        0x4D => b"\x1b[C", // right
+        0x4B => b"\x1b[D", // left
+        0x47 => b"\x1b[H", // home
+        0x4F => b"\x1b[F", // end
+        0x49 => b"\x1b[5~", // page up
+        0x51 => b"\x1b[6~", // page down
+        0x52 => b"\x1b[2~", // insert
+        0x53 => b"\x1b[3~", // delete
+        0x1C => b"\r",     // keypad enter
+        0x35 => b"/",      // keypad slash
+        _ => return None,
+    })
+}
+
+struct Keyboard {
+    modifiers: Modifiers,
+    extended: bool,
+    /// LEDs to send once the keyboard acknowledges `KEYBOARD_SET_LEDS`
+    pending_leds: Option<u8>,
+}
+
+static KEYBOARD: Mutex<Keyboard> = Mutex::new(Keyboard {
+    modifiers: Modifiers::empty(),
+    extended: false,
+    pending_leds: None,
+});
+
+impl Keyboard {
+    #[inline]
+    fn shift(&self) -> bool {
+        self.modifiers.intersects(Modifiers::LSHIFT | Modifiers::RSHIFT)
+    }
+
+    #[inline]
+    fn ctrl(&self) -> bool {
+        self.modifiers.intersects(Modifiers::LCTRL | Modifiers::RCTRL)
+    }
+
+    /// Decodes a scancode and pushes the resulting bytes into the input buffer.
+    fn process(&mut self, scancode: u8) {
+        if scancode == EXTENDED_PREFIX {
+            self.extended = true;
+            return;
+        }
+
+        let extended = core::mem::take(&mut self.extended);
+        let released = scancode & RELEASE_BIT != 0;
+        let code = scancode & !RELEASE_BIT;
+
+        let modifier = match (extended, code) {
+            (false, SC_LSHIFT) => Some(Modifiers::LSHIFT),
+            (false, SC_RSHIFT) => Some(Modifiers::RSHIFT),
+            (false, SC_LCTRL) => Some(Modifiers::LCTRL),
+            (true, SC_LCTRL) => Some(Modifiers::RCTRL),
+            _ => None,
+        };
+
+        if let Some(modifier) = modifier {
+            self.modifiers.set(modifier, !released);
+            return;
+        }
+
+        if released {
+            return;
+        }
+
+        if extended {
+            if let Some(seq) = extended_sequence(code) {
+                seq.iter().for_each(|&key| push_key(key));
+            }
+            return;
+        }
+
+        if code == SC_CAPS_LOCK {
+            self.modifiers.toggle(Modifiers::CAPS_LOCK);
+            self.set_leds();
+            return;
+        }
+
+        let Some(&plain) = KEYMAP.get(code as usize) else {
+            return;
+        };
+
+        if plain == 0 {
+            return;
+        }
+
+        let mut key = if self.shift() {
+            KEYMAP_SHIFT[code as usize]
+        } else {
+            plain
+        };
+
+        // caps lock inverts the case of letters only
+        if plain.is_ascii_lowercase() && self.modifiers.contains(Modifiers::CAPS_LOCK) {
+            key = if key.is_ascii_lowercase() {
+                key.to_ascii_uppercase()
+            } else {
+                key.to_ascii_lowercase()
+            };
+        }
+
+        // ctrl-a .. ctrl-z map to 0x01 .. 0x1A
+        if self.ctrl() && plain.is_ascii_lowercase() {
+            key = plain & 0x1F;
+        }
+
+        push_key(key);
+    }
+
+    /// Starts updating the caps lock LED. Nothing is waited for in the
+    /// interrupt handler, the LEDs are sent on the ACK of the command,
+    /// see `ack`. A newer state replaces one not sent yet.
+    fn set_leds(&mut self) {
+        let leds = if self.modifiers.contains(Modifiers::CAPS_LOCK) {
+            1 << 2
+        } else {
+            0
+        };
+
+        if self.pending_leds.replace(leds).is_none() && !try_write_data(KEYBOARD_SET_LEDS) {
+            self.pending_leds = None;
+            warn!("Failed to update keyboard LEDs");
+        }
+    }
+
+    fn ack(&mut self) {
+        if let Some(leds) = self.pending_leds.take() {
+            if !try_write_data(leds) {
+                warn!("Failed to update keyboard LEDs");
+            }
+        }
+    }
+}
+
+#[inline]
+fn status() -> ControllerStatus {
+    ControllerStatus::from_bits_truncate(unsafe { Port::<u8>::new(STATUS_PORT).read() })
+}
+
+fn wait_write() -> bool {
+    for _ in 0..TIMEOUT {
+        if !status().contains(ControllerStatus::INPUT_FULL) {
+            return true;
+        }
+        core::hint::spin_loop();
+    }
+    false
+}
+
+fn wait_read() -> bool {
+    for _ in 0..TIMEOUT {
+        if status().contains(ControllerStatus::OUTPUT_FULL) {
+            return true;
+        }
+        core::hint::spin_loop();
+    }
+    false
+}
+
+fn send_command(cmd: ControllerCommand) -> bool {
+    wait_write() && {
+        unsafe { Port::<u8>::new(COMMAND_PORT).write(cmd as u8) };
+        true
+    }
+}
+
+fn write_data(data: u8) -> bool {
+    wait_write() && {
+        unsafe { Port::<u8>::new(DATA_PORT).write(data) };
+        true
+    }
+}
+
+/// Writes without waiting, for the interrupt handler
+fn try_write_data(data: u8) -> bool {
+    !status().contains(ControllerStatus::INPUT_FULL) && {
+        unsafe { Port::<u8>::new(DATA_PORT).write(data) };
+        true
+    }
+}
+
+fn read_data() -> Option<u8> {
+    wait_read().then(|| unsafe { Port::<u8>::new(DATA_PORT).read() })
+}
+
+/// Initializes the i8042 controller with keyboard interrupts and translation enabled.
+pub fn init() {
+    // disable both ports while configuring
+    if !send_command(ControllerCommand::DisablePort1) {
+        warn!("PS/2 controller not found, keyboard disabled.");
+        return;
+    }
+    send_command(ControllerCommand::DisablePort2);
+
+    // flush the output buffer
+    while status().contains(ControllerStatus::OUTPUT_FULL) {
+        unsafe { Port::<u8>::new(DATA_PORT).read() };
+    }
+
+    send_command(ControllerCommand::ReadConfig);
+    let Some(config) = read_data() else {
+        warn!("Failed to read PS/2 controller configuration.");
+        return;
+    };
+
+    let mut config = ControllerConfig::from_bits_retain(config);
+    config.remove(ControllerConfig::PORT1_INTERRUPT | ControllerConfig::PORT2_INTERRUPT);
+
+    send_command(ControllerCommand::WriteConfig);
+    write_data(config.bits());
+
+    send_command(ControllerCommand::SelfTest);
+    if read_data() != Some(SELF_TEST_PASSED) {
+        warn!("PS/2 controller self test failed, keyboard disabled.");
+        return;
+    }
+
+    // self test may reset the controller, write the configuration again
+    config.insert(ControllerConfig::PORT1_INTERRUPT | ControllerConfig::PORT1_TRANSLATION);
+    config.remove(ControllerConfig::PORT1_CLOCK_OFF);
+
+    send_command(ControllerCommand::WriteConfig);
+    write_data(config.bits());
+
+    send_command(ControllerCommand::EnablePort1);
+
+    info!("PS/2 Keyboard Initialized.");
+}
+
+/// Reads a scancode from the controller and decodes it,
+/// should be called on every keyboard interrupt.
+pub fn receive() {
+    if !status().contains(ControllerStatus::OUTPUT_FULL) {
+        return;
+    }
+
+    let scancode = unsafe { Port::<u8>::new(DATA_PORT).read() };
+
+    match scancode {
+        KEYBOARD_ACK => KEYBOARD.lock().ack(),
+        KEYBOARD_RESEND => {}
+        _ => KEYBOARD.lock().process(scancode),
+    }
+}
@@ -1,5 +1,12 @@
 mod uart16550;
 pub mod serial;
+pub mod acpi;
+pub mod display;
 pub mod input;
+pub mod tty;
+pub mod keyboard;
+pub mod pci;
+pub mod rtc;
 pub mod ata;
-pub mod filesystem;
\ No newline at end of file
+pub mod virtio;
+pub mod filesystem;
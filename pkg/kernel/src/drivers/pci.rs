@@ -0,0 +1,209 @@
+//! PCI Configuration Space Access
+//!
+//! reference: https://wiki.osdev.org/PCI
+//! reference: https://wiki.osdev.org/PCI#Configuration_Space_Access_Mechanism_.231
+
+use alloc::vec::Vec;
+use spin::Mutex;
+use x86_64::instructions::port::Port;
+
+const CONFIG_ADDRESS: u16 = 0xCF8;
+const CONFIG_DATA: u16 = 0xCFC;
+
+/// Offsets into the common configuration header
+const VENDOR_ID: u8 = 0x00;
+const DEVICE_ID: u8 = 0x02;
+const COMMAND: u8 = 0x04;
+const CLASS_REVISION: u8 = 0x08;
+const HEADER_TYPE: u8 = 0x0E;
+const BAR0: u8 = 0x10;
+const SUBSYSTEM_ID: u8 = 0x2E;
+const INTERRUPT_LINE: u8 = 0x3C;
+
+/// Vendor id returned by a non-existent function
+const INVALID_VENDOR: u16 = 0xFFFF;
+
+bitflags! {
+    /// The bits of the PCI command register.
+    #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+    pub struct PciCommand: u16 {
+        const IO_SPACE          = 1 << 0;
+        const MEMORY_SPACE      = 1 << 1;
+        const BUS_MASTER        = 1 << 2;
+        const INTERRUPT_DISABLE = 1 << 10;
+    }
+}
+
+/// The 0xCF8/0xCFC ports are shared by every function on every bus,
+/// so the address/data pair must be written atomically.
+static CONFIG_PORTS: Mutex<(Port<u32>, Port<u32>)> =
+    Mutex::new((Port::new(CONFIG_ADDRESS), Port::new(CONFIG_DATA)));
+
+/// A Base Address Register of a PCI function.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bar {
+    Io(u16),
+    Memory(u64),
+}
+
+/// A function found on the PCI bus.
+#[derive(Debug, Clone, Copy)]
+pub struct PciDevice {
+    pub bus: u8,
+    pub device: u8,
+    pub function: u8,
+    pub vendor_id: u16,
+    pub device_id: u16,
+    pub class: u8,
+    pub subclass: u8,
+    pub subsystem_id: u16,
+}
+
+impl PciDevice {
+    fn probe(bus: u8, device: u8, function: u8) -> Option<Self> {
+        let vendor_id = read_u16(bus, device, function, VENDOR_ID);
+        if vendor_id == INVALID_VENDOR {
+            return None;
+        }
+
+        let class = read_u32(bus, device, function, CLASS_REVISION);
+
+        Some(Self {
+            bus,
+            device,
+            function,
+            vendor_id,
+            device_id: read_u16(bus, device, function, DEVICE_ID),
+            class: (class >> 24) as u8,
+            subclass: (class >> 16) as u8,
+            subsystem_id: read_u16(bus, device, function, SUBSYSTEM_ID),
+        })
+    }
+
+    #[inline]
+    pub fn read(&self, offset: u8) -> u32 {
+        read_u32(self.bus, self.device, self.function, offset)
+    }
+
+    #[inline]
+    pub fn write(&self, offset: u8, value: u32) {
+        write_u32(self.bus, self.device, self.function, offset, value)
+    }
+
+    /// Reads the `index`-th Base Address Register, 64-bit memory BARs
+    /// are combined with the following register.
+    pub fn bar(&self, index: u8) -> Option<Bar> {
+        let offset = BAR0 + index * 4;
+        let raw = self.read(offset);
+
+        if raw == 0 {
+            return None;
+        }
+
+        if raw & 0x1 == 1 {
+            return Some(Bar::Io((raw & !0x3) as u16));
+        }
+
+        let mut addr = (raw & !0xF) as u64;
+        if (raw >> 1) & 0x3 == 0x2 {
+            addr |= (self.read(offset + 4) as u64) << 32;
+        }
+
+        Some(Bar::Memory(addr))
+    }
+
+    pub fn interrupt_line(&self) -> u8 {
+        self.read(INTERRUPT_LINE) as u8
+    }
+
+    pub fn command(&self) -> PciCommand {
+        PciCommand::from_bits_truncate(self.read(COMMAND) as u16)
+    }
+
+    /// Sets the given bits in the command register,
+    /// the status register in the upper half is write-1-to-clear and left untouched.
+    pub fn enable(&self, flags: PciCommand) {
+        let command = self.command() | flags;
+        self.write(COMMAND, command.bits() as u32);
+    }
+}
+
+impl core::fmt::Display for PciDevice {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(
+            f,
+            "{:02x}:{:02x}.{} [{:04x}:{:04x}] class {:02x}:{:02x}",
+            self.bus,
+            self.device,
+            self.function,
+            self.vendor_id,
+            self.device_id,
+            self.class,
+            self.subclass
+        )
+    }
+}
+
+#[inline]
+fn config_address(bus: u8, device: u8, function: u8, offset: u8) -> u32 {
+    0x8000_0000
+        | (bus as u32) << 16
+        | (device as u32 & 0x1F) << 11
+        | (function as u32 & 0x7) << 8
+        | (offset as u32 & 0xFC)
+}
+
+fn read_u32(bus: u8, device: u8, function: u8, offset: u8) -> u32 {
+    let mut ports = CONFIG_PORTS.lock();
+    unsafe {
+        ports.0.write(config_address(bus, device, function, offset));
+        ports.1.read()
+    }
+}
+
+fn read_u16(bus: u8, device: u8, function: u8, offset: u8) -> u16 {
+    let value = read_u32(bus, device, function, offset);
+    (value >> ((offset as u32 & 0x2) * 8)) as u16
+}
+
+fn read_u8(bus: u8, device: u8, function: u8, offset: u8) -> u8 {
+    let value = read_u32(bus, device, function, offset);
+    (value >> ((offset as u32 & 0x3) * 8)) as u8
+}
+
+fn write_u32(bus: u8, device: u8, function: u8, offset: u8, value: u32) {
+    let mut ports = CONFIG_PORTS.lock();
+    unsafe {
+        ports.0.write(config_address(bus, device, function, offset));
+        ports.1.write(value);
+    }
+}
+
+/// Brute-force scan of all buses, devices and functions.
+pub fn scan() -> Vec<PciDevice> {
+    let mut devices = Vec::new();
+
+    for bus in 0..=255u8 {
+        for device in 0..32u8 {
+            let Some(dev) = PciDevice::probe(bus, device, 0) else {
+                continue;
+            };
+
+            devices.push(dev);
+
+            // multi-function device
+            if read_u8(bus, device, 0, HEADER_TYPE) & 0x80 != 0 {
+                devices.extend((1..8).filter_map(|func| PciDevice::probe(bus, device, func)));
+            }
+        }
+    }
+
+    devices
+}
+
+/// Finds the first function matching the given vendor and one of the device ids.
+pub fn find(vendor_id: u16, device_ids: &[u16]) -> Option<PciDevice> {
+    scan()
+        .into_iter()
+        .find(|dev| dev.vendor_id == vendor_id && device_ids.contains(&dev.device_id))
+}
@@ -0,0 +1,173 @@
+//! CMOS Real Time Clock
+//!
+//! reference: https://wiki.osdev.org/CMOS
+//! reference: https://wiki.osdev.org/RTC
+
+use chrono::{DateTime, NaiveDate, Utc};
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+use x86_64::instructions::port::Port;
+
+const CMOS_ADDRESS: u16 = 0x70;
+const CMOS_DATA: u16 = 0x71;
+
+/// Setting this bit in the address port disables NMI
+const NMI_DISABLE: u8 = 0x80;
+
+#[repr(u8)]
+#[derive(Clone, Copy)]
+enum Register {
+    Seconds = 0x00,
+    Minutes = 0x02,
+    Hours = 0x04,
+    Day = 0x07,
+    Month = 0x08,
+    Year = 0x09,
+    StatusA = 0x0A,
+    StatusB = 0x0B,
+    /// Not guaranteed to exist, but QEMU and most firmwares have it
+    Century = 0x32,
+}
+
+bitflags! {
+    #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+    struct StatusA: u8 {
+        const UPDATE_IN_PROGRESS = 1 << 7;
+    }
+}
+
+bitflags! {
+    #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+    struct StatusB: u8 {
+        const DAYLIGHT_SAVING    = 1 << 0;
+        const HOUR_24            = 1 << 1;
+        const BINARY             = 1 << 2;
+        const SQUARE_WAVE        = 1 << 3;
+        const UPDATE_INTERRUPT   = 1 << 4;
+        const ALARM_INTERRUPT    = 1 << 5;
+        const PERIODIC_INTERRUPT = 1 << 6;
+        const SET                = 1 << 7;
+    }
+}
+
+/// The PM flag of the hour register in 12-hour mode
+const HOUR_PM: u8 = 0x80;
+
+/// Used when the century register reads as garbage
+const DEFAULT_CENTURY: u16 = 20;
+
+/// The address/data port pair must be accessed atomically.
+static CMOS: Mutex<(Port<u8>, Port<u8>)> =
+    Mutex::new((Port::new(CMOS_ADDRESS), Port::new(CMOS_DATA)));
+
+/// Interrupts are disabled while holding the lock.
+fn read(reg: Register) -> u8 {
+    without_interrupts(|| {
+        let mut cmos = CMOS.lock();
+        unsafe {
+            cmos.0.write(NMI_DISABLE | reg as u8);
+            let value = cmos.1.read();
+            // re-enable NMI
+            cmos.0.write(0);
+            value
+        }
+    })
+}
+
+#[inline]
+fn update_in_progress() -> bool {
+    StatusA::from_bits_truncate(read(Register::StatusA)).contains(StatusA::UPDATE_IN_PROGRESS)
+}
+
+#[inline]
+fn bcd_to_binary(value: u8) -> u8 {
+    (value & 0x0F) + (value >> 4) * 10
+}
+
+/// Raw register values of a single read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct RawTime {
+    second: u8,
+    minute: u8,
+    hour: u8,
+    day: u8,
+    month: u8,
+    year: u8,
+    century: u8,
+}
+
+impl RawTime {
+    fn read() -> Self {
+        while update_in_progress() {
+            core::hint::spin_loop();
+        }
+
+        Self {
+            second: read(Register::Seconds),
+            minute: read(Register::Minutes),
+            hour: read(Register::Hours),
+            day: read(Register::Day),
+            month: read(Register::Month),
+            year: read(Register::Year),
+            century: read(Register::Century),
+        }
+    }
+
+    /// Converts the raw values according to the format in status register B.
+    fn decode(self) -> Option<DateTime<Utc>> {
+        let status = StatusB::from_bits_truncate(read(Register::StatusB));
+
+        let pm = self.hour & HOUR_PM != 0;
+        let mut hour = self.hour & !HOUR_PM;
+        let (mut second, mut minute, mut day, mut month, mut year, mut century) = (
+            self.second,
+            self.minute,
+            self.day,
+            self.month,
+            self.year,
+            self.century,
+        );
+
+        if !status.contains(StatusB::BINARY) {
+            second = bcd_to_binary(second);
+            minute = bcd_to_binary(minute);
+            hour = bcd_to_binary(hour);
+            day = bcd_to_binary(day);
+            month = bcd_to_binary(month);
+            year = bcd_to_binary(year);
+            century = bcd_to_binary(century);
+        }
+
+        // 12 AM is 0 and 12 PM is 12 in 24-hour mode
+        if !status.contains(StatusB::HOUR_24) {
+            hour %= 12;
+            if pm {
+                hour += 12;
+            }
+        }
+
+        let century = match century {
+            19..=99 => century as u16,
+            _ => DEFAULT_CENTURY,
+        };
+
+        NaiveDate::from_ymd_opt((century * 100 + year as u16) as i32, month as u32, day as u32)?
+            .and_hms_opt(hour as u32, minute as u32, second as u32)
+            .map(|time| time.and_utc())
+    }
+}
+
+/// Reads the current date and time, the RTC is assumed to be in UTC.
+///
+/// The registers are read until two consecutive reads agree,
+/// so that an update happening in between is not observed.
+pub fn read_time() -> Option<DateTime<Utc>> {
+    let mut last = RawTime::read();
+    loop {
+        let current = RawTime::read();
+        if current == last {
+            break current.decode();
+        }
+        last = current;
+    }
+}
@@ -0,0 +1,252 @@
+//! Line discipline of the console
+//!
+//! Every key typed on the serial port or the keyboard goes through `receive`
+//! in its interrupt handler. In canonical mode the line is edited and echoed
+//! here, and becomes readable once it is ended by Enter or Ctrl-D; in raw mode
+//! every byte is readable at once. Ctrl-C and Ctrl-Z send signals to the
+//! foreground process if `ISIG` is set.
+//!
+//! A process reading with no input is blocked on the console until the
+//! interrupt handler makes some readable, then it restarts the read.
+
+use crate::proc::ProcessId;
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use spin::Mutex;
+use syscall_def::signal::{SIGINT, SIGTSTP};
+use syscall_def::tty::*;
+
+const CTRL_C: u8 = 0x03;
+const CTRL_D: u8 = 0x04;
+const CTRL_Z: u8 = 0x1A;
+const BACKSPACE: u8 = 0x08;
+const DELETE: u8 = 0x7F;
+
+/// Readable bytes kept, more input is dropped
+const MAX_INPUT: usize = 4096;
+/// Longest line that can be edited
+const MAX_LINE: usize = 1024;
+
+struct Tty {
+    mode: u64,
+    /// The line being edited in canonical mode
+    line: Vec<u8>,
+    /// Readable input, an empty record is the end of input
+    input: VecDeque<Vec<u8>>,
+    input_len: usize,
+    foreground: Option<ProcessId>,
+    /// Processes blocked until there is input
+    waiters: Vec<ProcessId>,
+}
+
+static TTY: Mutex<Tty> = Mutex::new(Tty::new());
+
+impl Tty {
+    const fn new() -> Self {
+        Self {
+            mode: DEFAULT_MODE,
+            line: Vec::new(),
+            input: VecDeque::new(),
+            input_len: 0,
+            foreground: None,
+            waiters: Vec::new(),
+        }
+    }
+
+    /// The blocked readers to wake up, if there is input for them
+    fn take_waiters(&mut self) -> Vec<ProcessId> {
+        if self.input.is_empty() {
+            Vec::new()
+        } else {
+            core::mem::take(&mut self.waiters)
+        }
+    }
+
+    #[inline]
+    fn is_canonical(&self) -> bool {
+        self.mode & ICANON != 0
+    }
+
+    fn echo(&self, bytes: &[u8]) {
+        if self.mode & ECHO != 0 {
+            print!("{}", alloc::string::String::from_utf8_lossy(bytes));
+        }
+    }
+
+    /// Handle a typed key, returns the signal to send if it is one.
+    fn receive(&mut self, key: u8) -> Option<(ProcessId, usize)> {
+        if self.mode & ISIG != 0 {
+            let sig = match key {
+                CTRL_C => Some((SIGINT, b"^C\n")),
+                CTRL_Z => Some((SIGTSTP, b"^Z\n")),
+                _ => None,
+            };
+
+            if let Some((sig, echo)) = sig {
+                self.echo(echo);
+                self.line.clear();
+                return self.foreground.map(|pid| (pid, sig));
+            }
+        }
+
+        if !self.is_canonical() {
+            self.echo(&[key]);
+            self.push_raw(key);
+            return None;
+        }
+
+        match key {
+            b'\r' | b'\n' => {
+                self.echo(b"\n");
+                self.line.push(b'\n');
+                self.end_line();
+            }
+            BACKSPACE | DELETE if !self.line.is_empty() => {
+                self.line.pop();
+                self.echo(b"\x08\x20\x08");
+            }
+            BACKSPACE | DELETE => {}
+            // the line is readable without a newline, an empty one ends the input
+            CTRL_D => self.end_line(),
+            _ if self.line.len() < MAX_LINE => {
+                self.echo(&[key]);
+                self.line.push(key);
+            }
+            _ => {}
+        }
+
+        None
+    }
+
+    fn end_line(&mut self) {
+        let line = core::mem::take(&mut self.line);
+        if self.input_len + line.len() > MAX_INPUT {
+            warn!("Console input is full, dropping a line.");
+            return;
+        }
+
+        self.input_len += line.len();
+        self.input.push_back(line);
+    }
+
+    fn push_raw(&mut self, key: u8) {
+        if self.input_len >= MAX_INPUT {
+            warn!("Console input is full, dropping key '{:?}'", key);
+            return;
+        }
+
+        self.input_len += 1;
+        match self.input.back_mut() {
+            Some(record) if !record.is_empty() => record.push(key),
+            _ => self.input.push_back(alloc::vec![key]),
+        }
+    }
+
+    /// In canonical mode at most one line is read at a time.
+    fn read(&mut self, buf: &mut [u8]) -> Option<usize> {
+        let mut count = 0;
+
+        while count < buf.len() {
+            let Some(record) = self.input.front_mut() else {
+                break;
+            };
+
+            // the end of input is only returned by itself
+            if record.is_empty() {
+                if count == 0 {
+                    self.input.pop_front();
+                    return Some(0);
+                }
+                break;
+            }
+
+            let len = record.len().min(buf.len() - count);
+            buf[count..count + len].copy_from_slice(&record[..len]);
+            record.drain(..len);
+            count += len;
+
+            if !record.is_empty() {
+                break;
+            }
+
+            self.input.pop_front();
+            if self.is_canonical() {
+                break;
+            }
+        }
+
+        self.input_len -= count;
+        (count > 0).then_some(count)
+    }
+
+    fn set_mode(&mut self, mode: u64) {
+        // the line being edited becomes readable as it is
+        if self.is_canonical() && mode & ICANON == 0 && !self.line.is_empty() {
+            self.end_line();
+        }
+
+        self.mode = mode & (ISIG | ICANON | ECHO);
+    }
+}
+
+/// Called for every key typed on the console, in the interrupt handler.
+pub fn receive(key: u8) {
+    let mut tty = TTY.lock();
+    let signal = tty.receive(key);
+    let waiters = tty.take_waiters();
+    drop(tty);
+
+    // the processes are locked after the console, never while it is
+    wake_up(waiters);
+    if let Some((pid, sig)) = signal {
+        crate::proc::send_signal(pid, sig);
+    }
+}
+
+fn wake_up(waiters: Vec<ProcessId>) {
+    for pid in waiters {
+        crate::proc::wake_up(pid);
+    }
+}
+
+/// Block `pid` with `block` until there is input, which must be done
+/// under the lock of the console so the input can not be missed.
+/// Returns false without blocking if there is input already.
+pub fn wait_input(pid: ProcessId, block: impl FnOnce()) -> bool {
+    let mut tty = TTY.lock();
+    if !tty.input.is_empty() {
+        return false;
+    }
+
+    block();
+    tty.waiters.push(pid);
+    true
+}
+
+/// Read the console input into `buf`, `None` if there is nothing to read
+/// yet and `Some(0)` at the end of input (Ctrl-D on an empty line).
+pub fn read(buf: &mut [u8]) -> Option<usize> {
+    TTY.lock().read(buf)
+}
+
+pub fn mode() -> u64 {
+    TTY.lock().mode
+}
+
+pub fn set_mode(mode: u64) {
+    let mut tty = TTY.lock();
+    tty.set_mode(mode);
+    let waiters = tty.take_waiters();
+    drop(tty);
+
+    wake_up(waiters);
+}
+
+/// The process receiving the signals typed on the console
+pub fn foreground() -> Option<ProcessId> {
+    TTY.lock().foreground
+}
+
+pub fn set_foreground(pid: Option<ProcessId>) {
+    TTY.lock().foreground = pid;
+}
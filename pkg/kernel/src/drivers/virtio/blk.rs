@@ -0,0 +1,249 @@
+//! VirtIO Block Device
+//!
+//! Requests are issued synchronously and completion is polled,
+//! the same way the ATA driver works.
+//!
+//! reference: https://docs.oasis-open.org/virtio/virtio/v1.1/cs01/virtio-v1.1-cs01.html#x1-2390002
+
+use super::*;
+use crate::memory::{get_frame_alloc_blocking, physical_to_virtual};
+use spin::Mutex;
+use storage::{Block512, BlockDevice, DeviceError, FsResult};
+use x86_64::structures::paging::FrameAllocator;
+use x86_64::PhysAddr;
+
+/// Transitional (legacy capable) block device id
+const DEVICE_ID_LEGACY: u16 = 0x1001;
+
+/// Feature bits
+const VIRTIO_BLK_F_RO: u32 = 1 << 5;
+
+/// Offsets into the device specific configuration
+const CONFIG_CAPACITY: u16 = 0x00;
+
+/// Request types
+const VIRTIO_BLK_T_IN: u32 = 0;
+const VIRTIO_BLK_T_OUT: u32 = 1;
+
+/// Request status
+const VIRTIO_BLK_S_OK: u8 = 0;
+const VIRTIO_BLK_S_IOERR: u8 = 1;
+const VIRTIO_BLK_S_UNSUPP: u8 = 2;
+
+/// The block device only has a single request queue
+const REQUEST_QUEUE: u16 = 0;
+
+/// Layout of the DMA frame: request header, one sector of data, status byte
+const HEADER_OFFSET: u64 = 0;
+const DATA_OFFSET: u64 = 512;
+const STATUS_OFFSET: u64 = 1024;
+
+const SECTOR_SIZE: usize = 512;
+
+#[repr(C)]
+struct RequestHeader {
+    ty: u32,
+    reserved: u32,
+    sector: u64,
+}
+
+lazy_static! {
+    pub static ref VIRTIO_BLK: Option<Mutex<VirtioBlkDevice>> = {
+        let device = crate::drivers::pci::find(VIRTIO_VENDOR_ID, &[DEVICE_ID_LEGACY])
+            .and_then(VirtioBlkDevice::new);
+
+        if device.is_some() {
+            info!("Initialized VirtIO Block Device.");
+        }
+
+        device.map(Mutex::new)
+    };
+}
+
+pub struct VirtioBlkDevice {
+    transport: VirtioPci,
+    queue: VirtQueue,
+    /// Bounce buffer shared with the device, in the physical offset mapping
+    dma: PhysAddr,
+    capacity: u64,
+    read_only: bool,
+}
+
+impl VirtioBlkDevice {
+    fn new(pci: PciDevice) -> Option<Self> {
+        info!("Found VirtIO block device {}", pci);
+
+        let transport = VirtioPci::new(pci)?;
+        let features = transport.begin_init(VIRTIO_BLK_F_RO);
+
+        let Some(queue) = transport.setup_queue(REQUEST_QUEUE) else {
+            warn!("Failed to setup VirtIO block request queue");
+            transport.fail();
+            return None;
+        };
+
+        let Some(frame) = get_frame_alloc_blocking().allocate_frame() else {
+            warn!("Failed to allocate VirtIO block DMA buffer");
+            transport.fail();
+            return None;
+        };
+
+        let capacity = transport.config_u64(CONFIG_CAPACITY);
+
+        transport.finish_init();
+
+        Some(Self {
+            transport,
+            queue,
+            dma: frame.start_address(),
+            capacity,
+            read_only: features & VIRTIO_BLK_F_RO != 0,
+        })
+    }
+
+    #[inline]
+    fn dma_ptr(&self, offset: u64) -> *mut u8 {
+        physical_to_virtual(self.dma.as_u64() + offset) as *mut u8
+    }
+
+    /// Submits a single sector request and polls for its completion.
+    fn request(&mut self, ty: u32, sector: u64, data_writable: bool) -> FsResult {
+        if sector >= self.capacity {
+            return Err(DeviceError::InvalidOperation.into());
+        }
+
+        let header = RequestHeader {
+            ty,
+            reserved: 0,
+            sector,
+        };
+
+        unsafe {
+            core::ptr::write_volatile(self.dma_ptr(HEADER_OFFSET) as *mut RequestHeader, header);
+            core::ptr::write_volatile(self.dma_ptr(STATUS_OFFSET), 0xFF);
+        }
+
+        let buffers = [
+            Buffer {
+                addr: self.dma + HEADER_OFFSET,
+                len: size_of::<RequestHeader>() as u32,
+                writable: false,
+            },
+            Buffer {
+                addr: self.dma + DATA_OFFSET,
+                len: SECTOR_SIZE as u32,
+                writable: data_writable,
+            },
+            Buffer {
+                addr: self.dma + STATUS_OFFSET,
+                len: 1,
+                writable: true,
+            },
+        ];
+
+        if self.queue.push(&buffers).is_none() {
+            return Err(DeviceError::Busy.into());
+        }
+
+        self.transport.notify(REQUEST_QUEUE);
+
+        while !self.queue.can_pop() {
+            core::hint::spin_loop();
+        }
+
+        self.queue.pop();
+        // acknowledge the (unused) interrupt
+        self.transport.isr_status();
+
+        match unsafe { core::ptr::read_volatile(self.dma_ptr(STATUS_OFFSET)) } {
+            VIRTIO_BLK_S_OK => Ok(()),
+            VIRTIO_BLK_S_IOERR if ty == VIRTIO_BLK_T_IN => Err(DeviceError::ReadError.into()),
+            VIRTIO_BLK_S_IOERR => Err(DeviceError::WriteError.into()),
+            VIRTIO_BLK_S_UNSUPP => Err(DeviceError::InvalidOperation.into()),
+            status => Err(DeviceError::WithStatus(status as usize).into()),
+        }
+    }
+
+    pub fn read_sector(&mut self, sector: u64, buf: &mut [u8]) -> FsResult {
+        self.request(VIRTIO_BLK_T_IN, sector, true)?;
+
+        unsafe {
+            core::ptr::copy_nonoverlapping(self.dma_ptr(DATA_OFFSET), buf.as_mut_ptr(), SECTOR_SIZE);
+        }
+
+        Ok(())
+    }
+
+    pub fn write_sector(&mut self, sector: u64, buf: &[u8]) -> FsResult {
+        if self.read_only {
+            return Err(DeviceError::InvalidOperation.into());
+        }
+
+        unsafe {
+            core::ptr::copy_nonoverlapping(buf.as_ptr(), self.dma_ptr(DATA_OFFSET), SECTOR_SIZE);
+        }
+
+        self.request(VIRTIO_BLK_T_OUT, sector, false)
+    }
+}
+
+/// A handle to the VirtIO block device, cheap to clone like `AtaDrive`.
+#[derive(Clone)]
+pub struct VirtioBlk {
+    blocks: usize,
+    read_only: bool,
+}
+
+impl VirtioBlk {
+    pub fn open() -> Option<Self> {
+        trace!("Opening VirtIO block device...");
+
+        let device = VIRTIO_BLK.as_ref()?.lock();
+        let blk = Self {
+            blocks: device.capacity as usize,
+            read_only: device.read_only,
+        };
+
+        info!("Drive {} opened", blk);
+        Some(blk)
+    }
+
+    fn humanized_size(&self) -> (f32, &'static str) {
+        let bytes = self.blocks * SECTOR_SIZE;
+
+        crate::humanized_size(bytes as u64)
+    }
+}
+
+impl core::fmt::Display for VirtioBlk {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        let (size, unit) = self.humanized_size();
+        write!(f, "VirtIO Block Device ({} {})", size, unit)?;
+        if self.read_only {
+            write!(f, " [RO]")?;
+        }
+        Ok(())
+    }
+}
+
+impl BlockDevice<Block512> for VirtioBlk {
+    fn block_count(&self) -> FsResult<usize> {
+        Ok(self.blocks)
+    }
+
+    fn read_block(&self, offset: usize, block: &mut Block512) -> FsResult {
+        VIRTIO_BLK
+            .as_ref()
+            .ok_or(DeviceError::UnknownDevice)?
+            .lock()
+            .read_sector(offset as u64, block.as_mut())
+    }
+
+    fn write_block(&self, offset: usize, block: &Block512) -> FsResult {
+        VIRTIO_BLK
+            .as_ref()
+            .ok_or(DeviceError::UnknownDevice)?
+            .lock()
+            .write_sector(offset as u64, block.as_ref())
+    }
+}
@@ -0,0 +1,171 @@
+//! VirtIO over PCI
+//!
+//! Only the legacy (transitional) interface is implemented, which exposes
+//! the device registers through an I/O BAR and does not require MMIO mappings.
+//!
+//! reference: https://wiki.osdev.org/Virtio
+//! reference: https://docs.oasis-open.org/virtio/virtio/v1.1/cs01/virtio-v1.1-cs01.html#x1-1120003
+
+mod blk;
+mod queue;
+
+pub use blk::VirtioBlk;
+pub use queue::{Buffer, VirtQueue};
+
+use super::pci::{Bar, PciCommand, PciDevice};
+use x86_64::instructions::port::{Port, PortRead, PortWrite};
+
+pub const VIRTIO_VENDOR_ID: u16 = 0x1AF4;
+
+/// Legacy register offsets in the I/O BAR
+const DEVICE_FEATURES: u16 = 0x00;
+const DRIVER_FEATURES: u16 = 0x04;
+const QUEUE_ADDRESS: u16 = 0x08;
+const QUEUE_SIZE: u16 = 0x0C;
+const QUEUE_SELECT: u16 = 0x0E;
+const QUEUE_NOTIFY: u16 = 0x10;
+const DEVICE_STATUS: u16 = 0x12;
+const ISR_STATUS: u16 = 0x13;
+/// Device specific configuration, when MSI-X is disabled
+const DEVICE_CONFIG: u16 = 0x14;
+
+/// The legacy interface uses 4096-byte aligned queues, addressed by page number
+const QUEUE_ADDRESS_SHIFT: u64 = 12;
+
+bitflags! {
+    /// The device status field.
+    #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+    pub struct DeviceStatus: u8 {
+        const ACKNOWLEDGE        = 1;
+        const DRIVER             = 2;
+        const DRIVER_OK          = 4;
+        const FEATURES_OK        = 8;
+        const DEVICE_NEEDS_RESET = 64;
+        const FAILED             = 128;
+    }
+}
+
+/// Legacy virtio-pci transport of a single device.
+#[derive(Debug, Clone, Copy)]
+pub struct VirtioPci {
+    pci: PciDevice,
+    io_base: u16,
+}
+
+impl VirtioPci {
+    /// Takes over a transitional virtio device found on the PCI bus.
+    pub fn new(pci: PciDevice) -> Option<Self> {
+        let Some(Bar::Io(io_base)) = pci.bar(0) else {
+            warn!("VirtIO device {} has no legacy I/O BAR", pci);
+            return None;
+        };
+
+        pci.enable(PciCommand::IO_SPACE | PciCommand::BUS_MASTER);
+
+        Some(Self { pci, io_base })
+    }
+
+    #[inline]
+    pub fn pci(&self) -> &PciDevice {
+        &self.pci
+    }
+
+    #[inline]
+    fn read<T: PortRead>(&self, offset: u16) -> T {
+        unsafe { Port::<T>::new(self.io_base + offset).read() }
+    }
+
+    #[inline]
+    fn write<T: PortWrite>(&self, offset: u16, value: T) {
+        unsafe { Port::<T>::new(self.io_base + offset).write(value) }
+    }
+
+    pub fn status(&self) -> DeviceStatus {
+        DeviceStatus::from_bits_truncate(self.read::<u8>(DEVICE_STATUS))
+    }
+
+    pub fn set_status(&self, status: DeviceStatus) {
+        self.write::<u8>(DEVICE_STATUS, status.bits());
+    }
+
+    /// Writing zero to the status register resets the device.
+    pub fn reset(&self) {
+        self.write::<u8>(DEVICE_STATUS, 0);
+    }
+
+    pub fn device_features(&self) -> u32 {
+        self.read::<u32>(DEVICE_FEATURES)
+    }
+
+    pub fn set_driver_features(&self, features: u32) {
+        self.write::<u32>(DRIVER_FEATURES, features);
+    }
+
+    /// Reading the ISR status acknowledges the interrupt.
+    pub fn isr_status(&self) -> u8 {
+        self.read::<u8>(ISR_STATUS)
+    }
+
+    pub fn config_u32(&self, offset: u16) -> u32 {
+        self.read::<u32>(DEVICE_CONFIG + offset)
+    }
+
+    pub fn config_u64(&self, offset: u16) -> u64 {
+        let low = self.config_u32(offset) as u64;
+        let high = self.config_u32(offset + 4) as u64;
+        high << 32 | low
+    }
+
+    /// Resets the device and negotiates features, returns the accepted features.
+    ///
+    /// reference: https://docs.oasis-open.org/virtio/virtio/v1.1/cs01/virtio-v1.1-cs01.html#x1-920001
+    pub fn begin_init(&self, supported: u32) -> u32 {
+        self.reset();
+
+        let mut status = DeviceStatus::ACKNOWLEDGE;
+        self.set_status(status);
+        status |= DeviceStatus::DRIVER;
+        self.set_status(status);
+
+        let features = self.device_features() & supported;
+        self.set_driver_features(features);
+
+        features
+    }
+
+    /// Tells the device that the driver is ready to drive it.
+    pub fn finish_init(&self) {
+        self.set_status(DeviceStatus::ACKNOWLEDGE | DeviceStatus::DRIVER | DeviceStatus::DRIVER_OK);
+    }
+
+    /// Marks the device as failed, the driver gives up on it.
+    pub fn fail(&self) {
+        self.set_status(self.status() | DeviceStatus::FAILED);
+    }
+
+    /// Allocates the queue `index` with the size suggested by the device.
+    pub fn setup_queue(&self, index: u16) -> Option<VirtQueue> {
+        self.write::<u16>(QUEUE_SELECT, index);
+
+        let size = self.read::<u16>(QUEUE_SIZE);
+        if size == 0 {
+            // queue not available
+            return None;
+        }
+
+        let queue = VirtQueue::new(size)?;
+        self.write::<u32>(
+            QUEUE_ADDRESS,
+            (queue.phys_addr().as_u64() >> QUEUE_ADDRESS_SHIFT) as u32,
+        );
+
+        trace!("VirtIO queue {} initialized with {} entries", index, size);
+
+        Some(queue)
+    }
+
+    #[inline]
+    pub fn notify(&self, index: u16) {
+        self.write::<u16>(QUEUE_NOTIFY, index);
+    }
+}
@@ -0,0 +1,245 @@
+//! Split Virtqueue
+//!
+//! reference: https://docs.oasis-open.org/virtio/virtio/v1.1/cs01/virtio-v1.1-cs01.html#x1-240006
+//! reference: https://docs.oasis-open.org/virtio/virtio/v1.1/cs01/virtio-v1.1-cs01.html#x1-250001
+
+use crate::memory::{get_frame_alloc_blocking, physical_to_virtual, PAGE_SIZE};
+use core::ptr::{read_volatile, write_volatile};
+use core::sync::atomic::{fence, Ordering};
+use x86_64::PhysAddr;
+
+bitflags! {
+    /// Flags of a virtqueue descriptor.
+    #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+    pub struct DescFlags: u16 {
+        /// This marks a buffer as continuing via the next field.
+        const NEXT     = 1;
+        /// This marks a buffer as device write-only (otherwise device read-only).
+        const WRITE    = 2;
+        /// This means the buffer contains a list of buffer descriptors.
+        const INDIRECT = 4;
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct Descriptor {
+    addr: u64,
+    len: u32,
+    flags: u16,
+    next: u16,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct UsedElem {
+    id: u32,
+    len: u32,
+}
+
+/// A buffer handed to the device as part of a descriptor chain.
+#[derive(Debug, Clone, Copy)]
+pub struct Buffer {
+    pub addr: PhysAddr,
+    pub len: u32,
+    /// Whether the device writes into this buffer
+    pub writable: bool,
+}
+
+/// A split virtqueue in the legacy layout: descriptor table and
+/// available ring in the first pages, used ring on the next page boundary.
+///
+/// The whole queue lives in physically contiguous frames, accessed
+/// through the physical memory offset mapping.
+pub struct VirtQueue {
+    size: u16,
+    phys: PhysAddr,
+    base: u64,
+    avail_offset: usize,
+    used_offset: usize,
+    free_head: u16,
+    num_free: u16,
+    avail_idx: u16,
+    last_used_idx: u16,
+}
+
+#[inline]
+const fn align_up(value: usize, align: usize) -> usize {
+    (value + align - 1) & !(align - 1)
+}
+
+impl VirtQueue {
+    /// Allocates and initializes a queue with `size` entries.
+    pub fn new(size: u16) -> Option<Self> {
+        let n = size as usize;
+        let page_size = PAGE_SIZE as usize;
+
+        let avail_offset = 16 * n;
+        let used_offset = align_up(avail_offset + 6 + 2 * n, page_size);
+        let total = used_offset + align_up(6 + 8 * n, page_size);
+
+        let frame = get_frame_alloc_blocking().allocate_contiguous(total / page_size)?;
+        let phys = frame.start_address();
+        let base = physical_to_virtual(phys.as_u64());
+
+        unsafe {
+            core::ptr::write_bytes(base as *mut u8, 0, total);
+        }
+
+        let mut queue = Self {
+            size,
+            phys,
+            base,
+            avail_offset,
+            used_offset,
+            free_head: 0,
+            num_free: size,
+            avail_idx: 0,
+            last_used_idx: 0,
+        };
+
+        // link all descriptors into the free list
+        for i in 0..size {
+            let mut desc = queue.desc(i);
+            desc.next = i.wrapping_add(1);
+            queue.set_desc(i, desc);
+        }
+
+        Some(queue)
+    }
+
+    /// Physical address of the queue, to be written into the device.
+    #[inline]
+    pub fn phys_addr(&self) -> PhysAddr {
+        self.phys
+    }
+
+    #[inline]
+    pub fn size(&self) -> u16 {
+        self.size
+    }
+
+    #[inline]
+    fn desc_ptr(&self, idx: u16) -> *mut Descriptor {
+        (self.base as *mut Descriptor).wrapping_add(idx as usize)
+    }
+
+    #[inline]
+    fn desc(&self, idx: u16) -> Descriptor {
+        unsafe { read_volatile(self.desc_ptr(idx)) }
+    }
+
+    #[inline]
+    fn set_desc(&mut self, idx: u16, desc: Descriptor) {
+        unsafe { write_volatile(self.desc_ptr(idx), desc) }
+    }
+
+    /// avail ring: flags, idx, ring[size], used_event
+    #[inline]
+    fn avail_ptr(&self, field: usize) -> *mut u16 {
+        ((self.base as usize + self.avail_offset) as *mut u16).wrapping_add(field)
+    }
+
+    /// used ring: flags, idx, ring[size], avail_event
+    #[inline]
+    fn used_idx(&self) -> u16 {
+        unsafe { read_volatile((self.base as usize + self.used_offset + 2) as *const u16) }
+    }
+
+    #[inline]
+    fn used_elem(&self, idx: u16) -> UsedElem {
+        let ring = (self.base as usize + self.used_offset + 4) as *const UsedElem;
+        unsafe { read_volatile(ring.wrapping_add((idx % self.size) as usize)) }
+    }
+
+    /// Places a descriptor chain into the available ring,
+    /// returns the head index of the chain.
+    pub fn push(&mut self, buffers: &[Buffer]) -> Option<u16> {
+        if buffers.is_empty() || buffers.len() > self.num_free as usize {
+            return None;
+        }
+
+        let head = self.free_head;
+        let mut idx = head;
+
+        for (i, buf) in buffers.iter().enumerate() {
+            let mut desc = self.desc(idx);
+            let next = desc.next;
+
+            let mut flags = DescFlags::empty();
+            if buf.writable {
+                flags |= DescFlags::WRITE;
+            }
+            if i + 1 < buffers.len() {
+                flags |= DescFlags::NEXT;
+            }
+
+            desc.addr = buf.addr.as_u64();
+            desc.len = buf.len;
+            desc.flags = flags.bits();
+            self.set_desc(idx, desc);
+
+            if i + 1 < buffers.len() {
+                idx = next;
+            } else {
+                self.free_head = next;
+            }
+        }
+
+        self.num_free -= buffers.len() as u16;
+
+        unsafe {
+            write_volatile(self.avail_ptr(2 + (self.avail_idx % self.size) as usize), head);
+        }
+
+        // the ring entry must be visible before the index update
+        fence(Ordering::SeqCst);
+        self.avail_idx = self.avail_idx.wrapping_add(1);
+        unsafe {
+            write_volatile(self.avail_ptr(1), self.avail_idx);
+        }
+        fence(Ordering::SeqCst);
+
+        Some(head)
+    }
+
+    /// Whether the device has returned any chain not yet popped.
+    #[inline]
+    pub fn can_pop(&self) -> bool {
+        self.used_idx() != self.last_used_idx
+    }
+
+    /// Takes a used chain from the device and returns its descriptors
+    /// to the free list, returns the head index and the written length.
+    pub fn pop(&mut self) -> Option<(u16, u32)> {
+        if !self.can_pop() {
+            return None;
+        }
+
+        fence(Ordering::SeqCst);
+
+        let elem = self.used_elem(self.last_used_idx);
+        self.last_used_idx = self.last_used_idx.wrapping_add(1);
+
+        let head = elem.id as u16;
+        let mut idx = head;
+
+        loop {
+            let desc = self.desc(idx);
+            self.num_free += 1;
+
+            if DescFlags::from_bits_truncate(desc.flags).contains(DescFlags::NEXT) {
+                idx = desc.next;
+            } else {
+                let mut desc = desc;
+                desc.next = self.free_head;
+                self.set_desc(idx, desc);
+                break;
+            }
+        }
+
+        self.free_head = head;
+
+        Some((head, elem.len))
+    }
+}
@@ -3,9 +3,6 @@
 /// [Intel Doc](http://www.intel.com/design/chipsets/datashts/29056601.pdf)
 use bit_field::BitField;
 
-/// Default physical address of IO APIC
-pub const IOAPIC_ADDR: u64 = 0xFEC00000;
-
 bitflags! {
     /// The redirection table starts at REG_TABLE and uses
     /// two registers to configure each interrupt.
@@ -62,8 +59,12 @@ impl IoApic {
     }
 
     fn write_irq(&mut self, irq: u8, flags: RedirectionEntry, dest: u8) {
-        self.write(0x10 + 2 * irq, (32 + irq) as u32 | flags.bits());
-        self.write(0x10 + 2 * irq + 1, (dest as u32) << 24);
+        self.write_entry(irq, 32 + irq, flags, dest);
+    }
+
+    fn write_entry(&mut self, pin: u8, vector: u8, flags: RedirectionEntry, dest: u8) {
+        self.write(0x10 + 2 * pin, vector as u32 | flags.bits());
+        self.write(0x10 + 2 * pin + 1, (dest as u32) << 24);
     }
 
     pub fn enable(&mut self, irq: u8, cpuid: u8) {
@@ -74,6 +75,16 @@ impl IoApic {
         trace!("Enable IOApic: IRQ={}, CPU={}", irq, cpuid);
     }
 
+    /// Routes the input `pin` to `vector` on the given cpuid,
+    /// with the polarity and trigger mode from the ACPI interrupt source overrides.
+    pub fn route(&mut self, pin: u8, vector: u8, active_low: bool, level: bool, cpuid: u8) {
+        let mut flags = RedirectionEntry::NONE;
+        flags.set(RedirectionEntry::ACTIVELOW, active_low);
+        flags.set(RedirectionEntry::LEVEL, level);
+        self.write_entry(pin, vector, flags, cpuid);
+        trace!("Route IOApic: PIN={}, VECTOR={}, CPU={}", pin, vector, cpuid);
+    }
+
     pub fn disable(&mut self, irq: u8, cpuid: u8) {
         self.write_irq(irq, RedirectionEntry::DISABLED, cpuid);
     }
@@ -8,12 +8,21 @@
 //!
 //! Reference: [OSDev Wiki](https://wiki.osdev.org/APIC)
 
-pub use ioapic::{IoApic, IOAPIC_ADDR};
-pub use xapic::{XApic, LAPIC_ADDR};
+pub use ioapic::IoApic;
+pub use xapic::XApic;
 
 mod ioapic;
 mod xapic;
 
+/// Operating modes of the local APIC timer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimerMode {
+    /// Fires once when the count reaches zero
+    OneShot,
+    /// Reloads the initial count and fires repeatedly
+    Periodic,
+}
+
 pub trait LocalApic {
     /// If this type APIC is supported
     fn support() -> bool;
@@ -1,12 +1,27 @@
 use crate::interrupt::consts::{Interrupts, Irq};
-use super::LocalApic;
+use super::{LocalApic, TimerMode};
 use bit_field::BitField;
 use core::fmt::{Debug, Error, Formatter};
 use core::ptr::{read_volatile, write_volatile};
 use x86::cpuid::CpuId;
 
-/// Default physical address of xAPIC
-pub const LAPIC_ADDR: u64 = 0xFEE00000;
+/// Timer registers
+const TIMER_LVT: u32 = 0x320;
+const TIMER_INITIAL_COUNT: u32 = 0x380;
+const TIMER_CURRENT_COUNT: u32 = 0x390;
+const TIMER_DIVIDE: u32 = 0x3E0;
+
+/// Divide the bus clock by 16, the timer frequency is calibrated at boot
+const TIMER_DIVIDE_BY_16: u32 = 0b0011;
+
+const LVT_MASKED: u32 = 1 << 16;
+const LVT_TIMER_PERIODIC: u32 = 1 << 17;
+
+/// Interrupt Command Register fields
+const ICR_DELIVERY_INIT: u64 = 0b101 << 8;
+const ICR_DELIVERY_STARTUP: u64 = 0b110 << 8;
+const ICR_LEVEL_ASSERT: u64 = 1 << 14;
+const ICR_DESTINATION_SHIFT: u64 = 56;
 
 pub struct XApic {
     addr: u64,
@@ -31,6 +46,55 @@ impl XApic {
     }
 }
 
+impl XApic {
+    /// Starts the timer counting down from `count`, raising the timer interrupt
+    /// every `count` cycles in periodic mode or once in one-shot mode.
+    pub fn set_timer(&mut self, mode: TimerMode, count: u32) {
+        unsafe {
+            let mut lvt_timer = self.read(TIMER_LVT);
+            lvt_timer &= !(LVT_MASKED | LVT_TIMER_PERIODIC);
+            if mode == TimerMode::Periodic {
+                lvt_timer |= LVT_TIMER_PERIODIC;
+            }
+            self.write(TIMER_LVT, lvt_timer);
+            // writing the initial count starts the timer
+            self.write(TIMER_INITIAL_COUNT, count.max(1));
+        }
+    }
+
+    /// Stops the timer without raising an interrupt.
+    pub fn stop_timer(&mut self) {
+        unsafe {
+            self.write(TIMER_INITIAL_COUNT, 0);
+            let lvt_timer = self.read(TIMER_LVT);
+            self.write(TIMER_LVT, lvt_timer | LVT_MASKED);
+        }
+    }
+
+    /// Current count of the timer, counts down to zero.
+    pub fn timer_count(&self) -> u32 {
+        unsafe { self.read(TIMER_CURRENT_COUNT) }
+    }
+
+    /// Sends a fixed interrupt with `vector` to the CPU with the given APIC ID.
+    pub fn send_ipi(&mut self, apic_id: u32, vector: u8) {
+        let dest = (apic_id as u64) << ICR_DESTINATION_SHIFT;
+        self.set_icr(dest | ICR_LEVEL_ASSERT | vector as u64);
+    }
+
+    /// Sends an INIT IPI, which resets the target CPU into the wait-for-SIPI state.
+    pub fn send_init(&mut self, apic_id: u32) {
+        let dest = (apic_id as u64) << ICR_DESTINATION_SHIFT;
+        self.set_icr(dest | ICR_DELIVERY_INIT | ICR_LEVEL_ASSERT);
+    }
+
+    /// Sends a startup IPI, the target CPU starts in real mode at `page * 0x1000`.
+    pub fn send_startup(&mut self, apic_id: u32, page: u8) {
+        let dest = (apic_id as u64) << ICR_DESTINATION_SHIFT;
+        self.set_icr(dest | ICR_DELIVERY_STARTUP | ICR_LEVEL_ASSERT | page as u64);
+    }
+}
+
 impl LocalApic for XApic {
     /// If this type APIC is supported
     fn support() -> bool {
@@ -52,16 +116,17 @@ impl LocalApic for XApic {
             self.write(0xF0, spiv);
 
             // FIXME: The timer repeatedly counts down at bus frequency
-            self.write(0x3E0, 0b1011); // set Timer Divide to 1
-            self.write(0x380, 0x20000); // set initial count to 0x20000
-            
-            let mut lvt_timer = self.read(0x320);
+            // the timer is left stopped, it is started by `time::init`
+            // after calibration with the configured frequency
+            self.write(TIMER_DIVIDE, TIMER_DIVIDE_BY_16);
+            self.write(TIMER_INITIAL_COUNT, 0);
+
+            let mut lvt_timer = self.read(TIMER_LVT);
             // clear and set Vector
             lvt_timer &= !(0xFF);
             lvt_timer |= Interrupts::IrqBase as u32 + Irq::Timer as u32;
-            lvt_timer &= !(1 << 16); // clear Mask
-            lvt_timer |= 1 << 17; // set Timer Periodic Mode
-            self.write(0x320, lvt_timer);
+            lvt_timer |= LVT_MASKED; // set Mask
+            self.write(TIMER_LVT, lvt_timer);
 
             // FIXME: Disable logical interrupt lines (LINT0, LINT1)
             self.write(0x350, 1 << 16); // set Mask(LTNT0)
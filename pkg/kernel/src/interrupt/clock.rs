@@ -4,6 +4,7 @@ use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame};
 
 use crate::memory::gdt;
 use crate::proc::*;
+use crate::time::timer;
 use super::consts::*;
 
 pub unsafe fn register_idt(idt: &mut InterruptDescriptorTable) {
@@ -16,12 +17,16 @@ pub extern "C" fn clock(mut context: ProcessContext) {
     // debug!("Timer interrupt triggered");
     
     // do something
+    inc_counter();
+    timer::run_expired();
     switch(&mut context);
+    handle_signals(&mut context);
     super::ack();
 }
 
 as_handler!(clock);
 
+/// Number of clock interrupts since boot, grows slower when idle
 static COUNTER: AtomicU64 = AtomicU64::new(0);
 
 #[inline]
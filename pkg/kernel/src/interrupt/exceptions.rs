@@ -1,4 +1,6 @@
 use crate::memory::*;
+use crate::proc::{user_fault, ProcessContext};
+use syscall_def::signal::*;
 use x86_64::registers::control::Cr2;
 use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame, PageFaultErrorCode};
 use x86_64::VirtAddr;
@@ -40,10 +42,17 @@ pub unsafe fn register_idt(idt: &mut InterruptDescriptorTable) {
     idt.general_protection_fault.set_handler_fn(general_protection_fault_handler);
 }
 
-pub extern "x86-interrupt" fn divide_error_handler(stack_frame: InterruptStackFrame) {
-    panic!("EXCEPTION: DIVIDE ERROR\n\n{:#?}", stack_frame);
+// 用户态的异常只终止出错的进程，内核态的异常仍然 panic
+
+pub extern "C" fn divide_error(mut context: ProcessContext) {
+    if context.is_user() {
+        return user_fault("DIVIDE ERROR", SIGFPE, None, &mut context);
+    }
+    panic!("EXCEPTION: DIVIDE ERROR\n\n{:#?}", context);
 }
 
+as_handler!(divide_error);
+
 pub extern "x86-interrupt" fn double_fault_handler(
     stack_frame: InterruptStackFrame,
     error_code: u64,
@@ -54,32 +63,39 @@ pub extern "x86-interrupt" fn double_fault_handler(
     );
 }
 
-pub extern "x86-interrupt" fn page_fault_handler(
-    stack_frame: InterruptStackFrame,
-    err_code: PageFaultErrorCode,
-) {
-    if !crate::proc::handle_page_fault(Cr2::read().unwrap(), err_code) {
-        warn!(
-            "EXCEPTION: PAGE FAULT, ERROR_CODE: {:?}\n\nTrying to access: {:#x}\n{:#?}",
-            err_code,
-            Cr2::read().unwrap(),
-            stack_frame
-        );
-        // FIXME: print info about which process causes page fault?
-        panic!("Cannot handle page fault!");
+pub extern "C" fn page_fault(mut context: ProcessContext, err_code: u64) {
+    let err_code = PageFaultErrorCode::from_bits_truncate(err_code);
+    let addr = Cr2::read().unwrap_or(VirtAddr::zero());
+
+    if crate::proc::handle_page_fault(addr, err_code) {
+        return;
+    }
+
+    if context.is_user() {
+        return user_fault("PAGE FAULT", SIGSEGV, Some(addr), &mut context);
     }
+
+    warn!(
+        "EXCEPTION: PAGE FAULT, ERROR_CODE: {:?}\n\nTrying to access: {:#x}\n{:#?}",
+        err_code, addr, context
+    );
+    panic!("Cannot handle page fault!");
 }
 
-pub extern "x86-interrupt" fn general_protection_fault_handler(
-    stack_frame: InterruptStackFrame,
-    err_code: u64,
-) {
+as_handler_with_error_code!(page_fault, PageFaultErrorCode);
+
+pub extern "C" fn general_protection_fault(mut context: ProcessContext, err_code: u64) {
+    if context.is_user() {
+        return user_fault("GENERAL PROTECTION FAULT", SIGSEGV, None, &mut context);
+    }
     panic!(
         "EXCEPTION: GENERAL PROTECTION FAULT, ERROR_CODE: 0x{:016x}\n\n{:#?}",
-        err_code, stack_frame
+        err_code, context
     );
 }
 
+as_handler_with_error_code!(general_protection_fault, u64);
+
 pub extern "x86-interrupt" fn debug_handler(stack_frame: InterruptStackFrame) {
     panic!("EXCEPTION: DEBUG\n\n{:#?}", stack_frame);
 }
@@ -100,26 +116,41 @@ pub extern "x86-interrupt" fn bound_range_exceeded_handler(stack_frame: Interrup
     panic!("EXCEPTION: BOUND RANGE EXCEEDED\n\n{:#?}", stack_frame);
 }
 
-pub extern "x86-interrupt" fn invalid_opcode_handler(stack_frame: InterruptStackFrame) {
-    panic!("EXCEPTION: INVALID OPCODE\n\n{:#?}", stack_frame);
+pub extern "C" fn invalid_opcode(mut context: ProcessContext) {
+    if context.is_user() {
+        return user_fault("INVALID OPCODE", SIGILL, None, &mut context);
+    }
+    panic!("EXCEPTION: INVALID OPCODE\n\n{:#?}", context);
 }
 
+as_handler!(invalid_opcode);
+
 pub extern "x86-interrupt" fn device_not_available_handler(stack_frame: InterruptStackFrame) {
     panic!("EXCEPTION: DEVICE NOT AVAILABLE\n\n{:#?}", stack_frame);
 }
 
-pub extern "x86-interrupt" fn x87_floating_point_handler(stack_frame: InterruptStackFrame) {
-    panic!("EXCEPTION: X87 FLOATING POINT\n\n{:#?}", stack_frame);
+pub extern "C" fn x87_floating_point(mut context: ProcessContext) {
+    if context.is_user() {
+        return user_fault("X87 FLOATING POINT", SIGFPE, None, &mut context);
+    }
+    panic!("EXCEPTION: X87 FLOATING POINT\n\n{:#?}", context);
 }
 
+as_handler!(x87_floating_point);
+
 pub extern "x86-interrupt" fn machine_check_handler(stack_frame: InterruptStackFrame) -> ! {
     panic!("EXCEPTION: MACHINE CHECK\n\n{:#?}", stack_frame);
 }
 
-pub extern "x86-interrupt" fn simd_floating_point_handler(stack_frame: InterruptStackFrame) {
-    panic!("EXCEPTION: SIMD FLOATING POINT\n\n{:#?}", stack_frame);
+pub extern "C" fn simd_floating_point(mut context: ProcessContext) {
+    if context.is_user() {
+        return user_fault("SIMD FLOATING POINT", SIGFPE, None, &mut context);
+    }
+    panic!("EXCEPTION: SIMD FLOATING POINT\n\n{:#?}", context);
 }
 
+as_handler!(simd_floating_point);
+
 pub extern "x86-interrupt" fn virtualization_handler(stack_frame: InterruptStackFrame) {
     panic!("EXCEPTION: VIRTUALIZATION\n\n{:#?}", stack_frame);
 }
@@ -154,16 +185,18 @@ pub extern "x86-interrupt" fn stack_segment_fault_handler(
     );
 }
 
-pub extern "x86-interrupt" fn alignment_check_handler(
-    stack_frame: InterruptStackFrame,
-    error_code: u64,
-) {
+pub extern "C" fn alignment_check(mut context: ProcessContext, error_code: u64) {
+    if context.is_user() {
+        return user_fault("ALIGNMENT CHECK", SIGBUS, None, &mut context);
+    }
     panic!(
         "EXCEPTION: ALIGNMENT CHECK, ERROR_CODE: 0x{:016x}\n\n{:#?}",
-        error_code, stack_frame
+        error_code, context
     );
 }
 
+as_handler_with_error_code!(alignment_check, u64);
+
 pub extern "x86-interrupt" fn cp_protection_exception_handler(
     stack_frame: InterruptStackFrame,
     error_code: u64,
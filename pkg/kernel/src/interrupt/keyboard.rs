@@ -0,0 +1,15 @@
+use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame};
+
+use crate::drivers::keyboard;
+
+use super::consts::*;
+
+pub unsafe fn register_idt(idt: &mut InterruptDescriptorTable) {
+    idt[Interrupts::IrqBase as u8 + Irq::Keyboard as u8]
+        .set_handler_fn(keyboard_handler);
+}
+
+pub extern "x86-interrupt" fn keyboard_handler(_st: InterruptStackFrame) {
+    keyboard::receive();
+    super::ack();
+}
@@ -2,11 +2,14 @@ mod apic;
 mod consts;
 pub mod clock;
 mod serial;
+mod keyboard;
 mod exceptions;
 pub mod syscall;
 
 use apic::*;
+pub use apic::TimerMode;
 use x86_64::structures::idt::InterruptDescriptorTable;
+use crate::drivers::acpi;
 use crate::memory::physical_to_virtual;
 
 lazy_static! {
@@ -16,6 +19,7 @@ lazy_static! {
             exceptions::register_idt(&mut idt);
             clock::register_idt(&mut idt);
             serial::register_idt(&mut idt);
+            keyboard::register_idt(&mut idt);
             syscall::register_idt(&mut idt);
         }
         idt
@@ -29,7 +33,7 @@ pub fn init() {
     // FIXME: check and init APIC
     if XApic::support() {
         unsafe {
-            let mut xapic = XApic::new(physical_to_virtual(LAPIC_ADDR));
+            let mut xapic = XApic::new(physical_to_virtual(acpi::lapic_addr()));
             xapic.cpu_init();
         }
     } else {
@@ -37,17 +41,95 @@ pub fn init() {
     }
     // FIXME: enable serial irq with IO APIC (use enable_irq)
     enable_irq(consts::Irq::Serial0 as u8 , 0);
+    enable_irq(consts::Irq::Keyboard as u8, 0);
     info!("Interrupts Initialized.");
 }
 
-#[inline(always)]
+/// init interrupts for an application processor, the IDT is shared
+pub fn init_ap() {
+    IDT.load();
+
+    unsafe {
+        let mut xapic = XApic::new(physical_to_virtual(acpi::lapic_addr()));
+        xapic.cpu_init();
+    }
+}
+
+/// Enable an ISA IRQ on the I/O APIC that handles its global system interrupt.
 pub fn enable_irq(irq: u8, cpuid: u8) {
-    let mut ioapic = unsafe { IoApic::new(physical_to_virtual(IOAPIC_ADDR)) };
-    ioapic.enable(irq, cpuid);
+    let route = acpi::isa_irq(irq);
+
+    // the I/O APIC with the closest base below the GSI
+    let Some(info) = acpi::ioapics()
+        .iter()
+        .filter(|info| info.gsi_base <= route.gsi)
+        .max_by_key(|info| info.gsi_base)
+    else {
+        warn!("No IOAPIC found for IRQ {} (GSI {})", irq, route.gsi);
+        return;
+    };
+
+    let mut ioapic = unsafe { IoApic::new(physical_to_virtual(info.addr)) };
+    let pin = route.gsi - info.gsi_base;
+
+    if pin > ioapic.maxintr() as u32 {
+        warn!("GSI {} is out of range of IOAPIC {}", route.gsi, info.id);
+        return;
+    }
+
+    ioapic.route(
+        pin as u8,
+        consts::Interrupts::IrqBase as u8 + irq,
+        route.active_low,
+        route.level_triggered,
+        cpuid,
+    );
 }
 
 #[inline(always)]
 pub fn ack() {
-    let mut lapic = unsafe { XApic::new(physical_to_virtual(LAPIC_ADDR)) };
-    lapic.eoi();
+    local_apic().eoi();
+}
+
+#[inline(always)]
+fn local_apic() -> XApic {
+    unsafe { XApic::new(physical_to_virtual(acpi::lapic_addr())) }
+}
+
+/// Program the local APIC timer of the current CPU.
+#[inline(always)]
+pub fn set_timer(mode: TimerMode, count: u32) {
+    local_apic().set_timer(mode, count);
+}
+
+#[inline(always)]
+pub fn stop_timer() {
+    local_apic().stop_timer();
+}
+
+#[inline(always)]
+pub fn timer_count() -> u32 {
+    local_apic().timer_count()
+}
+
+/// APIC ID of the current CPU
+#[inline(always)]
+pub fn cpu_id() -> u32 {
+    local_apic().id()
+}
+
+#[inline(always)]
+pub fn send_init(apic_id: u32) {
+    local_apic().send_init(apic_id);
+}
+
+#[inline(always)]
+pub fn send_startup(apic_id: u32, page: u8) {
+    local_apic().send_startup(apic_id, page);
+}
+
+/// Makes another CPU run the scheduler by raising its timer interrupt.
+#[inline(always)]
+pub fn reschedule(apic_id: u32) {
+    local_apic().send_ipi(apic_id, consts::Interrupts::IrqBase as u8 + consts::Irq::Timer as u8);
 }
@@ -1,6 +1,6 @@
 use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame};
 
-use crate::serial::get_serial_for_sure;
+use crate::serial::get_serial_blocking;
 use crate::drivers::input::push_key;
 
 use super::consts::*;
@@ -19,7 +19,7 @@ pub extern "x86-interrupt" fn serial_handler(_st: InterruptStackFrame) {
 /// Should be called on every interrupt
 fn receive() {
     // FIXME: receive character from uart 16550, put it into INPUT_BUFFER
-    let mut serial = get_serial_for_sure(); // 获取串口
+    let mut serial = get_serial_blocking(); // 获取串口
     let c = serial.receive(); // 接收字符
     drop(serial);
     if let Some(c) = c {
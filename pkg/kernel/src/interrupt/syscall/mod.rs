@@ -24,6 +24,7 @@ pub unsafe fn register_idt(idt: &mut InterruptDescriptorTable) {
 pub extern "C" fn syscall(mut context: ProcessContext) {
     x86_64::instructions::interrupts::without_interrupts(|| {
         super::syscall::dispatcher(&mut context);
+        handle_signals(&mut context);
     });
 }
 
@@ -54,8 +55,9 @@ pub fn dispatcher(context: &mut ProcessContext) {
         // 后面是返回值
 
         // fd: arg0 as u8, buf: &[u8] (ptr: arg1 as *const u8, len: arg2)
+        //   -> length, 0 at the end of input, WOULD_BLOCK if O_NONBLOCK and nothing to read
         /* FIXME: read from fd & return length */
-        Syscall::Read => context.set_rax(sys_read(&args)), 
+        Syscall::Read => sys_read(&args, context),
 
         // fd: arg0 as u8, buf: &[u8] (ptr: arg1 as *const u8, len: arg2)
         /* FIXME: write to fd & return length */
@@ -76,13 +78,38 @@ pub fn dispatcher(context: &mut ProcessContext) {
         /* FIXME: exit process with retcode */
         Syscall::Exit => exit_process(&args, context),
 
-        // pid: arg0 as u16 -> status: isize
+        // pid: arg0 as u16, timeout: arg1 as ns or NO_TIMEOUT, options: arg2 (WUNTRACED)
+        //   -> status: isize, or EXITED, TIMED_OUT or STOPPED with a timeout or WUNTRACED
         /* FIXME: check if the process is running or get retcode */
         Syscall::WaitPid => sys_wait_pid(&args, context),
 
-        // op: u8, key: u32, val: usize -> ret: any
+        // pid: arg0 as u16, sig: arg1 as usize -> ret: 0 or -1
+        Syscall::Kill => context.set_rax(sys_kill(&args)),
+
+        // sig: arg0 as usize, handler: arg1 (SIG_DFL, SIG_IGN or address), restorer: arg2 -> old handler or -1
+        Syscall::Sigaction => context.set_rax(sys_sigaction(&args)),
+
+        // how: arg0 as usize, set: arg1 as u64 -> old mask or -1
+        Syscall::Sigprocmask => context.set_rax(sys_sigprocmask(&args)),
+
+        // None, called by the restorer when a signal handler returns
+        Syscall::Sigreturn => sys_sigreturn(context),
+
+        // fd: arg0 as u8, cmd: arg1 (F_GETFL or F_SETFL), flags: arg2 -> flags or 0 or -1
+        Syscall::Fcntl => context.set_rax(sys_fcntl(&args)),
+
+        // fd: arg0 as u8, request: arg1 as usize, arg: arg2 -> ret: 0 or -1
+        Syscall::Ioctl => context.set_rax(sys_ioctl(&args)),
+
+        // op: u8, key: u32, val: usize (timeout in ns for timed wait) -> ret: any
         Syscall::Sem => sys_sem(&args, context),
 
+        // ms: arg0 as u64 -> 0
+        Syscall::Sleep => sys_sleep(&args, context),
+
+        // req: arg0 as *const [u64; 2] -> ret: 0 or -1
+        Syscall::Nanosleep => sys_nanosleep(&args, context),
+
         // None
         // { /* FIXME: list processes */ },
         Syscall::Stat => list_process(),
@@ -98,14 +125,39 @@ pub fn dispatcher(context: &mut ProcessContext) {
 
         Syscall::Brk => context.set_rax(sys_brk(&args)),
 
-        // ----------------------------------------------------
-        // NOTE: following syscall examples are implemented
-        // ----------------------------------------------------
+        // args: arg0 as *const MmapArgs -> addr or MAP_FAILED
+        Syscall::Mmap => context.set_rax(sys_mmap(&args)),
+
+        // addr: arg0 (page aligned), len: arg1 -> ret: 0 or -1
+        Syscall::Munmap => context.set_rax(sys_munmap(&args)),
+
+        // addr: arg0 (page aligned), len: arg1, prot: arg2 -> ret: 0 or -1
+        Syscall::Mprotect => context.set_rax(sys_mprotect(&args)),
+
+        // pid: arg0 as u16 (0 for current) -> 20 - nice or -1
+        Syscall::GetPriority => context.set_rax(sys_get_priority(&args)),
+
+        // pid: arg0 as u16 (0 for current), nice: arg1 as isize -> ret: 0 or -1
+        Syscall::SetPriority => context.set_rax(sys_set_priority(&args)),
+
+        // None -> seconds since the Unix epoch
+        Syscall::Time => context.set_rax(sys_time()),
+
+        // clock: arg0 as usize, ts: arg1 as *mut [u64; 2] -> ret: 0 or -1
+        Syscall::ClockGetTime => context.set_rax(sys_clock_get_time(&args)),
+
+        // pid: arg0 as u16 (0 for current), mask: arg1 as u64 -> ret: 0 or -1
+        Syscall::SetAffinity => context.set_rax(sys_set_affinity(&args)),
+
+        // pid: arg0 as u16 (0 for current), mask: arg1 as *mut u64 -> ret: 0 or -1
+        Syscall::GetAffinity => context.set_rax(sys_get_affinity(&args)),
+
+        // resource: arg0 as usize, limit: arg1 as *mut u64 -> ret: 0 or -1
+        Syscall::GetRlimit => context.set_rax(sys_get_rlimit(&args)),
+
+        // resource: arg0 as usize, limit: arg1 as u64 (only lowered) -> ret: 0 or -1
+        Syscall::SetRlimit => context.set_rax(sys_set_rlimit(&args)),
 
-        // layout: arg0 as *const Layout -> ptr: *mut u8
-        Syscall::Allocate => context.set_rax(sys_allocate(&args)),
-        // ptr: arg0 as *mut u8
-        Syscall::Deallocate => sys_deallocate(&args),
         // Unknown
         Syscall::Unknown => warn!("Unhandled syscall: {:x?}", context.regs.rax),
     }
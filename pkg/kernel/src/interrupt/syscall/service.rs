@@ -1,13 +1,18 @@
-use core::alloc::Layout;
+use core::time::Duration;
 
 use x86_64::VirtAddr;
 
 use crate::proc;
 use crate::proc::manager::get_process_manager;
 use crate::proc::*;
+use crate::proc::signal::SigAction;
 use crate::utils::*;
 
 use super::SyscallArgs;
+use syscall_def::mman::{MmapArgs, MAP_FAILED};
+use syscall_def::signal::{SIG_DFL, SIG_IGN};
+use syscall_def::tty::{TCGETS, TCSETS, TIOCGPGRP, TIOCSPGRP};
+use syscall_def::{F_GETFL, F_SETFL, NO_TIMEOUT, O_NONBLOCK, WUNTRACED};
 
 pub fn spawn_process(args: &SyscallArgs) -> usize {
     // FIXME: get app name by args
@@ -47,15 +52,14 @@ pub fn sys_write(args: &SyscallArgs) -> usize {
     // 0
 }
 
-pub fn sys_read(args: &SyscallArgs) -> usize {
+pub fn sys_read(args: &SyscallArgs, context: &mut ProcessContext) {
     // FIXME: just like sys_write
     let fd = args.arg0 as u8;
     let buf = unsafe {
         core::slice::from_raw_parts_mut(args.arg1 as *mut u8, args.arg2)
     };
 
-    let read = proc::read(fd, buf);
-    read as usize
+    proc::read(fd, buf, context);
 }
 
 pub fn get_pid() -> u16 {
@@ -66,9 +70,18 @@ pub fn sys_fork(context: &mut ProcessContext) {
     proc::fork(context);
 }
 
+/// Timeouts are passed in nanoseconds, `NO_TIMEOUT` waits forever.
+fn timeout(arg: usize) -> Option<Duration> {
+    match arg {
+        NO_TIMEOUT => None,
+        ns => Some(Duration::from_nanos(ns as u64)),
+    }
+}
+
 pub fn sys_wait_pid(args: &SyscallArgs, context: &mut ProcessContext) {
     let pid = ProcessId(args.arg0 as u16);
-    proc::wait_pid(pid, context);
+    let untraced = args.arg2 & WUNTRACED != 0;
+    proc::wait_pid(pid, timeout(args.arg1), untraced, context);
 }
 
 pub fn sys_sem(args: &SyscallArgs, context: &mut ProcessContext) {
@@ -76,52 +89,41 @@ pub fn sys_sem(args: &SyscallArgs, context: &mut ProcessContext) {
         0 => context.set_rax(new_sem(args.arg1 as u32, args.arg2)),
         1 => context.set_rax(remove_sem(args.arg1 as u32)),
         2 => sem_signal(args.arg1 as u32, context),
-        3 => sem_wait(args.arg1 as u32, context),
+        3 => sem_wait(args.arg1 as u32, None, context),
+        4 => sem_wait(args.arg1 as u32, timeout(args.arg2), context),
         _ => context.set_rax(usize::MAX),
     }
 }
 
-pub fn exit_process(args: &SyscallArgs, context: &mut ProcessContext) {
-    // FIXME: exit process with retcode
-    proc::exit(args.arg0 as isize, context)
+pub fn sys_sleep(args: &SyscallArgs, context: &mut ProcessContext) {
+    proc::sleep(Duration::from_millis(args.arg0 as u64), context);
 }
 
-pub fn list_process() {
-    // FIXME: list all processes
-    proc::print_process_list();
-}
-
-pub fn sys_allocate(args: &SyscallArgs) -> usize {
-    let layout = unsafe { (args.arg0 as *const Layout).as_ref().unwrap() };
-
-    if layout.size() == 0 {
-        return 0;
-    }
-
-    let ret = crate::memory::user::USER_ALLOCATOR
-        .lock()
-        .allocate_first_fit(*layout);
-
-    match ret {
-        Ok(ptr) => ptr.as_ptr() as usize,
-        Err(_) => 0,
+pub fn sys_nanosleep(args: &SyscallArgs, context: &mut ProcessContext) {
+    // [seconds, nanoseconds]
+    if !proc::check_user_range(args.arg0, size_of::<[u64; 2]>(), false) {
+        context.set_rax(usize::MAX);
+        return;
     }
-}
 
-pub fn sys_deallocate(args: &SyscallArgs) {
-    let layout = unsafe { (args.arg1 as *const Layout).as_ref().unwrap() };
+    let [secs, nanos] = unsafe { *(args.arg0 as *const [u64; 2]) };
 
-    if args.arg0 == 0 || layout.size() == 0 {
+    if nanos >= crate::time::NANOS_PER_SEC {
+        context.set_rax(usize::MAX);
         return;
     }
 
-    let ptr = args.arg0 as *mut u8;
+    proc::sleep(Duration::new(secs, nanos as u32), context);
+}
 
-    unsafe {
-        crate::memory::user::USER_ALLOCATOR
-            .lock()
-            .deallocate(core::ptr::NonNull::new_unchecked(ptr), *layout);
-    }
+pub fn exit_process(args: &SyscallArgs, context: &mut ProcessContext) {
+    // FIXME: exit process with retcode
+    proc::exit(args.arg0 as isize, context)
+}
+
+pub fn list_process() {
+    // FIXME: list all processes
+    proc::print_process_list();
 }
 
 pub fn list_dir(args: &SyscallArgs) {
@@ -169,4 +171,190 @@ pub fn sys_brk(args: &SyscallArgs) -> usize {
         Some(new_heap_end) => new_heap_end.as_u64() as usize,
         None => !0,
     }
-}
\ No newline at end of file
+}
+
+pub fn sys_mmap(args: &SyscallArgs) -> usize {
+    if !proc::check_user_range(args.arg0, size_of::<MmapArgs>(), false) {
+        return MAP_FAILED;
+    }
+
+    let mmap = unsafe { *(args.arg0 as *const MmapArgs) };
+
+    match proc::mmap(&mmap) {
+        Some(addr) => addr.as_u64() as usize,
+        None => MAP_FAILED,
+    }
+}
+
+pub fn sys_munmap(args: &SyscallArgs) -> usize {
+    match VirtAddr::try_new(args.arg0 as u64) {
+        Ok(addr) if proc::munmap(addr, args.arg1 as u64) => 0,
+        _ => usize::MAX,
+    }
+}
+
+pub fn sys_mprotect(args: &SyscallArgs) -> usize {
+    match VirtAddr::try_new(args.arg0 as u64) {
+        Ok(addr) if proc::mprotect(addr, args.arg1 as u64, args.arg2) => 0,
+        _ => usize::MAX,
+    }
+}
+
+pub fn sys_time() -> usize {
+    crate::time::realtime().as_secs() as usize
+}
+
+pub fn sys_clock_get_time(args: &SyscallArgs) -> usize {
+    let time = match args.arg0 {
+        0 => crate::time::realtime(),  // CLOCK_REALTIME
+        1 => crate::time::monotonic(), // CLOCK_MONOTONIC
+        _ => return usize::MAX,
+    };
+
+    // [seconds, nanoseconds]
+    if !proc::check_user_range(args.arg1, size_of::<[u64; 2]>(), true) {
+        return usize::MAX;
+    }
+
+    let ts = unsafe { &mut *(args.arg1 as *mut [u64; 2]) };
+    *ts = [time.as_secs(), time.subsec_nanos() as u64];
+    0
+}
+
+/// pid 0 is the current process
+fn target_pid(arg: usize) -> ProcessId {
+    match arg as u16 {
+        0 => ProcessId(get_pid()),
+        pid => ProcessId(pid),
+    }
+}
+
+pub fn sys_set_priority(args: &SyscallArgs) -> usize {
+    let nice = (args.arg1 as isize).clamp(i8::MIN as isize, i8::MAX as isize) as i8;
+    if proc::set_nice(target_pid(args.arg0), nice) {
+        0
+    } else {
+        usize::MAX
+    }
+}
+
+/// Returns `20 - nice` as the Linux syscall does, so it is never negative.
+pub fn sys_get_priority(args: &SyscallArgs) -> usize {
+    match proc::get_nice(target_pid(args.arg0)) {
+        Some(nice) => (20 - nice as isize) as usize,
+        None => usize::MAX,
+    }
+}
+
+pub fn sys_set_affinity(args: &SyscallArgs) -> usize {
+    if proc::set_affinity(target_pid(args.arg0), args.arg1 as u64) {
+        0
+    } else {
+        usize::MAX
+    }
+}
+
+pub fn sys_get_affinity(args: &SyscallArgs) -> usize {
+    let Some(mask) = proc::get_affinity(target_pid(args.arg0)) else {
+        return usize::MAX;
+    };
+
+    if !proc::check_user_range(args.arg1, size_of::<u64>(), true) {
+        return usize::MAX;
+    }
+
+    unsafe { *(args.arg1 as *mut u64) = mask };
+    0
+}
+
+pub fn sys_get_rlimit(args: &SyscallArgs) -> usize {
+    let Some(limit) = proc::get_rlimit(args.arg0) else {
+        return usize::MAX;
+    };
+
+    if !proc::check_user_range(args.arg1, size_of::<u64>(), true) {
+        return usize::MAX;
+    }
+
+    unsafe { *(args.arg1 as *mut u64) = limit };
+    0
+}
+
+pub fn sys_set_rlimit(args: &SyscallArgs) -> usize {
+    if proc::set_rlimit(args.arg0, args.arg1 as u64) {
+        0
+    } else {
+        usize::MAX
+    }
+}
+
+pub fn sys_kill(args: &SyscallArgs) -> usize {
+    if proc::send_signal(ProcessId(args.arg0 as u16), args.arg1) {
+        0
+    } else {
+        usize::MAX
+    }
+}
+
+pub fn sys_sigaction(args: &SyscallArgs) -> usize {
+    let action = match args.arg1 {
+        SIG_DFL => SigAction::Default,
+        SIG_IGN => SigAction::Ignore,
+        handler => SigAction::Handler {
+            handler: VirtAddr::new_truncate(handler as u64),
+            restorer: VirtAddr::new_truncate(args.arg2 as u64),
+        },
+    };
+
+    match proc::sigaction(args.arg0, action) {
+        Some(old) => old.handler(),
+        None => usize::MAX,
+    }
+}
+
+pub fn sys_sigprocmask(args: &SyscallArgs) -> usize {
+    match proc::sigprocmask(args.arg0, args.arg1 as u64) {
+        Some(old) => old as usize,
+        None => usize::MAX,
+    }
+}
+
+pub fn sys_sigreturn(context: &mut ProcessContext) {
+    proc::sigreturn(context);
+}
+
+/// Only the console takes requests, see `syscall_def::tty`
+pub fn sys_ioctl(args: &SyscallArgs) -> usize {
+    use crate::drivers::tty;
+
+    if !proc::is_console(args.arg0 as u8) {
+        return usize::MAX;
+    }
+
+    match args.arg1 {
+        TCGETS if proc::check_user_range(args.arg2, size_of::<u64>(), true) => unsafe {
+            *(args.arg2 as *mut u64) = tty::mode()
+        },
+        TCSETS => tty::set_mode(args.arg2 as u64),
+        TIOCGPGRP if proc::check_user_range(args.arg2, size_of::<u16>(), true) => unsafe {
+            *(args.arg2 as *mut u16) = tty::foreground().map_or(0, |pid| pid.0)
+        },
+        TIOCSPGRP => tty::set_foreground(match args.arg2 as u16 {
+            0 => None,
+            pid => Some(ProcessId(pid)),
+        }),
+        _ => return usize::MAX,
+    }
+
+    0
+}
+
+/// Only `O_NONBLOCK` can be set
+pub fn sys_fcntl(args: &SyscallArgs) -> usize {
+    let fd = args.arg0 as u8;
+    match args.arg1 {
+        F_GETFL => proc::get_fd_flags(fd).unwrap_or(usize::MAX),
+        F_SETFL if proc::set_fd_flags(fd, args.arg2 & O_NONBLOCK) => 0,
+        _ => usize::MAX,
+    }
+}
@@ -28,6 +28,8 @@ pub use drivers::*;
 pub mod memory;
 pub mod interrupt;
 pub mod proc;
+pub mod time;
+pub mod smp;
 
 pub use alloc::format;
 
@@ -43,13 +45,17 @@ pub fn init(boot_info: &'static BootInfo) {
     serial::init(); // init serial output
     logger::init(); // init logger system
     memory::address::init(boot_info);
+    display::init(boot_info); // init framebuffer console
     memory::gdt::init(); // init gdt
     memory::allocator::init(); // init kernel heap allocator
+    acpi::init(boot_info); // parse acpi tables
     interrupt::init(); // init interrupts
+    keyboard::init(); // init ps/2 keyboard
+    time::init(boot_info); // calibrate timers and init clocks
     memory::init(boot_info); // init memory manager
-    memory::user::init(); // init user heap allocator
     proc::init(boot_info); // init process manager
     filesystem::init(); // init filesystem
+    smp::init(boot_info); // start application processors
     x86_64::instructions::interrupts::enable();
     info!("Interrupts Enabled.");
 
@@ -64,13 +70,23 @@ pub fn init(boot_info: &'static BootInfo) {
 
 pub fn shutdown() -> ! {
     info!("YatSenOS shutting down.");
+    acpi::shutdown();
+    // fallback to uefi runtime services
     uefi::runtime::reset(ResetType::SHUTDOWN, Status::SUCCESS, None);
 }
 
+pub fn reboot() -> ! {
+    info!("YatSenOS rebooting.");
+    acpi::reset();
+    uefi::runtime::reset(ResetType::COLD, Status::SUCCESS, None);
+}
+
 pub fn wait(init: proc::ProcessId) {
     loop {
         if proc::still_alive(init) {
             // Why? Check reflection question 5
+            // the tick is stopped while idle, so this sleeps until
+            // the next timer deadline or device interrupt
             x86_64::instructions::hlt();
         } else {
             break;
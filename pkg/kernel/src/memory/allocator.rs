@@ -1,12 +1,34 @@
-use linked_list_allocator::LockedHeap;
+use super::slab::{SlabAllocator, SlabStats, SLAB_SIZES};
+use super::{get_frame_alloc_blocking, physical_to_virtual, PAGE_SIZE};
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr::{addr_of_mut, null_mut, NonNull};
+use linked_list_allocator::Heap;
+use spin::Mutex;
 use x86_64::VirtAddr;
-use core::ptr::addr_of_mut;
 
 pub const HEAP_SIZE: usize = 8 * 1024 * 1024; // 8 MiB
 
-/// Use linked_list_allocator for kernel heap
+/// Regions the kernel heap can grow by
+const MAX_HEAPS: usize = 16;
+/// The heap grows by at least 2^GROW_ORDER frames, 1 MiB
+const GROW_ORDER: usize = 8;
+
+/// Small objects come from the slab caches,
+/// the rest from the kernel heap, which grows on demand.
 #[global_allocator]
-pub static ALLOCATOR: LockedHeap = LockedHeap::empty();
+pub static ALLOCATOR: KernelAllocator = KernelAllocator::new();
+
+pub struct KernelAllocator {
+    slab: SlabAllocator,
+    heap: Mutex<KernelHeap>,
+}
+
+/// The initial heap in the bss section, and the regions
+/// of frames added when it runs out.
+struct KernelHeap {
+    heaps: [Heap; MAX_HEAPS],
+    count: usize,
+}
 
 pub fn init() {
     // static buffer for kernel heap
@@ -17,7 +39,10 @@ pub fn init() {
     let heap_end = heap_start + HEAP_SIZE as u64;
 
     unsafe {
-        ALLOCATOR.lock().init(addr_of_mut!(HEAP) as *mut u8, HEAP_SIZE);
+        ALLOCATOR
+            .heap
+            .lock()
+            .add(addr_of_mut!(HEAP) as *mut u8, HEAP_SIZE);
     }
 
     debug!(
@@ -32,6 +57,121 @@ pub fn init() {
     info!("Kernel Heap Initialized.");
 }
 
+impl KernelAllocator {
+    const fn new() -> Self {
+        Self {
+            slab: SlabAllocator::new(),
+            heap: Mutex::new(KernelHeap::new()),
+        }
+    }
+
+    /// Used and total bytes of the kernel heap
+    pub fn heap_usage(&self) -> (usize, usize) {
+        let heap = self.heap.lock();
+        (heap.used(), heap.size())
+    }
+
+    pub fn slab_stats(&self) -> [SlabStats; SLAB_SIZES.len()] {
+        self.slab.stats()
+    }
+}
+
+unsafe impl GlobalAlloc for KernelAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        // the heap is used if the cache can not get a frame
+        if let Some(ptr) = SlabAllocator::class(&layout).and_then(|class| self.slab.allocate(class))
+        {
+            return ptr.as_ptr();
+        }
+
+        self.heap
+            .lock()
+            .allocate(layout)
+            .map_or(null_mut(), NonNull::as_ptr)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let ptr = NonNull::new(ptr).expect("Deallocating a null pointer");
+
+        let mut heap = self.heap.lock();
+        if heap.contains(ptr) {
+            unsafe { heap.deallocate(ptr, layout) };
+            return;
+        }
+        drop(heap);
+
+        let class = SlabAllocator::class(&layout).expect("Deallocating a pointer not allocated");
+        unsafe { self.slab.deallocate(class, ptr) };
+    }
+}
+
+impl KernelHeap {
+    const fn new() -> Self {
+        Self {
+            heaps: [const { Heap::empty() }; MAX_HEAPS],
+            count: 0,
+        }
+    }
+
+    unsafe fn add(&mut self, start: *mut u8, size: usize) {
+        unsafe { self.heaps[self.count].init(start, size) };
+        self.count += 1;
+    }
+
+    fn allocate(&mut self, layout: Layout) -> Option<NonNull<u8>> {
+        for heap in self.heaps[..self.count].iter_mut() {
+            if let Ok(ptr) = heap.allocate_first_fit(layout) {
+                return Some(ptr);
+            }
+        }
+
+        self.grow(layout)?;
+        self.heaps[self.count - 1].allocate_first_fit(layout).ok()
+    }
+
+    /// Add a region of frames large enough for `layout`. The frame allocator
+    /// never allocates from the heap while locked, so it can be waited for.
+    fn grow(&mut self, layout: Layout) -> Option<()> {
+        if self.count == MAX_HEAPS {
+            return None;
+        }
+
+        // a page more for the alignment and the bookkeeping of the heap
+        let pages = (layout.size() + layout.align()).div_ceil(PAGE_SIZE as usize) + 1;
+        let order = (pages.next_power_of_two().trailing_zeros() as usize).max(GROW_ORDER);
+        let frame = get_frame_alloc_blocking().allocate_frames(order)?;
+
+        let size = (PAGE_SIZE as usize) << order;
+        let start = physical_to_virtual(frame.start_address().as_u64()) as *mut u8;
+        // nothing is logged here, printing may allocate as well
+        unsafe { self.add(start, size) };
+        Some(())
+    }
+
+    unsafe fn deallocate(&mut self, ptr: NonNull<u8>, layout: Layout) {
+        let heap = self.heaps[..self.count]
+            .iter_mut()
+            .find(|heap| heap.bottom() <= ptr.as_ptr() && ptr.as_ptr() < heap.top())
+            .unwrap();
+
+        unsafe { heap.deallocate(ptr, layout) };
+    }
+
+    fn contains(&self, ptr: NonNull<u8>) -> bool {
+        self.heaps[..self.count]
+            .iter()
+            .any(|heap| heap.bottom() <= ptr.as_ptr() && ptr.as_ptr() < heap.top())
+    }
+
+    fn used(&self) -> usize {
+        self.heaps[..self.count].iter().map(Heap::used).sum()
+    }
+
+    fn size(&self) -> usize {
+        self.heaps[..self.count].iter().map(Heap::size).sum()
+    }
+}
+
 #[alloc_error_handler]
 fn alloc_error_handler(layout: alloc::alloc::Layout) -> ! {
     panic!("Allocation error: {:?}", layout);
@@ -0,0 +1,324 @@
+//! Buddy allocator of physical frames
+//!
+//! The usable memory of a zone is split into blocks of 2^order frames, a
+//! block is aligned to its size and its buddy is the other half of the block
+//! of the next order. A freed block is merged with its buddy while the buddy
+//! is free as a whole, so contiguous frames can be allocated again.
+//!
+//! The free lists are kept in the free frames themselves, and the state of
+//! every frame of a zone in two bitmaps and a count of its mappings, taken
+//! from the zone at init. No memory of the kernel heap is used, so the heap
+//! can grow from the frame allocator.
+
+use super::{physical_to_virtual, LOW_MEMORY_END, PAGE_SIZE};
+use boot::{MemoryMap, MemoryType};
+
+/// Blocks are at most 2^MAX_ORDER frames, 4 MiB
+pub const MAX_ORDER: usize = 10;
+pub const ORDERS: usize = MAX_ORDER + 1;
+
+/// End of the free lists, frame 0 is never in a zone
+const NIL: u64 = 0;
+
+/// Written at the start of a free block
+#[repr(C)]
+struct FreeBlock {
+    order: u64,
+    prev: u64,
+    next: u64,
+}
+
+fn block<'a>(pfn: u64) -> &'a mut FreeBlock {
+    unsafe { &mut *(physical_to_virtual(pfn * PAGE_SIZE) as *mut FreeBlock) }
+}
+
+pub struct Zone {
+    name: &'static str,
+    /// Frame numbers spanned by the usable regions, [start, end)
+    start: u64,
+    end: u64,
+    /// The first block of each order, `NIL` if there is none
+    free_lists: [u64; ORDERS],
+    free_blocks: [usize; ORDERS],
+    /// Frames managed by the zone
+    owned: &'static mut [u64],
+    /// Free frames, every frame of a free block is set
+    free: &'static mut [u64],
+    /// Mappings of each allocated frame besides the first one, after fork
+    shared: &'static mut [u32],
+    total: usize,
+    free_frames: usize,
+}
+
+impl Zone {
+    /// Build the zone of the usable memory in [low, high) of `memory_map`.
+    pub fn new(name: &'static str, low: u64, high: u64, memory_map: &MemoryMap) -> Self {
+        let mut zone = Self {
+            name,
+            start: 0,
+            end: 0,
+            free_lists: [NIL; ORDERS],
+            free_blocks: [0; ORDERS],
+            owned: &mut [],
+            free: &mut [],
+            shared: &mut [],
+            total: 0,
+            free_frames: 0,
+        };
+
+        // low memory is kept for the real mode AP trampoline
+        let low = low.max(LOW_MEMORY_END);
+        let regions = || {
+            memory_map
+                .iter()
+                .filter(|r| r.ty == MemoryType::CONVENTIONAL)
+                .filter_map(move |r| {
+                    let start = r.phys_start.max(low).div_ceil(PAGE_SIZE);
+                    let end = (r.phys_start + r.page_count * PAGE_SIZE).min(high) / PAGE_SIZE;
+                    (start < end).then_some((start, end))
+                })
+        };
+
+        let (Some(start), Some(end)) = (
+            regions().map(|(start, _)| start).min(),
+            regions().map(|(_, end)| end).max(),
+        ) else {
+            return zone;
+        };
+
+        // the bitmaps and the counts are taken from the start of a region large enough
+        let frames = (end - start) as usize;
+        let words = frames.div_ceil(64);
+        let meta_bytes = 2 * words * size_of::<u64>() + frames * size_of::<u32>();
+        let meta_frames = (meta_bytes as u64).div_ceil(PAGE_SIZE);
+        let Some((meta, _)) = regions().find(|(start, end)| end - start > meta_frames) else {
+            warn!("Zone {} is too fragmented to be used.", name);
+            return zone;
+        };
+
+        let (bitmaps, shared) = unsafe {
+            let ptr = physical_to_virtual(meta * PAGE_SIZE) as *mut u64;
+            core::ptr::write_bytes(ptr as *mut u8, 0, meta_bytes);
+            (
+                core::slice::from_raw_parts_mut(ptr, 2 * words),
+                core::slice::from_raw_parts_mut(ptr.add(2 * words) as *mut u32, frames),
+            )
+        };
+        let (owned, free) = bitmaps.split_at_mut(words);
+
+        zone.start = start;
+        zone.end = end;
+        zone.owned = owned;
+        zone.free = free;
+        zone.shared = shared;
+
+        for (start, end) in regions() {
+            let start = if start == meta { start + meta_frames } else { start };
+
+            for pfn in start..end {
+                zone.set(pfn, true, true);
+            }
+
+            zone.total += (end - start) as usize;
+            zone.free_range(start, end);
+        }
+
+        zone
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.total == 0
+    }
+
+    pub fn frames_total(&self) -> usize {
+        self.total
+    }
+
+    pub fn frames_free(&self) -> usize {
+        self.free_frames
+    }
+
+    /// Free blocks of each order
+    pub fn free_blocks(&self) -> &[usize; ORDERS] {
+        &self.free_blocks
+    }
+
+    pub fn contains(&self, pfn: u64) -> bool {
+        self.start <= pfn && pfn < self.end
+    }
+
+    /// Allocate a block of 2^order frames, returns its first frame number.
+    pub fn allocate(&mut self, order: usize) -> Option<u64> {
+        let found = (order..ORDERS).find(|&order| self.free_lists[order] != NIL)?;
+        let pfn = self.free_lists[found];
+        self.remove(pfn, found);
+
+        // the upper halves stay free, as smaller blocks
+        for order in (order..found).rev() {
+            self.push(pfn + (1 << order), order);
+        }
+
+        for pfn in pfn..pfn + (1 << order) {
+            self.set(pfn, false, false);
+        }
+
+        self.free_frames -= 1 << order;
+        Some(pfn)
+    }
+
+    /// Allocate `count` contiguous frames, the rest of the block is freed again.
+    pub fn allocate_contiguous(&mut self, count: usize) -> Option<u64> {
+        let order = count.next_power_of_two().trailing_zeros() as usize;
+        if count == 0 || order > MAX_ORDER {
+            return None;
+        }
+
+        let pfn = self.allocate(order)?;
+        self.free_range(pfn + count as u64, pfn + (1 << order));
+        Some(pfn)
+    }
+
+    /// Count one more mapping of the allocated frame `pfn`.
+    pub fn share(&mut self, pfn: u64) {
+        self.shared[(pfn - self.start) as usize] += 1;
+    }
+
+    /// Drop a mapping of the allocated frame `pfn`,
+    /// returns false if it is still mapped by others.
+    pub fn unshare(&mut self, pfn: u64) -> bool {
+        let shared = &mut self.shared[(pfn - self.start) as usize];
+        if *shared == 0 {
+            return true;
+        }
+
+        *shared -= 1;
+        false
+    }
+
+    /// How many times the allocated frame `pfn` is mapped
+    pub fn refs(&self, pfn: u64) -> usize {
+        self.shared[(pfn - self.start) as usize] as usize + 1
+    }
+
+    /// Free a block of 2^order frames and merge it with its buddies.
+    pub fn deallocate(&mut self, pfn: u64, order: usize) -> Result<(), &'static str> {
+        if order > MAX_ORDER || !pfn.is_multiple_of(1 << order) {
+            return Err("misaligned block");
+        }
+
+        let count = 1 << order;
+
+        if !self.contains(pfn)
+            || pfn + count > self.end
+            || (pfn..pfn + count).any(|pfn| !self.get(pfn, true))
+        {
+            return Err("not owned by the allocator");
+        }
+
+        if (pfn..pfn + count).any(|pfn| self.get(pfn, false)) {
+            return Err("double free");
+        }
+
+        for pfn in pfn..pfn + count {
+            self.set(pfn, false, true);
+        }
+        self.free_frames += count as usize;
+
+        let (mut pfn, mut order) = (pfn, order);
+        while order < MAX_ORDER {
+            let buddy = pfn ^ (1 << order);
+            if !self.is_free_block(buddy, order) {
+                break;
+            }
+
+            self.remove(buddy, order);
+            pfn = pfn.min(buddy);
+            order += 1;
+        }
+
+        self.push(pfn, order);
+        Ok(())
+    }
+
+    /// Free the owned frames [start, end) as the largest blocks possible.
+    fn free_range(&mut self, mut start: u64, end: u64) {
+        while start < end {
+            let order = (0..=MAX_ORDER)
+                .rev()
+                .find(|&order| start.is_multiple_of(1 << order) && start + (1 << order) <= end)
+                .unwrap();
+
+            self.deallocate(start, order)
+                .expect("Failed to free the frames of a zone");
+            start += 1 << order;
+        }
+    }
+
+    /// Whether a free block of `order` starts at `pfn`, asked for the buddy
+    /// of a block being freed. Only the start of a free block has its order
+    /// written in it, a free frame inside a larger block would contain the
+    /// block being freed as well, which is a double free caught before.
+    fn is_free_block(&self, pfn: u64, order: usize) -> bool {
+        self.contains(pfn)
+            && pfn + (1 << order) <= self.end
+            && self.get(pfn, false)
+            && block(pfn).order == order as u64
+    }
+
+    fn push(&mut self, pfn: u64, order: usize) {
+        let head = self.free_lists[order];
+
+        *block(pfn) = FreeBlock {
+            order: order as u64,
+            prev: NIL,
+            next: head,
+        };
+
+        if head != NIL {
+            block(head).prev = pfn;
+        }
+
+        self.free_lists[order] = pfn;
+        self.free_blocks[order] += 1;
+    }
+
+    fn remove(&mut self, pfn: u64, order: usize) {
+        let FreeBlock { prev, next, .. } = *block(pfn);
+
+        if prev != NIL {
+            block(prev).next = next;
+        } else {
+            self.free_lists[order] = next;
+        }
+
+        if next != NIL {
+            block(next).prev = prev;
+        }
+
+        // not the start of a free block anymore
+        block(pfn).order = u64::MAX;
+        self.free_blocks[order] -= 1;
+    }
+
+    #[inline]
+    fn get(&self, pfn: u64, owned: bool) -> bool {
+        let bitmap = if owned { &self.owned } else { &self.free };
+        let idx = (pfn - self.start) as usize;
+        bitmap[idx / 64] & (1 << (idx % 64)) != 0
+    }
+
+    #[inline]
+    fn set(&mut self, pfn: u64, owned: bool, value: bool) {
+        let bitmap = if owned { &mut self.owned } else { &mut self.free };
+        let idx = (pfn - self.start) as usize;
+        if value {
+            bitmap[idx / 64] |= 1 << (idx % 64);
+        } else {
+            bitmap[idx / 64] &= !(1 << (idx % 64));
+        }
+    }
+}
@@ -1,83 +1,192 @@
-use alloc::boxed::Box;
-use alloc::vec::Vec;
-use boot::{MemoryMap, MemoryType};
+use super::buddy::{Zone, MAX_ORDER, ORDERS};
+use super::PAGE_SIZE;
+use boot::MemoryMap;
 use x86_64::structures::paging::{FrameAllocator, FrameDeallocator, PhysFrame, Size4KiB};
 use x86_64::PhysAddr;
 
-once_mutex!(pub FRAME_ALLOCATOR: BootInfoFrameAllocator);
+once_mutex!(pub FRAME_ALLOCATOR: BuddyFrameAllocator);
 
 guard_access_fn! {
-    pub get_frame_alloc(FRAME_ALLOCATOR: BootInfoFrameAllocator)
+    pub get_frame_alloc(FRAME_ALLOCATOR: BuddyFrameAllocator)
 }
 
-type BootInfoFrameIter = Box<dyn Iterator<Item = PhysFrame> + Send>;
+/// Frames below 1 MiB are not handed out, see `smp`
+pub const LOW_MEMORY_END: u64 = 0x10_0000;
 
-/// A FrameAllocator that returns usable frames from the bootloader's memory map.
-pub struct BootInfoFrameAllocator {
-    size: usize,
-    used: usize,
-    frames: BootInfoFrameIter,
-    recycled: Vec<PhysFrame>,
+/// Zones by physical address, frames are allocated from the last one
+/// with free memory, so the low memory is kept for devices that need it.
+const ZONES: [(&str, u64, u64); 3] = [
+    ("DMA", 0, 0x100_0000),
+    ("DMA32", 0x100_0000, 0x1_0000_0000),
+    ("Normal", 0x1_0000_0000, u64::MAX),
+];
+
+#[derive(Debug, Clone, Copy)]
+pub struct ZoneStats {
+    pub name: &'static str,
+    pub frames_total: usize,
+    pub frames_free: usize,
+    /// Free blocks of each order
+    pub free_blocks: [usize; ORDERS],
+}
+
+/// A FrameAllocator of the usable frames in the bootloader's memory map,
+/// with a buddy allocator for each zone.
+///
+/// The kernel heap grows from it, so nothing is allocated from the heap
+/// while it is locked.
+pub struct BuddyFrameAllocator {
+    zones: [Zone; ZONES.len()],
 }
 
-impl BootInfoFrameAllocator {
+impl BuddyFrameAllocator {
     /// Create a FrameAllocator from the passed memory map.
     ///
     /// This function is unsafe because the caller must guarantee that the passed
     /// memory map is valid. The main requirement is that all frames that are marked
     /// as `USABLE` in it are really unused.
-    pub unsafe fn init(memory_map: &MemoryMap, size: usize) -> Self {
-        BootInfoFrameAllocator {
-            size,
-            frames: create_frame_iter(memory_map),
-            used: 0,
-            recycled: Vec::new(),
+    pub unsafe fn init(memory_map: &MemoryMap) -> Self {
+        BuddyFrameAllocator {
+            zones: ZONES.map(|(name, low, high)| Zone::new(name, low, high, memory_map)),
         }
     }
 
     pub fn frames_used(&self) -> usize {
-        self.used
+        self.frames_total() - self.frames_free()
     }
 
     pub fn frames_total(&self) -> usize {
-        self.size
+        self.zones.iter().map(Zone::frames_total).sum()
+    }
+
+    pub fn frames_free(&self) -> usize {
+        self.zones.iter().map(Zone::frames_free).sum()
     }
 
-    pub fn frames_recycled(&self) -> usize {
-        self.recycled.len()
+    /// The zones with usable memory
+    pub fn zones(&self) -> impl Iterator<Item = &Zone> {
+        self.zones.iter().filter(|zone| !zone.is_empty())
+    }
+
+    /// Copied out, so they can be printed without the allocator locked
+    pub fn zone_stats(&self) -> [ZoneStats; ZONES.len()] {
+        self.zones.each_ref().map(|zone| ZoneStats {
+            name: zone.name(),
+            frames_total: zone.frames_total(),
+            frames_free: zone.frames_free(),
+            free_blocks: *zone.free_blocks(),
+        })
+    }
+
+    /// Count one more mapping of `frame`, it is only recycled
+    /// after every mapping is deallocated.
+    pub fn share_frame(&mut self, frame: PhysFrame) {
+        let pfn = pfn_of(frame);
+        match self.zone_mut(pfn) {
+            Some(zone) => zone.share(pfn),
+            None => error!("Sharing frame {:#x}: not owned by the allocator", frame.start_address()),
+        }
+    }
+
+    /// How many times `frame` is mapped
+    pub fn frame_refs(&self, frame: PhysFrame) -> usize {
+        let pfn = pfn_of(frame);
+        self.zones
+            .iter()
+            .find(|zone| zone.contains(pfn))
+            .map_or(1, |zone| zone.refs(pfn))
+    }
+
+    fn zone_mut(&mut self, pfn: u64) -> Option<&mut Zone> {
+        self.zones.iter_mut().find(|zone| zone.contains(pfn))
+    }
+
+    /// Allocate 2^order physically contiguous frames, aligned to their size.
+    pub fn allocate_frames(&mut self, order: usize) -> Option<PhysFrame> {
+        if order > MAX_ORDER {
+            return None;
+        }
+
+        self.zones
+            .iter_mut()
+            .rev()
+            .find_map(|zone| zone.allocate(order))
+            .map(frame_of)
+    }
+
+    /// Free 2^order frames allocated by `allocate_frames`.
+    ///
+    /// # Safety
+    ///
+    /// The frames must not be used anymore.
+    pub unsafe fn deallocate_frames(&mut self, frame: PhysFrame, order: usize) {
+        let pfn = pfn_of(frame);
+
+        let ret = match self.zone_mut(pfn) {
+            Some(zone) => zone.deallocate(pfn, order),
+            None => Err("not owned by the allocator"),
+        };
+
+        if let Err(err) = ret {
+            bad_free(frame, order, err);
+        }
+    }
+
+    /// Allocate `count` physically contiguous frames, returns the first one.
+    ///
+    /// Used for DMA buffers shared with devices, the frames can be
+    /// deallocated one by one.
+    pub fn allocate_contiguous(&mut self, count: usize) -> Option<PhysFrame> {
+        self.zones
+            .iter_mut()
+            .rev()
+            .find_map(|zone| zone.allocate_contiguous(count))
+            .map(frame_of)
     }
 }
 
-unsafe impl FrameAllocator<Size4KiB> for BootInfoFrameAllocator {
+unsafe impl FrameAllocator<Size4KiB> for BuddyFrameAllocator {
     fn allocate_frame(&mut self) -> Option<PhysFrame> {
-        // try pop from recycled frames
-        if let Some(frame) = self.recycled.pop() {
-            return Some(frame);
-        }
-        // if recycled is empty:
-        // try allocate from frames like before
-        self.used += 1;
-        self.frames.next()
+        self.allocate_frames(0)
     }
 }
 
-impl FrameDeallocator<Size4KiB> for BootInfoFrameAllocator {
+impl FrameDeallocator<Size4KiB> for BuddyFrameAllocator {
     unsafe fn deallocate_frame(&mut self, frame: PhysFrame) {
-        // TODO: deallocate frame (not for lab 2)
-        self.recycled.push(frame);
+        // a shared frame is still used by the other mappings
+        let pfn = pfn_of(frame);
+        if self.zone_mut(pfn).is_some_and(|zone| !zone.unshare(pfn)) {
+            return;
+        }
+
+        unsafe { self.deallocate_frames(frame, 0) };
     }
 }
 
-fn create_frame_iter(memory_map: &MemoryMap) -> BootInfoFrameIter {
-    let iter = memory_map
-        .clone()
-        .into_iter()
-        // get usable regions from memory map
-        .filter(|r| r.ty == MemoryType::CONVENTIONAL)
-        // align to page boundary
-        .flat_map(|r| (0..r.page_count).map(move |v| (v * 4096 + r.phys_start)))
-        // create `PhysFrame` types from the start addresses
-        .map(|addr| PhysFrame::containing_address(PhysAddr::new(addr)));
-
-    Box::new(iter)
+fn pfn_of(frame: PhysFrame) -> u64 {
+    frame.start_address().as_u64() / PAGE_SIZE
+}
+
+fn frame_of(pfn: u64) -> PhysFrame {
+    PhysFrame::containing_address(PhysAddr::new(pfn * PAGE_SIZE))
+}
+
+/// A double free or a frame not from the allocator is a bug of the kernel,
+/// the frames are not freed.
+fn bad_free(frame: PhysFrame, order: usize, err: &str) {
+    if cfg!(debug_assertions) {
+        panic!(
+            "Freeing frame {:#x} of order {}: {}",
+            frame.start_address(),
+            order,
+            err
+        );
+    }
+
+    error!(
+        "Freeing frame {:#x} of order {}: {}",
+        frame.start_address(),
+        order,
+        err
+    );
 }
@@ -10,7 +10,9 @@ pub const PAGE_FAULT_IST_INDEX: u16 = 1;
 pub const CLOCK_INTERRUPT_IST_INDEX: u16 = 2;
 pub const SYSCALL_IST_INDEX: u16 = 3;
 
-pub const IST_SIZES: [usize; 4] = [0x1000, 0x1000, 0x1000, 0x1000];
+/// The page fault handler swaps pages in and out, reading and writing
+/// the disk on its stack, so it takes 32 KiB.
+pub const IST_SIZES: [usize; 4] = [0x1000, 0x1000, 0x8000, 0x1000];
 
 lazy_static! {
     static ref TSS: TaskStateSegment = {
@@ -156,6 +158,51 @@ pub fn init() {
     info!("GDT Initialized.");
 }
 
+/// Load a new GDT and TSS for an application processor,
+/// the stacks are allocated from the kernel heap and never freed.
+///
+/// The descriptors are in the same order as the BSP's,
+/// so the selectors from `get_selector` are valid on every CPU.
+pub fn init_ap() {
+    use alloc::boxed::Box;
+    use alloc::vec;
+    use x86_64::instructions::segmentation::{CS, DS, ES, FS, GS, SS};
+    use x86_64::instructions::tables::load_tss;
+    use x86_64::PrivilegeLevel;
+
+    let alloc_stack = |size: usize| {
+        let stack = vec![0u8; size].leak();
+        VirtAddr::from_ptr(stack.as_ptr()) + size as u64
+    };
+
+    let mut tss = TaskStateSegment::new();
+    tss.privilege_stack_table[0] = alloc_stack(IST_SIZES[0]);
+    tss.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] = alloc_stack(IST_SIZES[1]);
+    tss.interrupt_stack_table[PAGE_FAULT_IST_INDEX as usize] = alloc_stack(IST_SIZES[2]);
+    tss.interrupt_stack_table[CLOCK_INTERRUPT_IST_INDEX as usize] = alloc_stack(IST_SIZES[3]);
+    tss.interrupt_stack_table[SYSCALL_IST_INDEX as usize] = alloc_stack(IST_SIZES[3]);
+    let tss: &'static TaskStateSegment = Box::leak(Box::new(tss));
+
+    let gdt: &'static mut GlobalDescriptorTable = Box::leak(Box::new(GlobalDescriptorTable::new()));
+    let code_selector = gdt.append(Descriptor::kernel_code_segment());
+    let data_selector = gdt.append(Descriptor::kernel_data_segment());
+    let tss_selector = gdt.append(Descriptor::tss_segment(tss));
+    gdt.append(Descriptor::user_code_segment());
+    gdt.append(Descriptor::user_data_segment());
+
+    let gdt: &'static GlobalDescriptorTable = gdt;
+    gdt.load();
+    unsafe {
+        CS::set_reg(code_selector);
+        DS::set_reg(data_selector);
+        SS::set_reg(SegmentSelector::new(0, PrivilegeLevel::Ring0));
+        ES::set_reg(SegmentSelector::new(0, PrivilegeLevel::Ring0));
+        FS::set_reg(SegmentSelector::new(0, PrivilegeLevel::Ring0));
+        GS::set_reg(SegmentSelector::new(0, PrivilegeLevel::Ring0));
+        load_tss(tss_selector);
+    }
+}
+
 pub fn get_selector() -> &'static KernelSelectors {
     &GDT.1
 }
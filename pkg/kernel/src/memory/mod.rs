@@ -1,14 +1,18 @@
 pub mod address;
 pub mod allocator;
+mod buddy;
 mod frames;
+mod slab;
+pub mod swap;
 
 pub mod gdt;
-pub mod user;
 
 pub use address::*;
 pub use frames::*;
 
 use crate::humanized_size;
+use x86_64::registers::control::{Cr0, Cr0Flags};
+use x86_64::registers::model_specific::{Efer, EferFlags};
 
 pub fn init(boot_info: &'static boot::BootInfo) {
     let memory_map = &boot_info.memory_map;
@@ -30,11 +34,23 @@ pub fn init(boot_info: &'static boot::BootInfo) {
     info!("Free Usable Memory : {:>7.*} {}", 3, size, unit);
 
     unsafe {
-        init_FRAME_ALLOCATOR(BootInfoFrameAllocator::init(
-            memory_map,
-            usable_mem_size as usize,
-        ));
+        init_FRAME_ALLOCATOR(BuddyFrameAllocator::init(memory_map));
+    }
+
+    for zone in get_frame_alloc_for_sure().zones() {
+        let (size, unit) = humanized_size(zone.frames_total() as u64 * PAGE_SIZE);
+        info!("Zone {:<6}       : {:>7.*} {}", zone.name(), 3, size, unit);
     }
 
     info!("Frame Allocator initialized.");
+
+    // the kernel faults on writing read-only user pages as well,
+    // so a page shared by fork is copied before the kernel writes it
+    unsafe { Cr0::update(|flags| flags.insert(Cr0Flags::WRITE_PROTECT)) };
+
+    // set by the bootloader, the kernel is mapped with NO_EXECUTE already
+    assert!(
+        Efer::read().contains(EferFlags::NO_EXECUTE_ENABLE),
+        "NX is not enabled"
+    );
 }
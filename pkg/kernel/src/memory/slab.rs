@@ -0,0 +1,125 @@
+//! Object caches for small kernel allocations
+//!
+//! Each cache hands out objects of one size, carved from whole frames taken
+//! from the frame allocator. A freed object goes back to the free list of its
+//! cache, and the frames are kept by the cache to be used again.
+
+use super::{get_frame_alloc_blocking, physical_to_virtual, PAGE_SIZE};
+use core::alloc::Layout;
+use core::ptr::NonNull;
+use spin::Mutex;
+use x86_64::structures::paging::FrameAllocator;
+
+/// Sizes of the caches, an object is aligned to its size
+pub const SLAB_SIZES: [usize; 9] = [8, 16, 32, 64, 128, 256, 512, 1024, 2048];
+
+struct Cache {
+    size: usize,
+    /// The first free object, 0 if there is none
+    free: usize,
+    slabs: usize,
+    in_use: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SlabStats {
+    pub size: usize,
+    /// Frames taken by the cache
+    pub slabs: usize,
+    pub objects: usize,
+    pub in_use: usize,
+}
+
+impl Cache {
+    const fn new(size: usize) -> Self {
+        Self {
+            size,
+            free: 0,
+            slabs: 0,
+            in_use: 0,
+        }
+    }
+
+    /// Take a frame for more objects, see `KernelHeap::grow`
+    fn refill(&mut self) -> bool {
+        let Some(frame) = get_frame_alloc_blocking().allocate_frame() else {
+            return false;
+        };
+
+        let base = physical_to_virtual(frame.start_address().as_u64()) as usize;
+        for addr in (base..base + PAGE_SIZE as usize).step_by(self.size).rev() {
+            self.push(addr);
+        }
+
+        self.slabs += 1;
+        true
+    }
+
+    fn push(&mut self, addr: usize) {
+        unsafe { *(addr as *mut usize) = self.free };
+        self.free = addr;
+    }
+
+    fn pop(&mut self) -> Option<usize> {
+        if self.free == 0 && !self.refill() {
+            return None;
+        }
+
+        let addr = self.free;
+        self.free = unsafe { *(addr as *const usize) };
+        Some(addr)
+    }
+
+    fn stats(&self) -> SlabStats {
+        SlabStats {
+            size: self.size,
+            slabs: self.slabs,
+            objects: self.slabs * PAGE_SIZE as usize / self.size,
+            in_use: self.in_use,
+        }
+    }
+}
+
+pub struct SlabAllocator {
+    caches: [Mutex<Cache>; SLAB_SIZES.len()],
+}
+
+impl SlabAllocator {
+    pub const fn new() -> Self {
+        let mut caches = [const { Mutex::new(Cache::new(0)) }; SLAB_SIZES.len()];
+
+        let mut i = 0;
+        while i < SLAB_SIZES.len() {
+            caches[i] = Mutex::new(Cache::new(SLAB_SIZES[i]));
+            i += 1;
+        }
+
+        Self { caches }
+    }
+
+    /// The cache for `layout`, if it is small enough for one
+    pub fn class(layout: &Layout) -> Option<usize> {
+        let size = layout.size().max(layout.align());
+        SLAB_SIZES.iter().position(|&slab| slab >= size)
+    }
+
+    pub fn allocate(&self, class: usize) -> Option<NonNull<u8>> {
+        let mut cache = self.caches[class].lock();
+        let addr = cache.pop()?;
+        cache.in_use += 1;
+        NonNull::new(addr as *mut u8)
+    }
+
+    /// # Safety
+    ///
+    /// `ptr` must be allocated from the cache `class`.
+    pub unsafe fn deallocate(&self, class: usize, ptr: NonNull<u8>) {
+        let mut cache = self.caches[class].lock();
+        cache.push(ptr.as_ptr() as usize);
+        cache.in_use -= 1;
+    }
+
+    pub fn stats(&self) -> [SlabStats; SLAB_SIZES.len()] {
+        core::array::from_fn(|class| self.caches[class].lock().stats())
+    }
+}
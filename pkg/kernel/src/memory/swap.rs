@@ -0,0 +1,215 @@
+//! Swap space for the pages of user processes
+//!
+//! The space is split into slots of a page. A page swapped out is written to
+//! a free slot, and its page table entry is left not present, with the slot
+//! number as the address and `SWAPPED` set. The page is read back on the
+//! next fault on it, see `ProcessVm::handle_page_fault`.
+//!
+//! A slot is shared by the processes forked after its page is swapped out,
+//! it is freed when no page table entry refers to it anymore.
+
+use super::{physical_to_virtual, PAGE_SIZE};
+use alloc::{boxed::Box, vec, vec::Vec};
+use spin::{Mutex, Once};
+use storage::{Block512, BlockDevice};
+use x86_64::structures::paging::{page_table::PageTableEntry, PageTableFlags, PhysFrame};
+use x86_64::PhysAddr;
+
+/// Marks a page table entry of a page in the swap space
+pub const SWAPPED: PageTableFlags = PageTableFlags::BIT_10;
+
+/// The partition type of a swap partition in MBR
+pub const SWAP_PARTITION_TYPE: u8 = 0x82;
+
+const BLOCK_SIZE: usize = 512;
+const BLOCKS_PER_SLOT: usize = PAGE_SIZE as usize / BLOCK_SIZE;
+
+static SWAP: Once<Mutex<SwapSpace>> = Once::new();
+
+struct SwapSpace {
+    device: Box<dyn BlockDevice<Block512>>,
+    /// How many page table entries refer to each slot, 0 if it is free
+    refs: Vec<u16>,
+    used: usize,
+    /// Where the search for a free slot starts
+    next: usize,
+}
+
+/// Use `device` as the swap space
+pub fn init(device: Box<dyn BlockDevice<Block512>>) {
+    let slots = device.block_count().unwrap_or(0) / BLOCKS_PER_SLOT;
+    if slots == 0 {
+        warn!("Swap space is too small, swapping is disabled.");
+        return;
+    }
+
+    let (size, unit) = crate::humanized_size(slots as u64 * PAGE_SIZE);
+    info!("Swap Space       : {:>7.*} {}", 3, size, unit);
+
+    SWAP.call_once(|| {
+        Mutex::new(SwapSpace {
+            device,
+            refs: vec![0; slots],
+            used: 0,
+            next: 0,
+        })
+    });
+}
+
+pub fn is_enabled() -> bool {
+    SWAP.get().is_some()
+}
+
+/// Used and total bytes of the swap space
+pub fn usage() -> Option<(usize, usize)> {
+    let swap = SWAP.get()?.lock();
+    Some((
+        swap.used * PAGE_SIZE as usize,
+        swap.refs.len() * PAGE_SIZE as usize,
+    ))
+}
+
+#[inline]
+pub fn is_swapped(entry: &PageTableEntry) -> bool {
+    let flags = entry.flags();
+    flags.contains(SWAPPED) && !flags.contains(PageTableFlags::PRESENT)
+}
+
+/// Write the page mapped by `entry` to a free slot, and mark the entry
+/// swapped. Returns the frame to be deallocated, the TLB must be flushed
+/// for the page before.
+pub fn swap_out(entry: &mut PageTableEntry) -> Option<PhysFrame> {
+    let mut swap = SWAP.get()?.lock();
+    let frame = entry.frame().ok()?;
+
+    let slot = swap.find_free()?;
+    if let Err(err) = swap.write(slot, frame) {
+        error!("Failed to write swap slot {}: {:?}", slot, err);
+        return None;
+    }
+
+    swap.refs[slot] = 1;
+    swap.used += 1;
+
+    let flags = entry.flags()
+        - PageTableFlags::PRESENT
+        - PageTableFlags::ACCESSED
+        - PageTableFlags::DIRTY;
+    entry.set_addr(slot_addr(slot), flags | SWAPPED);
+
+    Some(frame)
+}
+
+/// Read the page of the swapped `entry` into `frame`, and map it there.
+/// Returns false if the frame is not used.
+pub fn swap_in(entry: &mut PageTableEntry, frame: PhysFrame) -> bool {
+    let Some(swap) = SWAP.get() else {
+        return false;
+    };
+    let mut swap = swap.lock();
+
+    // another thread may have read it while this one was waiting
+    if !is_swapped(entry) {
+        return false;
+    }
+
+    let slot = slot_of(entry);
+    if let Err(err) = swap.read(slot, frame) {
+        error!("Failed to read swap slot {}: {:?}", slot, err);
+        return false;
+    }
+
+    swap.put(slot);
+
+    let flags = entry.flags() - SWAPPED;
+    entry.set_frame(frame, flags | PageTableFlags::PRESENT);
+    true
+}
+
+/// One more page table entry refers to the slot of the swapped `entry`
+pub fn share(entry: &PageTableEntry) {
+    if let Some(swap) = SWAP.get() {
+        let mut swap = swap.lock();
+        let slot = slot_of(entry);
+        swap.refs[slot] += 1;
+    }
+}
+
+/// Clear the swapped `entry`, the slot is freed if it is the last one
+/// referring to it. Returns false if the entry is not swapped.
+pub fn release(entry: &mut PageTableEntry) -> bool {
+    if !is_swapped(entry) {
+        return false;
+    }
+
+    if let Some(swap) = SWAP.get() {
+        swap.lock().put(slot_of(entry));
+    }
+
+    entry.set_unused();
+    true
+}
+
+impl SwapSpace {
+    fn find_free(&mut self) -> Option<usize> {
+        let slots = self.refs.len();
+        let slot = (0..slots)
+            .map(|i| (self.next + i) % slots)
+            .find(|&slot| self.refs[slot] == 0)?;
+
+        self.next = (slot + 1) % slots;
+        Some(slot)
+    }
+
+    fn put(&mut self, slot: usize) {
+        debug_assert!(self.refs[slot] > 0, "Swap slot {} is free", slot);
+
+        self.refs[slot] -= 1;
+        if self.refs[slot] == 0 {
+            self.used -= 1;
+        }
+    }
+
+    fn write(&self, slot: usize, frame: PhysFrame) -> storage::FsResult {
+        let page = frame_bytes(frame);
+        let mut block = Block512::default();
+
+        for (i, data) in page.chunks(BLOCK_SIZE).enumerate() {
+            block.as_mut().copy_from_slice(data);
+            self.device.write_block(slot * BLOCKS_PER_SLOT + i, &block)?;
+        }
+
+        Ok(())
+    }
+
+    fn read(&self, slot: usize, frame: PhysFrame) -> storage::FsResult {
+        let page = frame_bytes(frame);
+        let mut block = Block512::default();
+
+        for (i, data) in page.chunks_mut(BLOCK_SIZE).enumerate() {
+            self.device.read_block(slot * BLOCKS_PER_SLOT + i, &mut block)?;
+            data.copy_from_slice(block.as_ref());
+        }
+
+        Ok(())
+    }
+}
+
+fn frame_bytes(frame: PhysFrame) -> &'static mut [u8] {
+    unsafe {
+        core::slice::from_raw_parts_mut(
+            physical_to_virtual(frame.start_address().as_u64()) as *mut u8,
+            PAGE_SIZE as usize,
+        )
+    }
+}
+
+#[inline]
+fn slot_addr(slot: usize) -> PhysAddr {
+    PhysAddr::new(slot as u64 * PAGE_SIZE)
+}
+
+#[inline]
+fn slot_of(entry: &PageTableEntry) -> usize {
+    (entry.addr().as_u64() / PAGE_SIZE) as usize
+}
@@ -1,5 +1,5 @@
 use volatile::{access::ReadOnly, VolatileRef};
-use x86_64::{registers::rflags::RFlags, structures::{gdt::SegmentSelector, idt::InterruptStackFrameValue}, VirtAddr};
+use x86_64::{registers::rflags::RFlags, structures::{gdt::SegmentSelector, idt::InterruptStackFrameValue}, PrivilegeLevel, VirtAddr};
 
 use crate::{memory::gdt::{get_selector, get_user_selector}, RegistersValue};
 
@@ -37,6 +37,44 @@ impl ProcessContext {
         self.value.stack_frame.stack_pointer += offset;
     }
 
+    /// Whether the context returns to user mode
+    #[inline]
+    pub fn is_user(&self) -> bool {
+        self.value.stack_frame.code_segment.rpl() == PrivilegeLevel::Ring3
+    }
+
+    /// Run the `int 0x80` of this context again when it is resumed,
+    /// `rax` and the arguments must still be those of the syscall.
+    pub fn restart_syscall(&mut self) {
+        self.value.stack_frame.instruction_pointer -= 2;
+    }
+
+    /// Call `handler(arg)` on the given stack, as the ABI expects for a call
+    pub fn enter_handler(&mut self, handler: VirtAddr, stack_pointer: VirtAddr, arg: usize) {
+        self.value.stack_frame.instruction_pointer = handler;
+        self.value.stack_frame.stack_pointer = stack_pointer;
+        self.value.stack_frame.cpu_flags.remove(RFlags::DIRECTION_FLAG);
+        self.value.regs.rdi = arg;
+    }
+
+    /// Resume a context the user may have modified, only the registers,
+    /// the addresses and the arithmetic flags are taken from it.
+    pub fn restore_user(&mut self, saved: &ProcessContextValue) {
+        const USER_FLAGS: RFlags = RFlags::CARRY_FLAG
+            .union(RFlags::PARITY_FLAG)
+            .union(RFlags::AUXILIARY_CARRY_FLAG)
+            .union(RFlags::ZERO_FLAG)
+            .union(RFlags::SIGN_FLAG)
+            .union(RFlags::DIRECTION_FLAG)
+            .union(RFlags::OVERFLOW_FLAG);
+
+        let frame = &mut self.value.stack_frame;
+        frame.instruction_pointer = saved.stack_frame.instruction_pointer;
+        frame.stack_pointer = saved.stack_frame.stack_pointer;
+        frame.cpu_flags = (frame.cpu_flags - USER_FLAGS) | (saved.stack_frame.cpu_flags & USER_FLAGS);
+        self.value.regs = saved.regs;
+    }
+
     #[inline]
     pub fn save(&mut self, context: &ProcessContext) {
         self.value = context.as_ref().as_ptr().read();
@@ -63,6 +101,17 @@ impl ProcessContext {
 
         trace!("Init stack frame: {:#?}", &self.stack_frame);
     }
+
+    /// Init a frame that runs `entry` in ring 0 on the given stack with interrupts enabled.
+    pub fn init_kernel_frame(&mut self, entry: VirtAddr, stack_top: VirtAddr) {
+        self.value.stack_frame.stack_pointer = stack_top;
+        self.value.stack_frame.instruction_pointer = entry;
+        self.value.stack_frame.cpu_flags = RFlags::INTERRUPT_FLAG;
+
+        let selector = get_selector();
+        self.value.stack_frame.code_segment = selector.code_selector;
+        self.value.stack_frame.stack_segment = selector.data_selector;
+    }
 }
 
 impl Default for ProcessContextValue {
@@ -1,5 +1,5 @@
 use alloc::{collections::BTreeMap, sync::Arc};
-use spin::RwLock;
+use spin::{Mutex, RwLock};
 use crate::resource::{Resource, ResourceSet};
 use x86_64::structures::paging::{
     page::{PageRange, PageRangeInclusive},
@@ -47,6 +47,22 @@ impl ProcessData {
         self.resources.read().write(fd, buf)
     }
 
+    pub fn get_flags(&self, fd: u8) -> Option<usize> {
+        self.resources.read().get_flags(fd)
+    }
+
+    pub fn set_flags(&mut self, fd: u8, flags: usize) -> bool {
+        self.resources.write().set_flags(fd, flags)
+    }
+
+    pub fn get_resource(&self, fd: u8) -> Option<Arc<Mutex<Resource>>> {
+        self.resources.read().get(fd)
+    }
+
+    pub fn is_console(&self, fd: u8) -> bool {
+        self.resources.read().is_console(fd)
+    }
+
     pub fn open(&mut self, res: Resource) -> u8 {
         self.resources.write().open(res)
     }
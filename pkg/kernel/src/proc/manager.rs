@@ -1,12 +1,19 @@
 use super::*;
 use crate::{filesystem::get_rootfs, humanized_size, memory::{
     self,
-    allocator::{ALLOCATOR, HEAP_SIZE},
-    get_frame_alloc_for_sure, PAGE_SIZE,
-}, proc::vm::stack::STACK_INIT_TOP, resource::Resource};
+    allocator::ALLOCATOR,
+    get_frame_alloc_blocking, swap, PAGE_SIZE,
+}, proc::vm::{stack::STACK_INIT_TOP, USER_SPACE_END}, resource::Resource, time::timer};
 use alloc::{collections::*, format, sync::{Arc, Weak}};
+use sched::Queued;
+use signal::{default_action, exit_code, DefaultAction, SigAction};
+use syscall_def::signal::*;
+use alloc::boxed::Box;
+use core::time::Duration;
+use syscall_def::{EXITED, STOPPED, TIMED_OUT};
 use spin::{Mutex, RwLock};
 use storage::FileSystem;
+use x86_64::structures::paging::Page;
 
 pub static PROCESS_MANAGER: spin::Once<ProcessManager> = spin::Once::new();
 
@@ -27,17 +34,39 @@ pub fn get_process_manager() -> &'static ProcessManager {
         .expect("Process Manager has not been initialized")
 }
 
+/// What a process waiting for another one is woken up with
+#[derive(Clone, Copy, Eq, PartialEq)]
+enum WaitFor {
+    /// The exit code
+    ExitCode,
+    /// `EXITED`, or `TIMED_OUT` by its timeout
+    Exit,
+    /// `EXITED` or `STOPPED`, with `WUNTRACED`
+    ExitOrStop,
+}
+
+impl WaitFor {
+    fn exited(self, ret: isize) -> isize {
+        match self {
+            WaitFor::ExitCode => ret,
+            _ => EXITED as isize,
+        }
+    }
+}
+
 pub struct ProcessManager {
     processes: RwLock<BTreeMap<ProcessId, Arc<Process>>>,
-    ready_queue: Mutex<VecDeque<ProcessId>>,
     app_list: boot::AppListRef,
-    wait_queue: Mutex<BTreeMap<ProcessId, BTreeSet<ProcessId>>>,
+    /// The processes waiting for each process
+    wait_queue: Mutex<BTreeMap<ProcessId, BTreeMap<ProcessId, WaitFor>>>,
+    /// Where `reclaim` goes on looking for a page to swap out,
+    /// the process and the address in it
+    clock: Mutex<(ProcessId, u64)>,
 }
 
 impl ProcessManager {
     pub fn new(init: Arc<Process>, app_list: boot::AppListRef) -> Self {
         let mut processes = BTreeMap::new();
-        let ready_queue = VecDeque::new();
         let wait_queue = BTreeMap::new();
         let pid = init.pid();
 
@@ -46,9 +75,9 @@ impl ProcessManager {
         processes.insert(pid, init);
         Self {
             processes: RwLock::new(processes),
-            ready_queue: Mutex::new(ready_queue),
             app_list: app_list,
             wait_queue: Mutex::new(wait_queue),
+            clock: Mutex::new((pid, 0)),
         }
     }
 
@@ -58,9 +87,78 @@ impl ProcessManager {
 
     
 
-    #[inline]
-    pub fn push_ready(&self, pid: ProcessId) {
-        self.ready_queue.lock().push_back(pid);
+    /// Queue the process on the least loaded CPU it may run on,
+    /// the current CPU is preferred on a tie.
+    pub fn push_ready(&self, pid: ProcessId, reason: Enqueue) {
+        let Some(proc) = self.get_proc(&pid) else {
+            return;
+        };
+
+        let current = processor::current_id();
+        let (target, processor) = processor::online()
+            .filter(|(id, _)| self.allowed_on(pid, *id))
+            .min_by_key(|(id, p)| (p.load(), *id != current))
+            .unwrap_or_else(|| (current, processor::current()));
+
+        // the queue is always locked before the process
+        let mut queue = processor.ready_queue().lock();
+        queue.enqueue(pid, proc.write().sched_mut(), reason);
+        drop(queue);
+
+        timer::wake_cpu(target as u32);
+    }
+
+    /// Whether the affinity of the process allows the CPU,
+    /// a mask without any online CPU allows all of them.
+    fn allowed_on(&self, pid: ProcessId, cpu: usize) -> bool {
+        let Some(proc) = self.get_proc(&pid) else {
+            return true;
+        };
+
+        let affinity = proc.read().affinity();
+        affinity & processor::online_mask() == 0
+            || affinity & 1u64.checked_shl(cpu as u32).unwrap_or(0) != 0
+    }
+
+    /// Take the next process queued on the current CPU, or steal one
+    /// from the busiest CPU if there is none. Processes no longer
+    /// allowed on the current CPU are moved to another one.
+    fn pop_ready(&self) -> Option<Queued> {
+        let current = processor::current_id();
+
+        loop {
+            let next = processor::current().ready_queue().lock().dequeue();
+            let queued = match next {
+                Some(queued) => queued,
+                None => return self.steal(current),
+            };
+
+            if self.allowed_on(queued.pid, current) {
+                return Some(queued);
+            }
+
+            self.push_ready(queued.pid, Enqueue::Yielded);
+        }
+    }
+
+    /// Take a process that may run on `cpu` from the longest queue of the other CPUs.
+    fn steal(&self, cpu: usize) -> Option<Queued> {
+        let (_, victim) = processor::online()
+            .filter(|(id, _)| *id != cpu)
+            .max_by_key(|(_, p)| p.ready_queue().lock().len())?;
+
+        victim
+            .ready_queue()
+            .lock()
+            .steal(&mut |pid| self.allowed_on(pid, cpu))
+    }
+
+    /// Account a clock tick to the scheduler of the running process,
+    /// returns whether it should be preempted.
+    pub fn tick_current(&self) -> bool {
+        let current = self.current();
+        let mut queue = processor::current().ready_queue().lock();
+        queue.tick(current.write().sched_mut())
     }
 
     #[inline]
@@ -86,28 +184,73 @@ impl ProcessManager {
         self.current().write().save(context);
     }
 
-    pub fn switch_next(&self, context: &mut ProcessContext) -> ProcessId {
+    /// Switch to the next ready process,
+    /// or to the idle loop of the current CPU if there is none.
+    pub fn switch_next(&self, context: &mut ProcessContext) -> Option<ProcessId> {
 
         // 如何使这一函数的功能仅限于“切换到下一个进程”
 
-        let mut ready_queue = self.ready_queue.lock();
-
         // FIXME: fetch the next process from ready queue
         // FIXME: check if the next process is ready,
         //        continue to fetch if not ready
-        if let Some(next_pid) = ready_queue.pop_front() {
-            if let Some(next_proc) = self.get_proc(&next_pid) {
-                if next_proc.read().status() == ProgramStatus::Ready {
-                    // FIXME: restore next process's context
-                    next_proc.write().restore(context);
-                    // FIXME: update processor's current pid
-                    processor::set_pid(next_pid);
-                    // FIXME: return next process's pid
-                    return next_pid;
-                }
+        while let Some(Queued { pid: next_pid, level }) = self.pop_ready() {
+            let Some(next_proc) = self.get_proc(&next_pid) else {
+                continue;
+            };
+
+            // checked under the write lock, another CPU may take the same pid
+            let mut next = next_proc.write();
+
+            // 阻塞的进程会在唤醒时重新入队，退出的进程不再需要调度
+            if next.status() != ProgramStatus::Ready {
+                continue;
             }
+
+            // FIXME: restore next process's context
+            // the level may have been boosted while queued
+            next.sched_mut().level = level;
+            next.restore(context);
+            drop(next);
+            // FIXME: update processor's current pid
+            processor::set_pid(next_pid);
+            self.update_tick();
+            // FIXME: return next process's pid
+            return Some(next_pid);
+        }
+
+        // nothing is runnable, run the idle task of this CPU,
+        // the current process has been saved, blocked or killed,
+        // and may already be picked up by another CPU
+        self.enter_idle(context);
+        self.update_tick();
+        None
+    }
+
+    fn enter_idle(&self, context: &mut ProcessContext) {
+        // the page table of the previous process may be freed
+        if let Some(kproc) = self.get_proc(&KERNEL_PID) {
+            kproc.read().vm().page_table.load();
+        }
+        processor::enter_idle(context);
+    }
+
+    /// The kernel process only halts in `wait` after boot,
+    /// so running it or the idle loop with nothing else ready means
+    /// the CPU is idle and the periodic tick can be stopped until the next timer.
+    fn update_tick(&self) {
+        let has_ready = processor::current().ready_queue().lock().pids().iter().any(|pid| {
+            self.get_proc(pid)
+                .is_some_and(|proc| proc.read().status() == ProgramStatus::Ready)
+        });
+
+        if has_ready {
+            // let the idle CPUs steal the rest of the work
+            timer::wake_idle();
+        } else if processor::try_get_pid().is_none_or(|pid| pid == KERNEL_PID) {
+            timer::enter_idle();
+        } else {
+            timer::exit_idle();
         }
-        get_pid()
     }
 
     // pub fn spawn_kernel_thread(
@@ -141,18 +284,34 @@ impl ProcessManager {
     pub fn spawn(
         &self,
         elf: &ElfFile,
+        image: Image,
         name: String,
         parent: Option<Weak<Process>>,
         proc_data: Option<ProcessData>,
-    ) -> ProcessId {
+    ) -> Option<ProcessId> {
         let kproc = self.get_proc(&KERNEL_PID).unwrap();
-        let page_table = kproc.read().clone_page_table();
-        let proc_vm = Some(ProcessVm::new(page_table));
-        let proc = Process::new(name, parent, proc_vm, proc_data);
+        let Some(page_table) = kproc.read().clone_page_table() else {
+            warn!("Failed to spawn {}: out of memory", name);
+            return None;
+        };
+
+        // the limits of the memory are inherited
+        let limits = parent
+            .as_ref()
+            .and_then(Weak::upgrade)
+            .map(|parent| parent.read().vm().limits)
+            .unwrap_or_default();
+
+        let mut proc_vm = ProcessVm::new(page_table);
+        proc_vm.limits = limits;
+        let proc = Process::new(name, parent, Some(proc_vm), proc_data);
     
         let mut inner = proc.write();
         // FIXME: load elf to process pagetable
-        inner.load_elf(elf);
+        if let Err(err) = inner.load_elf(elf, image) {
+            warn!("Failed to load {}: {}", inner.name(), err);
+            return None;
+        }
 
         debug!("load elf success.");
 
@@ -169,21 +328,26 @@ impl ProcessManager {
         let pid = proc.pid();
         // FIXME: something like kernel thread
         self.add_proc(pid, proc);
-        self.push_ready(pid);
+        self.push_ready(pid, Enqueue::New);
     
-        pid
+        Some(pid)
     }
 
+    /// Fork the current process, which returns -1 if out of memory
     pub fn fork(&self) {
         // FIXME: get current process
         let current = self.current();
 
         // FIXME: fork to get child
-        let child_proc = current.fork();
+        let Some(child_proc) = current.fork() else {
+            warn!("Failed to fork #{}: out of memory", current.pid());
+            current.write().set_return_value(-1);
+            return;
+        };
         
         // FIXME: add child to process list
         self.add_proc(child_proc.pid(), child_proc.clone());
-        self.push_ready(child_proc.pid());
+        self.push_ready(child_proc.pid(), Enqueue::New);
 
         // FOR DBG: maybe print the process ready queue?
     }
@@ -196,19 +360,122 @@ impl ProcessManager {
         }
     }
 
-    pub fn wait_pid(&self, pid: ProcessId) {
+    /// Return the exit code of `pid` if it has exited,
+    /// or block the current process until it does.
+    /// With a timeout or `untraced`, it returns `EXITED`, `TIMED_OUT` or
+    /// `STOPPED` when `pid` stops instead, so that the status is never
+    /// mistaken for an exit code.
+    pub fn wait_pid(
+        &self,
+        pid: ProcessId,
+        timeout: Option<Duration>,
+        untraced: bool,
+        context: &ProcessContext,
+    ) -> Option<isize> {
+        let wait_for = match (timeout, untraced) {
+            (_, true) => WaitFor::ExitOrStop,
+            (Some(_), false) => WaitFor::Exit,
+            (None, false) => WaitFor::ExitCode,
+        };
+
+        // `kill` sets the exit code before taking the wait queue
         let mut wait_queue = self.wait_queue.lock();
+
+        if let Some(ret) = self.get_exit_code(pid) {
+            return Some(wait_for.exited(ret));
+        }
+
+        if timeout.is_some_and(|timeout| timeout.is_zero()) {
+            return Some(TIMED_OUT as isize);
+        }
+
+        let waiter = get_pid();
+        let seq = self.current().write().save_and_block(context);
+
         // FIXME: push the current process to the wait queue
         //        `processor::get_pid()` is waiting for `pid`
-        wait_queue.entry(pid).or_default().insert(get_pid());
+        wait_queue.entry(pid).or_default().insert(waiter, wait_for);
+        drop(wait_queue);
+
+        if let Some(timeout) = timeout {
+            self.add_wait_timeout(waiter, seq, timeout, TIMED_OUT as isize, move || {
+                get_process_manager().cancel_wait_pid(pid, waiter)
+            });
+        }
+
+        None
+    }
+
+    /// Remove `waiter` from the processes waiting for `pid`,
+    /// returns false if it has been woken up already.
+    fn cancel_wait_pid(&self, pid: ProcessId, waiter: ProcessId) -> bool {
+        let mut wait_queue = self.wait_queue.lock();
+        let Some(waiters) = wait_queue.get_mut(&pid) else {
+            return false;
+        };
+
+        let removed = waiters.remove(&waiter).is_some();
+        if waiters.is_empty() {
+            wait_queue.remove(&pid);
+        }
+        removed
+    }
+
+    /// Block the current process until `duration` has passed.
+    pub fn sleep(&self, duration: Duration, context: &ProcessContext) {
+        let current = self.current();
+        let seq = current.write().save_and_block(context);
+        self.add_wait_timeout(current.pid(), seq, duration, 0, || true);
+    }
+
+    /// Wake the process up with `ret` after `timeout`, if it is still in the
+    /// wait numbered `seq`. `cancel` removes it from what it is waiting on
+    /// and returns false if it has been woken up by that already.
+    pub fn add_wait_timeout(
+        &self,
+        pid: ProcessId,
+        seq: u64,
+        timeout: Duration,
+        ret: isize,
+        cancel: impl FnOnce() -> bool + Send + 'static,
+    ) {
+        let id = timer::add_timer(
+            timeout,
+            Box::new(move || {
+                let manager = get_process_manager();
+                let Some(proc) = manager.get_proc(&pid) else {
+                    return;
+                };
+
+                // `cancel` may take locks that are held before the process
+                let timed_out = proc.write().take_timeout(seq);
+                if timed_out && cancel() {
+                    manager.wake_up(pid, Some(ret));
+                }
+            }),
+        );
+
+        // the timer may have expired already
+        if let Some(proc) = self.get_proc(&pid) {
+            proc.write().set_timeout(seq, id);
+        }
     }
 
     /// Wake up the process with the given pid
     ///
-    /// If `ret` is `Some`, set the return value of the process
-    pub fn wake_up(&self, pid: ProcessId, ret: Option<isize>) {
+    /// If `ret` is `Some`, set the return value of the process,
+    /// returns false if it is not blocked.
+    pub fn wake_up(&self, pid: ProcessId, ret: Option<isize>) -> bool {
         if let Some(proc) = self.get_proc(&pid) {
             let mut inner = proc.write();
+
+            // 只唤醒阻塞的进程，避免同一个进程被重复放入队列
+            if inner.status() != ProgramStatus::Blocked {
+                return false;
+            }
+
+            inner.cancel_timeout();
+
             if let Some(ret) = ret {
                 // FIXME: set the return value of the process
                 //        like `context.set_rax(ret as usize)`
@@ -217,8 +484,14 @@ impl ProcessManager {
             // FIXME: set the process as ready
             // FIXME: push to ready queue
             inner.pause();
-            self.push_ready(pid);
+            // another CPU may lock the process while holding the ready queue
+            drop(inner);
+            // wakes up the CPU it is queued on
+            self.push_ready(pid, Enqueue::Woken);
+            return true;
         }
+
+        false
     }
 
     pub fn kill_current(&self, ret: isize) {
@@ -228,15 +501,135 @@ impl ProcessManager {
     pub fn handle_page_fault(&self, addr: VirtAddr, err_code: PageFaultErrorCode) -> bool {
         // FIXME: handle page fault
 
-        // 越权访问
+        // 越权访问, only writing a page shared by fork is handled
         if err_code.contains(PageFaultErrorCode::PROTECTION_VIOLATION) {
-            warn!("Page fault: writing to address {:#x}", addr);
-            return false;
+            if err_code.contains(PageFaultErrorCode::INSTRUCTION_FETCH) {
+                warn!("Page fault: executing non-executable page at {:#x}", addr);
+                return false;
+            }
+
+            if !err_code.contains(PageFaultErrorCode::CAUSED_BY_WRITE) {
+                warn!("Page fault: protection violation at {:#x}", addr);
+                return false;
+            }
         }
 
         return self.current().write().handle_page_fault(addr);
     }
 
+    /// Swap out pages of user processes until `count` frames are free,
+    /// returns whether they are.
+    ///
+    /// The pages are scanned like a clock from the one after the last swapped
+    /// out, a page accessed since the last scan is given a second chance.
+    pub fn reclaim(&self, count: usize) -> bool {
+        let is_enough = || get_frame_alloc_blocking().frames_free() >= count;
+
+        if is_enough() || !swap::is_enabled() {
+            return is_enough();
+        }
+
+        let mut clock = self.clock.lock();
+        let processes = self.processes.read().values().cloned().collect::<Vec<_>>();
+        let first = processes
+            .iter()
+            .position(|proc| proc.pid() >= clock.0)
+            .unwrap_or(0);
+
+        // the second round finds the pages cleared by the first one
+        for proc in processes.iter().cycle().skip(first).take(processes.len() * 2 + 1) {
+            if proc.pid() != clock.0 {
+                *clock = (proc.pid(), 0);
+            }
+
+            if proc.pid() == KERNEL_PID {
+                continue;
+            }
+
+            // the process may be locked by the caller, or running on
+            // another CPU with its pages in the TLB there
+            let Some(mut inner) = proc.try_write() else {
+                continue;
+            };
+
+            let status = inner.status();
+            if status == ProgramStatus::Dead
+                || (status == ProgramStatus::Running && proc.pid() != processor::get_pid())
+            {
+                continue;
+            }
+
+            let vm = inner.vm_mut();
+            while !is_enough() {
+                let start = Page::containing_address(VirtAddr::new(clock.1 % USER_SPACE_END));
+                match vm.swap_out_page(start) {
+                    Some(page) => clock.1 = page.start_address().as_u64() + PAGE_SIZE,
+                    None => break,
+                }
+            }
+
+            if is_enough() {
+                return true;
+            }
+
+            clock.1 = 0;
+        }
+
+        is_enough()
+    }
+
+    /// Kill the user process using the most memory for its frames, returns
+    /// false if there is none, or it is running and killed when it returns
+    /// to user mode, so the frames are not freed yet.
+    pub fn oom_kill(&self) -> bool {
+        let processes = self.processes.read().values().cloned().collect::<Vec<_>>();
+
+        let victim = processes
+            .into_iter()
+            .filter(|proc| proc.pid() != KERNEL_PID)
+            .filter_map(|proc| {
+                let inner = proc.read();
+                if inner.status() == ProgramStatus::Dead {
+                    return None;
+                }
+
+                let usage = inner.vm().memory_usage();
+                drop(inner);
+                Some((usage, proc))
+            })
+            .max_by_key(|(usage, _)| *usage);
+
+        let Some((usage, proc)) = victim else {
+            return false;
+        };
+
+        let mut inner = proc.write();
+        let (size, unit) = humanized_size(usage);
+        warn!(
+            "Out of memory: killing {}#{} using {:.3} {}",
+            inner.name(),
+            proc.pid(),
+            size,
+            unit
+        );
+
+        // checked under the write lock, it may be picked up by another CPU
+        match inner.status() {
+            ProgramStatus::Dead => false,
+            ProgramStatus::Running => {
+                drop(inner);
+                self.send_signal(proc.pid(), SIGKILL);
+                false
+            }
+            _ => {
+                inner.kill(exit_code(SIGKILL));
+                drop(inner);
+                self.notify_exit(&proc, exit_code(SIGKILL));
+                true
+            }
+        }
+    }
+
     pub fn kill(&self, pid: ProcessId, ret: isize) {
         let proc = self.get_proc(&pid);
 
@@ -255,16 +648,182 @@ impl ProcessManager {
         trace!("Kill {:#?}", &proc);
 
         proc.kill(ret);
+        self.notify_exit(&proc, ret);
+    }
+
+    /// Wake up the processes waiting for the exited process and tell its parent.
+    fn notify_exit(&self, proc: &Process, ret: isize) {
+        if let Some(waiters) = self.wait_queue.lock().remove(&proc.pid()) {
+            for (waiter, wait_for) in waiters {
+                self.wake_up(waiter, Some(wait_for.exited(ret)));
+            }
+        }
+
+        let parent = proc.read().parent();
+        if let Some(parent) = parent {
+            self.send_signal(parent.pid(), SIGCHLD);
+        }
+
+        // the kernel may be waiting for it on an idle CPU
+        timer::wake_idle();
+    }
+
+    /// Wake up the processes waiting for the process to stop.
+    fn notify_stop(&self, pid: ProcessId) {
+        let mut wait_queue = self.wait_queue.lock();
+        let Some(waiters) = wait_queue.get_mut(&pid) else {
+            return;
+        };
+
+        let untraced: Vec<ProcessId> = waiters
+            .iter()
+            .filter(|(_, wait_for)| **wait_for == WaitFor::ExitOrStop)
+            .map(|(waiter, _)| *waiter)
+            .collect();
+        waiters.retain(|_, wait_for| *wait_for != WaitFor::ExitOrStop);
+
+        if waiters.is_empty() {
+            wait_queue.remove(&pid);
+        }
+        drop(wait_queue);
+
+        for waiter in untraced {
+            self.wake_up(waiter, Some(STOPPED as isize));
+        }
+    }
 
-        if let Some(pids) = self.wait_queue.lock().remove(&pid) {
-            for pid in pids {
-                self.wake_up(pid, Some(ret));
+    /// Send `sig` to the process, signal 0 only checks that it exists.
+    ///
+    /// The signal is handled when the process returns to user mode, except
+    /// that a blocked process is terminated at once if the signal would
+    /// terminate it, and a stopped process only reacts to SIGKILL and SIGCONT.
+    pub fn send_signal(&self, pid: ProcessId, sig: usize) -> bool {
+        let Some(proc) = self.get_proc(&pid) else {
+            return false;
+        };
+
+        if sig >= NSIG {
+            return false;
+        }
+
+        let mut inner = proc.write();
+        let status = inner.status();
+        if status == ProgramStatus::Dead {
+            return false;
+        }
+
+        if sig == 0 {
+            return true;
+        }
+
+        inner.signals_mut().raise(sig);
+
+        let terminates = sig == SIGKILL
+            || (inner.signals().action(sig) == SigAction::Default
+                && default_action(sig) == DefaultAction::Terminate
+                && inner.signals().is_deliverable(sig));
+
+        match status {
+            // not running anywhere, so it is safe to kill it here
+            ProgramStatus::Blocked | ProgramStatus::Stopped
+                if sig == SIGKILL || (terminates && status == ProgramStatus::Blocked) =>
+            {
+                inner.kill(exit_code(sig));
+                drop(inner);
+                debug!("Process #{} terminated by signal {}.", pid, sig);
+                self.notify_exit(&proc, exit_code(sig));
+            }
+            ProgramStatus::Stopped if sig == SIGCONT => {
+                inner.pause();
+                drop(inner);
+                self.push_ready(pid, Enqueue::Woken);
             }
+            _ => {}
         }
+
+        true
+    }
+
+    /// Handle the pending signals of the current process, which is about to
+    /// return to user mode with `context`. Returns true if the process is
+    /// terminated or stopped and `context` now belongs to another one.
+    pub fn handle_signal(&self, context: &mut ProcessContext) -> bool {
+        let Some(pid) = processor::try_get_pid() else {
+            return false;
+        };
+
+        let proc = self.current();
+        let mut inner = proc.write();
+
+        while let Some(sig) = inner.signals_mut().take() {
+            let action = match inner.signals().action(sig) {
+                SigAction::Ignore => continue,
+                SigAction::Handler { handler, restorer } => {
+                    if inner.push_signal_frame(context, sig, handler, restorer) {
+                        return false;
+                    }
+
+                    warn!("Process #{} has no stack for the handler of signal {}.", pid, sig);
+                    DefaultAction::Terminate
+                }
+                SigAction::Default => default_action(sig),
+            };
+
+            match action {
+                DefaultAction::Ignore | DefaultAction::Continue => continue,
+                DefaultAction::Terminate => {
+                    drop(inner);
+                    debug!("Process #{} terminated by signal {}.", pid, sig);
+                    self.kill(pid, exit_code(sig));
+                }
+                DefaultAction::Stop => {
+                    inner.tick();
+                    inner.save(context);
+                    inner.stop();
+                    drop(inner);
+                    debug!("Process #{} stopped by signal {}.", pid, sig);
+                    self.notify_stop(pid);
+                }
+            }
+
+            self.switch_next(context);
+            return true;
+        }
+
+        false
+    }
+
+    /// Set the action of `sig` for the current process, returns the old one.
+    pub fn set_sigaction(&self, sig: usize, action: SigAction) -> Option<SigAction> {
+        self.current().write().signals_mut().set_action(sig, action)
+    }
+
+    /// Change the blocked signals of the current process as `Sigprocmask`
+    /// does, returns the old mask.
+    pub fn sigprocmask(&self, how: usize, set: u64) -> Option<u64> {
+        let current = self.current();
+        let mut inner = current.write();
+        let old = inner.signals().blocked();
+
+        let new = match how {
+            SIG_BLOCK => old | set,
+            SIG_UNBLOCK => old & !set,
+            SIG_SETMASK => set,
+            _ => return None,
+        };
+
+        inner.signals_mut().set_blocked(new);
+        Some(old)
+    }
+
+    /// Return from a signal handler to the context saved before it,
+    /// returns false if the signal frame is not valid.
+    pub fn sigreturn(&self, context: &mut ProcessContext) -> bool {
+        self.current().write().pop_signal_frame(context)
     }
 
     pub fn print_process_list(&self) {
-        let mut output = String::from("  PID | PPID | Process Name |  Ticks  |   Memory  | Status\n");
+        let mut output = String::from("  PID | PPID | Process Name |  Ticks  | Nice | Lvl |   Memory  | Status\n");
 
         self.processes
             .read()
@@ -273,30 +832,105 @@ impl ProcessManager {
             .for_each(|p| output += format!("{}\n", p).as_str());
 
         // TODO: print memory usage of kernel heap
-        let heap_used = ALLOCATOR.lock().used();
-        let heap_size = HEAP_SIZE;
+        let (heap_used, heap_size) = ALLOCATOR.heap_usage();
 
-        output += &format_usage("Kernel", heap_used, heap_size);        
+        output += &format_usage("Kernel", heap_used, heap_size);
 
+        for slab in ALLOCATOR.slab_stats().iter().filter(|slab| slab.slabs > 0) {
+            output += format!(
+                "  {:>4} B: {:>5} / {:>5} objects in {} frames\n",
+                slab.size, slab.in_use, slab.objects, slab.slabs
+            )
+            .as_str();
+        }
 
-        let alloc = get_frame_alloc_for_sure();
-        let frames_used = alloc.frames_used();
-        let frames_recycled = alloc.frames_recycled();
-        let frames_total = alloc.frames_total();
 
-        let used = (frames_used - frames_recycled) * PAGE_SIZE as usize;
-        let total = frames_total * PAGE_SIZE as usize;
+        // the heap grows from the frame allocator, it is not locked while formatting
+        let alloc = get_frame_alloc_blocking();
+        let used = alloc.frames_used() * PAGE_SIZE as usize;
+        let total = alloc.frames_total() * PAGE_SIZE as usize;
+        let zones = alloc.zone_stats();
+        drop(alloc);
 
         output += &format_usage("Memory", used, total);
-        drop(alloc);
 
-        output += format!("Queue  : {:?}\n", self.ready_queue.lock()).as_str();
+        // free blocks of each order, from a single frame up
+        for zone in zones.iter().filter(|zone| zone.frames_total > 0) {
+            let used = (zone.frames_total - zone.frames_free) * PAGE_SIZE as usize;
+            let total = zone.frames_total * PAGE_SIZE as usize;
+
+            output += &format_usage(zone.name, used, total);
+            output += format!("         free blocks: {:?}\n", zone.free_blocks).as_str();
+        }
+
+        if let Some((used, total)) = swap::usage() {
+            output += &format_usage("Swap", used, total);
+        }
+
+        output += format!(
+            "Timer  : {} interrupts, {} pending, {}\n",
+            crate::interrupt::clock::read_counter(),
+            timer::pending_timers(),
+            if timer::is_tickless() { "tickless" } else { "periodic" }
+        )
+        .as_str();
+
+        output += format!(
+            "Sched  : {}\n",
+            processor::current().ready_queue().lock().name()
+        )
+        .as_str();
 
         output += &processor::print_processors();
 
         print!("{}", output);
     }
 
+    /// Set the CPUs the process may run on, it moves at its next switch.
+    pub fn set_affinity(&self, pid: ProcessId, mask: u64) -> bool {
+        // at least one CPU must be able to run it
+        if mask & processor::online_mask() == 0 {
+            return false;
+        }
+
+        match self.get_proc(&pid) {
+            Some(proc) if proc.read().status() != ProgramStatus::Dead => {
+                proc.write().set_affinity(mask);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn get_affinity(&self, pid: ProcessId) -> Option<u64> {
+        self.get_proc(&pid).map(|proc| proc.read().affinity())
+    }
+
+    /// The limit of `resource` of the current process, see `MemoryLimits`
+    pub fn get_rlimit(&self, resource: usize) -> Option<u64> {
+        self.current().read().vm().limits.get(resource)
+    }
+
+    pub fn set_rlimit(&self, resource: usize, limit: u64) -> bool {
+        self.current().write().vm_mut().limits.set(resource, limit)
+    }
+
+    /// Set the nice value of the process, clamped to the valid range.
+    /// It takes effect the next time the process is queued.
+    pub fn set_nice(&self, pid: ProcessId, nice: i8) -> bool {
+        match self.get_proc(&pid) {
+            Some(proc) if proc.read().status() != ProgramStatus::Dead => {
+                proc.write().sched_mut().nice = nice.clamp(sched::NICE_MIN, sched::NICE_MAX);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn get_nice(&self, pid: ProcessId) -> Option<i8> {
+        self.get_proc(&pid).map(|proc| proc.read().sched().nice)
+    }
+
     pub fn get_exit_code(&self, pid: ProcessId) -> Option<isize> {
         x86_64::instructions::interrupts::without_interrupts(|| {
             self.get_proc(&pid).and_then(|proc| proc.read().exit_code())
@@ -5,6 +5,8 @@ mod paging;
 mod pid;
 mod process;
 mod processor;
+pub mod sched;
+pub mod signal;
 mod sync;
 mod vm;
 
@@ -14,13 +16,19 @@ use alloc::vec::Vec;
 use manager::*;
 use process::*;
 use processor::get_pid;
-use storage::FileSystem;
+use sched::{Enqueue, SchedInfo};
+use signal::SigAction;
+use core::time::Duration;
+use syscall_def::mman::MmapArgs;
+use syscall_def::{O_NONBLOCK, TIMED_OUT, WOULD_BLOCK};
+use storage::{FileHandle, FileSystem};
 use sync::SemaphoreResult;
 use uefi::proto::debug;
-use vm::ProcessVm;
+use vm::{vma::Image, ProcessVm};
 use x86::current;
 use crate::filesystem::get_rootfs;
-use crate::memory::PAGE_SIZE;
+use crate::resource::Resource;
+use crate::memory::{get_frame_alloc_blocking, PAGE_SIZE};
 
 use alloc::string::{String, ToString};
 pub use context::ProcessContext;
@@ -33,16 +41,23 @@ use x86_64::VirtAddr;
 use xmas_elf::ElfFile;
 pub const KERNEL_PID: ProcessId = ProcessId(1);
 
+/// Frames kept free for the kernel when pages are swapped out
+const LOW_WATERMARK: usize = 32;
+
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum ProgramStatus {
     Running,
     Ready,
     Blocked,
+    Stopped,
     Dead,
 }
 
 /// init process manager
 pub fn init(boot_info: &'static boot::BootInfo) {
+    sched::init(&boot_info.cmdline);
+    processor::init();
+
     let proc_vm = ProcessVm::new(PageTableContext::new()).init_kernel_vm(&boot_info.kernel_pages);
 
     trace!("Init kernel vm: {:#?}", proc_vm);
@@ -60,6 +75,16 @@ pub fn init(boot_info: &'static boot::BootInfo) {
     info!("Process Manager Initialized.");
 }
 
+/// Called on an application processor once it can take processes.
+pub fn init_ap() {
+    processor::set_online();
+}
+
+/// Number of CPUs taking processes
+pub fn online_count() -> usize {
+    processor::online_count()
+}
+
 pub fn switch(context: &mut ProcessContext) {
     x86_64::instructions::interrupts::without_interrupts(|| {
         // 如果当前的在Running，改变状态，扔进队列
@@ -68,12 +93,20 @@ pub fn switch(context: &mut ProcessContext) {
         // FIXME: switch to the next process
         //      - save current process's context && update status
         let manager = get_process_manager();
-        let proc = manager.current();
 
-        if proc.read().status() == ProgramStatus::Running {
-            manager.save_current(context); // 这里会改变成ready
-            //      - handle ready queue update
-            manager.push_ready(get_pid());
+        // 空闲的 CPU 没有要保存的进程
+        if let Some(pid) = processor::try_get_pid() {
+            if manager.current().read().status() == ProgramStatus::Running {
+                // 时间片没用完就继续运行
+                if !manager.tick_current() {
+                    manager.current().write().tick();
+                    return;
+                }
+
+                manager.save_current(context); // 这里会改变成ready
+                //      - handle ready queue update
+                manager.push_ready(pid, Enqueue::Preempted);
+            }
         }
 
         //      - restore next process's context
@@ -114,10 +147,29 @@ pub fn process_exit(ret: isize) -> ! {
 
 pub fn handle_page_fault(addr: VirtAddr, err_code: PageFaultErrorCode) -> bool {
     x86_64::instructions::interrupts::without_interrupts(|| {
-        get_process_manager().handle_page_fault(addr, err_code)
+        let manager = get_process_manager();
+
+        // the page and the page tables to map it, a process is
+        // killed instead of failing the fault if there is no swap
+        while !reserve_frames(4) && manager.oom_kill() {}
+
+        manager.handle_page_fault(addr, err_code)
     })
 }
 
+/// Swap out pages of user processes if `count` frames can not be allocated
+/// with some left for the kernel, it must be called without any lock held.
+/// Returns whether `count` frames can be allocated.
+fn reserve_frames(count: usize) -> bool {
+    get_process_manager().reclaim(count + LOW_WATERMARK)
+        || get_frame_alloc_blocking().frames_free() >= count
+}
+
+/// How many pages the buffer of `len` bytes may span
+fn buffer_pages(len: usize) -> usize {
+    len.div_ceil(PAGE_SIZE as usize) + 1
+}
+
 pub fn list_app() {
     x86_64::instructions::interrupts::without_interrupts(|| {
         let app_list = get_process_manager().app_list();
@@ -145,45 +197,168 @@ pub fn spawn(name: &str) -> Option<ProcessId> {
         app_list.iter().find(|&app| app.name.eq(name))
     })?;
 
-    elf_spawn(name.to_string(), &app.elf)
+    elf_spawn(name.to_string(), &app.elf, Image::Memory(app.elf.input))
 }
 
+/// Spawn the program at `path`, only its headers are read here
+/// and the segments are read from the file on access.
 pub fn fs_spawn(path: &str) -> Option<ProcessId> {
     debug!("Spawning app from path: {}", path);
-    let mut file = get_rootfs().open_file(path).expect("Failed to open app binary file");
-    // if file.is_directory().unwrap_or(true) {
-    //     continue;
-    // }
-
-    let mut buf = Vec::new(); // 64KB buffer
-    file.read_all(&mut buf).expect("Failed to read app binary file");
-    let elf = ElfFile::new(&buf).expect("Failed to parse ELF file");
+    let mut file = match get_rootfs().open_file(path) {
+        Ok(file) => file,
+        Err(err) => {
+            warn!("Failed to open app binary file '{}': {:?}", path, err);
+            return None;
+        }
+    };
 
+    let headers = read_elf_headers(&mut file)?;
+    let elf = ElfFile::new(&headers).ok()?;
     let name = file.meta.name.to_string();
+    let image = Image::File(Arc::new(spin::Mutex::new(Resource::File(file))));
+
+    elf_spawn(name, &elf, image)
+}
+
+/// Read the ELF header and the program headers of `file`
+fn read_elf_headers(file: &mut FileHandle) -> Option<Vec<u8>> {
+    let mut buf = alloc::vec![0; PAGE_SIZE as usize];
+    let len = read_fully(file, &mut buf)?;
+    buf.truncate(len);
+
+    let header = ElfFile::new(&buf).ok()?.header.pt2;
+    let end = header.ph_offset() as usize + header.ph_count() as usize * header.ph_entry_size() as usize;
+
+    if end > buf.len() {
+        let start = buf.len();
+        buf.resize(end, 0);
+        if read_fully(file, &mut buf[start..])? < end - start {
+            warn!("Program headers are out of the file.");
+            return None;
+        }
+    }
+
+    Some(buf)
+}
+
+/// Read until `buf` is full or the end of `file`
+fn read_fully(file: &mut FileHandle, buf: &mut [u8]) -> Option<usize> {
+    let mut count = 0;
+
+    while count < buf.len() {
+        match file.read(&mut buf[count..]) {
+            Ok(0) => break,
+            Ok(len) => count += len,
+            Err(err) => {
+                warn!("Failed to read app binary file: {:?}", err);
+                return None;
+            }
+        }
+    }
 
-    elf_spawn(name, &elf)
+    Some(count)
 }
 
-pub fn elf_spawn(name: String, elf: &ElfFile) -> Option<ProcessId> {
-    let pid = x86_64::instructions::interrupts::without_interrupts(|| {
+pub fn elf_spawn(name: String, elf: &ElfFile, image: Image) -> Option<ProcessId> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
         let manager = get_process_manager();
         let process_name = name.to_lowercase();
         let parent = Arc::downgrade(&manager.current());
-        let pid = manager.spawn(elf, name, Some(parent), None);
+        let pid = manager.spawn(elf, image, name, Some(parent), None)?;
 
         debug!("Spawned process: {}#{}", process_name, pid);
-        pid
-    });
+        Some(pid)
+    })
+}
 
-    Some(pid)
+/// Read from `fd`, a read of the console blocks until there is input
+/// unless `O_NONBLOCK` is set on `fd`.
+pub fn read(fd: u8, buf: &mut [u8], context: &mut ProcessContext) {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let manager = get_process_manager();
+        let current = manager.current();
+        reserve_frames(buffer_pages(buf.len()));
+
+        // the buffer is written with the process locked, so its pages
+        // shared by fork can not be copied on the fault, do it before
+        let addr = VirtAddr::from_ptr(buf.as_ptr());
+        if !current.write().vm_mut().check_user_range(addr, buf.len() as u64, true) {
+            context.set_rax(-1isize as usize);
+            return;
+        }
+
+        loop {
+            let ret = current.read().read(fd, buf);
+            let nonblock = current.read().get_flags(fd).unwrap_or(0) & O_NONBLOCK != 0;
+
+            if ret != WOULD_BLOCK as isize || nonblock {
+                context.set_rax(ret as usize);
+                return;
+            }
+
+            // 被唤醒后重新执行这次 read
+            let blocked = crate::drivers::tty::wait_input(current.pid(), || {
+                context.restart_syscall();
+                current.write().save_and_block(context);
+            });
+
+            if blocked {
+                manager.switch_next(context);
+                return;
+            }
+        }
+    })
+}
+
+pub fn get_fd_flags(fd: u8) -> Option<usize> {
+    x86_64::instructions::interrupts::without_interrupts(|| get_process_manager().current().read().get_flags(fd))
+}
+
+pub fn set_fd_flags(fd: u8, flags: usize) -> bool {
+    x86_64::instructions::interrupts::without_interrupts(|| get_process_manager().current().write().set_flags(fd, flags))
 }
 
-pub fn read(fd: u8, buf: &mut [u8]) -> isize {
-    x86_64::instructions::interrupts::without_interrupts(|| get_process_manager().current().read().read(fd, buf))
+/// Wake up a process blocked in the kernel.
+pub fn wake_up(pid: ProcessId) -> bool {
+    x86_64::instructions::interrupts::without_interrupts(|| get_process_manager().wake_up(pid, None))
 }
 
 pub fn write(fd: u8, buf: &[u8]) -> isize {
-    x86_64::instructions::interrupts::without_interrupts(|| get_process_manager().current().read().write(fd, buf))
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let current = get_process_manager().current();
+        reserve_frames(buffer_pages(buf.len()));
+
+        // the buffer is read with the process locked,
+        // its pages not loaded yet can not be loaded on the fault
+        let addr = VirtAddr::from_ptr(buf.as_ptr());
+        if !current.write().vm_mut().check_user_range(addr, buf.len() as u64, false) {
+            return -1;
+        }
+
+        current.read().write(fd, buf)
+    })
+}
+
+/// Whether the current process may access `len` bytes at `addr`, for the
+/// syscalls taking a pointer. Its pages are loaded, so the kernel can access
+/// them without faulting.
+pub fn check_user_range(addr: usize, len: usize, write: bool) -> bool {
+    let Ok(addr) = VirtAddr::try_new(addr as u64) else {
+        return false;
+    };
+
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        reserve_frames(buffer_pages(len));
+        get_process_manager()
+            .current()
+            .write()
+            .vm_mut()
+            .check_user_range(addr, len as u64, write)
+    })
+}
+
+pub fn is_console(fd: u8) -> bool {
+    x86_64::instructions::interrupts::without_interrupts(|| get_process_manager().current().read().is_console(fd))
 }
 
 pub fn open(path: &str) -> Option<u8> {
@@ -215,30 +390,67 @@ pub fn fork(context: &mut ProcessContext) {
         // FIXME: push to child & parent to ready queue
         // child在manager中push进去
         let parent_pid = manager.current().pid();
-        manager.push_ready(parent_pid);
+        manager.push_ready(parent_pid, Enqueue::Yielded);
 
         // FIXME: switch to next process
         manager.switch_next(context);
     })
 }
 
+pub fn mmap(args: &MmapArgs) -> Option<VirtAddr> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        get_process_manager().current().write().mmap(args)
+    })
+}
+
+pub fn munmap(addr: VirtAddr, len: u64) -> bool {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        get_process_manager()
+            .current()
+            .write()
+            .vm_mut()
+            .munmap(addr.as_u64(), len)
+    })
+}
+
+pub fn mprotect(addr: VirtAddr, len: u64, prot: usize) -> bool {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        get_process_manager()
+            .current()
+            .write()
+            .vm_mut()
+            .mprotect(addr.as_u64(), len, prot)
+    })
+}
+
 pub fn brk(addr: Option<VirtAddr>) -> Option<VirtAddr> {
     x86_64::instructions::interrupts::without_interrupts(|| {
+        let current = get_process_manager().current();
+
+        // the pages the heap grows by
+        if let (Some(addr), Some(end)) = (addr, current.read().brk(None)) {
+            let size = addr.as_u64().saturating_sub(end.as_u64());
+            reserve_frames(size.div_ceil(PAGE_SIZE) as usize);
+        }
+
         // NOTE: `brk` does not need to get write lock
-        get_process_manager().current().read().brk(addr)
+        current.read().brk(addr)
     })
 }
 
-pub fn wait_pid(pid: ProcessId, context: &mut ProcessContext) {
+pub fn wait_pid(
+    pid: ProcessId,
+    timeout: Option<Duration>,
+    untraced: bool,
+    context: &mut ProcessContext,
+) {
     x86_64::instructions::interrupts::without_interrupts(|| {
         let manager = get_process_manager();
-        if let Some(ret) = manager.get_exit_code(pid) {
-            context.set_rax(ret as usize);
-        } else {
-            manager.wait_pid(pid);
-            manager.save_current(context);
-            manager.current().write().block();
-            manager.switch_next(context);
+        match manager.wait_pid(pid, timeout, untraced, context) {
+            Some(ret) => context.set_rax(ret as usize),
+            None => {
+                manager.switch_next(context);
+            }
         }
     })
 }
@@ -268,31 +480,55 @@ pub fn remove_sem(key: u32) -> usize {
 pub fn sem_signal(key: u32, context: &mut ProcessContext) {
     x86_64::instructions::interrupts::without_interrupts(|| {
         let manager = get_process_manager();
-        let ret = manager.current().write().sem_signal(key);
-        match ret {
-            SemaphoreResult::Ok => context.set_rax(0),
-            SemaphoreResult::NotExist => context.set_rax(1),
-            SemaphoreResult::WakeUp(pid) => {
-                manager.wake_up(pid, None);
+        loop {
+            let ret = manager.current().write().sem_signal(key);
+            match ret {
+                SemaphoreResult::Ok => context.set_rax(0),
+                SemaphoreResult::NotExist => context.set_rax(1),
+                // 等待者可能已被信号终止，此时把信号量交给下一个
+                SemaphoreResult::WakeUp(pid) if !manager.wake_up(pid, None) => continue,
+                SemaphoreResult::WakeUp(_) => context.set_rax(0),
+                _ => unreachable!(),
             }
-            _ => unreachable!(),
+            break;
         }
     })
 }
 
-pub fn sem_wait(key: u32, context: &mut ProcessContext) {
+/// Wait the semaphore, gives up with `TIMED_OUT` if `timeout` expires first.
+pub fn sem_wait(key: u32, timeout: Option<Duration>, context: &mut ProcessContext) {
     x86_64::instructions::interrupts::without_interrupts(|| {
         let manager = get_process_manager();
         let pid = processor::get_pid();
-        let ret = manager.current().write().sem_wait(key, pid);
+        let current = manager.current();
+        // 在同一把锁内加入等待队列并阻塞，其他 CPU 的 signal 会等到阻塞之后才唤醒
+        let mut inner = current.write();
+        let ret = inner.sem_wait(key, pid);
         match ret {
             SemaphoreResult::Ok => context.set_rax(0),
             SemaphoreResult::NotExist => context.set_rax(1),
-            SemaphoreResult::Block(pid) => {
+            SemaphoreResult::Block(_) if timeout.is_some_and(|t| t.is_zero()) => {
+                // 没能取消说明已经被 signal 选中，等同于获得了信号量
+                if inner.semaphores.read().cancel_wait(key, pid) {
+                    context.set_rax(TIMED_OUT);
+                } else {
+                    context.set_rax(0);
+                }
+            }
+            SemaphoreResult::Block(_) => {
                 // FIXME: save, block it, then switch to next
                 //        use `save_current` and `switch_next`
-                manager.save_current(context);
-                manager.block(pid);
+                context.set_rax(0);
+                let seq = inner.save_and_block(context);
+                let sems = inner.semaphores.clone();
+                drop(inner);
+
+                if let Some(timeout) = timeout {
+                    manager.add_wait_timeout(pid, seq, timeout, TIMED_OUT as isize, move || {
+                        sems.read().cancel_wait(key, pid)
+                    });
+                }
+
                 manager.switch_next(context);
             }
             _ => unreachable!(),
@@ -300,6 +536,118 @@ pub fn sem_wait(key: u32, context: &mut ProcessContext) {
     })
 }
 
+/// Block the current process for `duration`.
+pub fn sleep(duration: Duration, context: &mut ProcessContext) {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let manager = get_process_manager();
+        context.set_rax(0);
+
+        if duration.is_zero() {
+            return;
+        }
+
+        manager.sleep(duration, context);
+        manager.switch_next(context);
+    })
+}
+
+pub fn set_affinity(pid: ProcessId, mask: u64) -> bool {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        get_process_manager().set_affinity(pid, mask)
+    })
+}
+
+pub fn get_affinity(pid: ProcessId) -> Option<u64> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        get_process_manager().get_affinity(pid)
+    })
+}
+
+pub fn get_rlimit(resource: usize) -> Option<u64> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        get_process_manager().get_rlimit(resource)
+    })
+}
+
+pub fn set_rlimit(resource: usize, limit: u64) -> bool {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        get_process_manager().set_rlimit(resource, limit)
+    })
+}
+
+pub fn set_nice(pid: ProcessId, nice: i8) -> bool {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        get_process_manager().set_nice(pid, nice)
+    })
+}
+
+pub fn get_nice(pid: ProcessId) -> Option<i8> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        get_process_manager().get_nice(pid)
+    })
+}
+
+/// Handle the pending signals before returning to user mode with `context`.
+pub fn handle_signals(context: &mut ProcessContext) {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let manager = get_process_manager();
+        // the process switched to may have signals pending too
+        while context.is_user() && manager.handle_signal(context) {}
+    })
+}
+
+/// Report a CPU exception of the current process in user mode with `sig`,
+/// it is terminated unless it handles the signal.
+pub fn user_fault(name: &str, sig: usize, addr: Option<VirtAddr>, context: &mut ProcessContext) {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let current = get_process_manager().current();
+        let mut inner = current.write();
+
+        let rip = context.stack_frame.instruction_pointer;
+        match addr {
+            Some(addr) => warn!(
+                "EXCEPTION: {} in {}#{} at {:#x}, accessing {:#x}",
+                name, inner.name(), current.pid(), rip, addr
+            ),
+            None => warn!("EXCEPTION: {} in {}#{} at {:#x}", name, inner.name(), current.pid(), rip),
+        }
+
+        inner.signals_mut().force(sig);
+    });
+
+    handle_signals(context);
+}
+
+pub fn send_signal(pid: ProcessId, sig: usize) -> bool {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        get_process_manager().send_signal(pid, sig)
+    })
+}
+
+pub fn sigaction(sig: usize, action: SigAction) -> Option<SigAction> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        get_process_manager().set_sigaction(sig, action)
+    })
+}
+
+pub fn sigprocmask(how: usize, set: u64) -> Option<u64> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        get_process_manager().sigprocmask(how, set)
+    })
+}
+
+pub fn sigreturn(context: &mut ProcessContext) {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let manager = get_process_manager();
+        if !manager.sigreturn(context) {
+            // there is no context to go back to
+            warn!("Process #{} returned with a bad signal frame.", get_pid());
+            manager.kill_current(signal::exit_code(syscall_def::signal::SIGSEGV));
+            manager.switch_next(context);
+        }
+    })
+}
+
 #[inline]
 pub fn still_alive(pid: ProcessId) -> bool {
     x86_64::instructions::interrupts::without_interrupts(|| {
@@ -1,13 +1,23 @@
-use crate::memory::*;
+use super::vm::USER_SPACE_END;
+use crate::memory::{swap, *};
+use core::ops::Range;
 use core::ptr::copy_nonoverlapping;
 
 use alloc::sync::Arc;
+use spin::Once;
 use x86_64::{
     registers::control::{Cr3, Cr3Flags},
-    structures::paging::*,
+    structures::paging::{page_table::PageTableEntry, *},
     VirtAddr,
 };
 
+/// Marks a user page shared by fork, it is copied on the first write
+pub const COPY_ON_WRITE: PageTableFlags = PageTableFlags::BIT_9;
+
+/// The page table of the kernel, the first one read from Cr3.
+/// User processes share the entries they clone from it.
+static KERNEL_PAGE_TABLE: Once<PhysFrame> = Once::new();
+
 pub struct Cr3RegValue {
     pub addr: PhysFrame,
     pub flags: Cr3Flags,
@@ -26,18 +36,17 @@ pub struct PageTableContext {
 impl PageTableContext {
     pub fn new() -> Self {
         let (frame, flags) = Cr3::read();
+        KERNEL_PAGE_TABLE.call_once(|| frame);
         Self {
             reg: Arc::new(Cr3RegValue::new(frame, flags)),
         }
     }
 
-    /// Create a new page table object based on current page table.
-    pub fn clone_level_4(&self) -> Self {
+    /// Create a new page table object based on current page table,
+    /// None if out of memory.
+    pub fn clone_level_4(&self) -> Option<Self> {
         // 1. alloc new page table
-        let mut frame_alloc = crate::memory::get_frame_alloc_for_sure();
-        let page_table_addr = frame_alloc
-            .allocate_frame()
-            .expect("Cannot alloc page table for new process.");
+        let page_table_addr = crate::memory::get_frame_alloc_blocking().allocate_frame()?;
 
         // 2. copy current page table to new page table
         unsafe {
@@ -49,9 +58,9 @@ impl PageTableContext {
         }
 
         // 3. create page table object
-        Self {
+        Some(Self {
             reg: Arc::new(Cr3RegValue::new(page_table_addr, Cr3Flags::empty())),
-        }
+        })
     }
 
     /// Load the page table to Cr3 register.
@@ -75,12 +84,206 @@ impl PageTableContext {
         Arc::strong_count(&self.reg)
     }
 
-    pub fn fork(&self) -> Self {
-        // forked process shares the page table
-        Self {
-            reg: self.reg.clone(),
+    /// The entry mapping `page`, if its page tables exist
+    pub fn page_entry(&mut self, page: Page) -> Option<&mut PageTableEntry> {
+        page_entry(unsafe { page_table_mut(self.reg.addr) }, page)
+    }
+
+    /// Visit the present user pages from `start` on, wrapping around
+    /// to the first one, until `f` returns true for a page, which is returned.
+    pub fn find_user_page(
+        &mut self,
+        start: Page,
+        mut f: impl FnMut(&mut PageTableEntry) -> bool,
+    ) -> Option<Page> {
+        let kernel = *KERNEL_PAGE_TABLE.get().unwrap();
+        let (p4, kernel_p4) = unsafe { (page_table_mut(self.reg.addr), page_table_mut(kernel)) };
+        let start = start.start_address().as_u64();
+
+        for range in [start..USER_SPACE_END, 0..start] {
+            for index in 0..256 {
+                let entry = &mut p4[index];
+                let base = index as u64 * L4_ENTRY_SIZE;
+
+                // shared with the kernel, e.g. the identity mapping of UEFI
+                if entry.is_unused()
+                    || entry.addr() == kernel_p4[index].addr()
+                    || base + L4_ENTRY_SIZE <= range.start
+                    || base >= range.end
+                {
+                    continue;
+                }
+
+                let table = unsafe { page_table_mut(entry.frame().unwrap()) };
+                if let Some(page) = find_page(table, 3, base, &range, &mut f) {
+                    return Some(page);
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Create the page table of a forked child, None if out of memory.
+    ///
+    /// The user part is copied, and the pages in it are shared: writable ones
+    /// become read-only with `COPY_ON_WRITE` in both page tables, and are
+    /// copied on the first write, see `ProcessVm::handle_page_fault`.
+    ///
+    /// If it runs out of memory on the way, the part copied is returned
+    /// as the error, to be cleaned up like the page table of an exited process.
+    pub fn fork(&self) -> Option<Result<Self, Self>> {
+        let child = self.clone_level_4()?;
+        let kernel = *KERNEL_PAGE_TABLE.get().unwrap();
+        let mut frame_alloc = crate::memory::get_frame_alloc_blocking();
+
+        let (parent_p4, child_p4, kernel_p4) = unsafe {
+            (
+                page_table_mut(self.reg.addr),
+                page_table_mut(child.reg.addr),
+                page_table_mut(kernel),
+            )
+        };
+
+        // only the entries shared with the kernel are kept,
+        // the others are left unused if it fails before them
+        for index in 0..256 {
+            child_p4[index] = kernel_p4[index].clone();
         }
+
+        // the upper half is the kernel's
+        let complete = (0..256).all(|index| {
+            let entry = &mut parent_p4[index];
+
+            // shared with the kernel, e.g. the identity mapping of UEFI
+            entry.is_unused()
+                || entry.addr() == kernel_p4[index].addr()
+                || fork_page_table(entry, &mut child_p4[index], 3, &mut frame_alloc)
+        });
+
+        // the parent may not write its shared pages anymore
+        x86_64::instructions::tlb::flush_all();
+
+        Some(if complete { Ok(child) } else { Err(child) })
+    }
+}
+
+/// The memory mapped by an entry of the level 4 table, 512 GiB
+const L4_ENTRY_SIZE: u64 = 1 << 39;
+
+unsafe fn page_table_mut(frame: PhysFrame) -> &'static mut PageTable {
+    unsafe { &mut *(physical_to_virtual(frame.start_address().as_u64()) as *mut PageTable) }
+}
+
+/// Walk the level 4 table `p4` to the entry mapping the 4 KiB `page`
+pub fn page_entry(p4: &mut PageTable, page: Page) -> Option<&mut PageTableEntry> {
+    let mut table = p4;
+
+    for index in [page.p4_index(), page.p3_index(), page.p2_index()] {
+        let entry = &table[index];
+        if entry.flags().contains(PageTableFlags::HUGE_PAGE) {
+            return None;
+        }
+
+        table = unsafe { page_table_mut(entry.frame().ok()?) };
     }
+
+    Some(&mut table[page.p1_index()])
+}
+
+/// Visit the present pages in `range` mapped by `table` of `level`,
+/// which starts at `base`, see `PageTableContext::find_user_page`.
+fn find_page(
+    table: &mut PageTable,
+    level: u8,
+    base: u64,
+    range: &Range<u64>,
+    f: &mut impl FnMut(&mut PageTableEntry) -> bool,
+) -> Option<Page> {
+    let size = PAGE_SIZE << (9 * (level - 1));
+
+    for (index, entry) in table.iter_mut().enumerate() {
+        let start = base + index as u64 * size;
+        let flags = entry.flags();
+
+        if !flags.contains(PageTableFlags::PRESENT) || start + size <= range.start || start >= range.end {
+            continue;
+        }
+
+        if level == 1 {
+            if f(entry) {
+                return Some(Page::containing_address(VirtAddr::new(start)));
+            }
+            continue;
+        }
+
+        if flags.contains(PageTableFlags::HUGE_PAGE) {
+            continue;
+        }
+
+        let table = unsafe { page_table_mut(entry.frame().unwrap()) };
+        if let Some(page) = find_page(table, level - 1, start, range, f) {
+            return Some(page);
+        }
+    }
+
+    None
+}
+
+/// Copy the page table `entry` points to, which is of `level`, to `copy`,
+/// and share the pages mapped by it. Returns false if out of memory,
+/// the part copied is already in `copy`.
+fn fork_page_table(
+    entry: &mut PageTableEntry,
+    copy: &mut PageTableEntry,
+    level: u8,
+    frame_alloc: &mut BuddyFrameAllocator,
+) -> bool {
+    let Some(frame) = frame_alloc.allocate_frame() else {
+        warn!("Cannot alloc page table for forked process.");
+        return false;
+    };
+
+    let (table, copy_table) = unsafe { (page_table_mut(entry.frame().unwrap()), page_table_mut(frame)) };
+    copy_table.zero();
+    copy.set_frame(frame, entry.flags());
+
+    for (entry, copy) in table.iter_mut().zip(copy_table.iter_mut()) {
+        if entry.is_unused() {
+            continue;
+        }
+
+        if level > 1 {
+            if entry.flags().contains(PageTableFlags::HUGE_PAGE) {
+                warn!("Huge user page is not shared by fork: {:?}", entry);
+                continue;
+            }
+
+            if !fork_page_table(entry, copy, level - 1, frame_alloc) {
+                return false;
+            }
+            continue;
+        }
+
+        // the slot in the swap space is shared instead
+        if swap::is_swapped(entry) {
+            swap::share(entry);
+            *copy = entry.clone();
+            continue;
+        }
+
+        let mut flags = entry.flags();
+        if flags.contains(PageTableFlags::WRITABLE) {
+            flags.remove(PageTableFlags::WRITABLE);
+            flags.insert(COPY_ON_WRITE);
+            entry.set_flags(flags);
+        }
+
+        frame_alloc.share_frame(entry.frame().unwrap());
+        *copy = entry.clone();
+    }
+
+    true
 }
 
 impl core::fmt::Debug for PageTableContext {
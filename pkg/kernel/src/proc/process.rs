@@ -3,6 +3,10 @@ use crate::{humanized_size, memory::*};
 use alloc::sync::{Arc, Weak};
 use alloc::vec::Vec;
 use spin::*;
+use crate::time::{cancel_timer, TimerId};
+use signal::{SignalFrame, SignalState};
+use syscall_def::mman::{MmapArgs, MAP_ANONYMOUS};
+use syscall_def::signal::sigbit;
 use x86_64::structures::paging::mapper::MapToError;
 use x86_64::structures::paging::page::PageRange;
 use x86_64::structures::paging::*;
@@ -23,6 +27,14 @@ pub struct ProcessInner {
     exit_code: Option<isize>,
     proc_data: Option<ProcessData>,
     proc_vm: Option<ProcessVm>,
+    /// Bit `n` allows the process to run on the CPU with APIC ID `n`
+    affinity: u64,
+    sched: SchedInfo,
+    /// Counts the waits, so a timeout only ends the wait it was set for
+    wait_seq: u64,
+    /// The timer ending the current wait
+    timeout: Option<TimerId>,
+    signals: SignalState,
 }
 
 impl Process {
@@ -41,6 +53,11 @@ impl Process {
         self.inner.read()
     }
 
+    #[inline]
+    pub fn try_write(&self) -> Option<RwLockWriteGuard<'_, ProcessInner>> {
+        self.inner.try_write()
+    }
+
     pub fn new(
         name: String,
         parent: Option<Weak<Process>>,
@@ -63,6 +80,11 @@ impl Process {
             children: Vec::new(),
             proc_vm: Some(proc_vm),
             proc_data: Some(proc_data.unwrap_or_default()),
+            affinity: u64::MAX,
+            sched: SchedInfo::default(),
+            wait_seq: 0,
+            timeout: None,
+            signals: SignalState::new(),
         };
 
         trace!("New process {}#{} created.", &inner.name, pid);
@@ -74,13 +96,14 @@ impl Process {
         })
     }
 
-    pub fn fork(self: &Arc<Self>) -> Arc<Self> {
+    /// None if out of memory
+    pub fn fork(self: &Arc<Self>) -> Option<Arc<Self>> {
         // FIXME: lock inner as write
         let mut inner = self.inner.write();
         // FIXME: inner fork with parent weak ref
         let parent_weak = Arc::downgrade(self);
+        let child_inner = inner.fork(parent_weak)?;
         let child_pid = ProcessId::new();
-        let child_inner = inner.fork(parent_weak);
 
         // FOR DBG: maybe print the child process info
         //          e.g. parent, name, pid, etc.
@@ -93,7 +116,7 @@ impl Process {
         inner.context.set_rax(child.pid.0 as usize);
         // FIXME: mark the child as ready & return it
         child.write().pause();
-        child
+        Some(child)
     }
 
     pub fn kill(&self, ret: isize) {
@@ -139,6 +162,34 @@ impl ProcessInner {
         self.status = ProgramStatus::Blocked;
     }
 
+    pub fn stop(&mut self) {
+        self.status = ProgramStatus::Stopped;
+    }
+
+    pub fn affinity(&self) -> u64 {
+        self.affinity
+    }
+
+    pub fn set_affinity(&mut self, mask: u64) {
+        self.affinity = mask;
+    }
+
+    pub fn sched(&self) -> &SchedInfo {
+        &self.sched
+    }
+
+    pub fn sched_mut(&mut self) -> &mut SchedInfo {
+        &mut self.sched
+    }
+
+    pub fn signals(&self) -> &SignalState {
+        &self.signals
+    }
+
+    pub fn signals_mut(&mut self) -> &mut SignalState {
+        &mut self.signals
+    }
+
     pub fn exit_code(&self) -> Option<isize> {
         self.exit_code
     }
@@ -147,7 +198,7 @@ impl ProcessInner {
         self.context.set_rax(ret as usize);
     }
 
-    pub fn clone_page_table(&self) -> PageTableContext {
+    pub fn clone_page_table(&self) -> Option<PageTableContext> {
         self.proc_vm.as_ref().unwrap().page_table.clone_level_4()
     }
 
@@ -175,6 +226,84 @@ impl ProcessInner {
         self.pause();
     }
 
+    /// Save the context and mark the process as blocked,
+    /// returns the sequence number of this wait.
+    ///
+    /// Must be done while holding the lock that publishes the process to a
+    /// wait queue, so a waker on another CPU always finds it blocked.
+    pub(super) fn save_and_block(&mut self, context: &ProcessContext) -> u64 {
+        self.tick();
+        self.save(context);
+        self.block();
+        self.wait_seq += 1;
+        self.wait_seq
+    }
+
+    /// Record the timer ending the wait `seq`, if it is still going on.
+    pub(super) fn set_timeout(&mut self, seq: u64, id: TimerId) {
+        if self.status == ProgramStatus::Blocked && self.wait_seq == seq {
+            self.timeout = Some(id);
+        }
+    }
+
+    /// Called when the timer of the wait `seq` expires,
+    /// returns false if the wait is already over.
+    pub(super) fn take_timeout(&mut self, seq: u64) -> bool {
+        if self.status == ProgramStatus::Blocked && self.wait_seq == seq {
+            self.timeout = None;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Cancel the timer of the current wait, if any.
+    pub(super) fn cancel_timeout(&mut self) {
+        if let Some(id) = self.timeout.take() {
+            cancel_timer(id);
+        }
+    }
+
+    /// Redirect `context` to the handler of `sig` with a signal frame pushed
+    /// on the user stack, returns false if the stack can not take the frame.
+    pub(super) fn push_signal_frame(
+        &mut self,
+        context: &mut ProcessContext,
+        sig: usize,
+        handler: VirtAddr,
+        restorer: VirtAddr,
+    ) -> bool {
+        let addr = SignalFrame::address(context);
+        if !self.vm_mut().check_user_range(addr, SignalFrame::SIZE, true) {
+            return false;
+        }
+
+        let frame = SignalFrame::new(sig, restorer, self.signals.blocked(), context);
+        unsafe {
+            addr.as_mut_ptr::<SignalFrame>().write(frame);
+        }
+
+        // the signal is blocked while its handler runs
+        let blocked = self.signals.blocked() | sigbit(sig);
+        self.signals.set_blocked(blocked);
+        context.enter_handler(handler, addr, sig);
+        true
+    }
+
+    /// Resume the context saved before the handler that returned,
+    /// returns false if the frame is not in user memory.
+    pub(super) fn pop_signal_frame(&mut self, context: &mut ProcessContext) -> bool {
+        let addr = SignalFrame::address_on_return(context);
+        if !self.vm_mut().check_user_range(addr, SignalFrame::SIZE, false) {
+            return false;
+        }
+
+        let frame = unsafe { addr.as_ptr::<SignalFrame>().read() };
+        context.restore_user(frame.context());
+        self.signals.set_blocked(frame.blocked());
+        true
+    }
+
     /// Restore the process's context
     /// mark the process as running
     pub(super) fn restore(&mut self, context: &mut ProcessContext) {
@@ -189,24 +318,21 @@ impl ProcessInner {
         self.parent.as_ref().and_then(|p| p.upgrade())
     }
 
-    pub fn fork(&mut self, parent: Weak<Process>) -> ProcessInner {
+    pub fn fork(&mut self, parent: Weak<Process>) -> Option<ProcessInner> {
         // FIXME: fork the process virtual memory struct
-        let child_vm = self.vm().fork(self.children.len() as u64 + 1);
-        // FIXME: calculate the real stack offset
-        let offset = child_vm.stack.offset(&self.vm().stack);
+        let child_vm = self.vm().fork()?;
 
-        // FIXME: update `rsp` in interrupt stack frame
+        // the stack of the child is at the same address, `rsp` is kept
         // FIXME: set the return value 0 for child with `context.set_rax`
 
         let mut child_context = self.context.clone();
-        child_context.set_stack_offset(offset);
         child_context.set_rax(0);
 
         // FIXME: clone the process data struct
 
         // FIXME: construct the child process inner
 
-        Self {
+        Some(Self {
             name: self.name.clone(),
             parent: Some(parent),
             children: Vec::new(),
@@ -216,7 +342,15 @@ impl ProcessInner {
             exit_code: None,
             proc_data: self.proc_data.clone(),
             proc_vm: Some(child_vm),
-        }
+            affinity: self.affinity,
+            sched: SchedInfo {
+                nice: self.sched.nice,
+                ..Default::default()
+            },
+            wait_seq: 0,
+            timeout: None,
+            signals: self.signals.fork(),
+        })
 
         // NOTE: return inner because there's no pid record in inner
     }
@@ -224,6 +358,7 @@ impl ProcessInner {
     pub fn kill(&mut self, ret: isize) {
         // FIXME: set exit code
         self.exit_code = Some(ret);
+        self.cancel_timeout();
 
         // FIXME: set status to dead
         self.status = ProgramStatus::Dead;
@@ -237,13 +372,27 @@ impl ProcessInner {
         self.context.init_stack_frame(entry, stack_top);
     }
 
-    pub fn load_elf(&mut self, elf: &ElfFile) {
-        self.vm_mut().load_elf(elf);
+    pub fn load_elf(&mut self, elf: &ElfFile, image: Image) -> Result<(), &'static str> {
+        self.vm_mut().load_elf(elf, image)
     }
 
     pub fn brk(&self, addr: Option<VirtAddr>) -> Option<VirtAddr> {
         self.vm().brk(addr)
     }
+
+    /// Map a file descriptor or anonymous memory, see `ProcessVm::mmap`
+    pub fn mmap(&mut self, args: &MmapArgs) -> Option<VirtAddr> {
+        let file = if args.flags & MAP_ANONYMOUS != 0 {
+            None
+        } else {
+            let fd = u8::try_from(args.fd).ok()?;
+            let file = self.get_resource(fd).filter(|res| res.lock().is_file())?;
+            Some((file, args.offset))
+        };
+
+        self.vm_mut()
+            .mmap(args.addr as u64, args.len as u64, args.prot, args.flags, file)
+    }
 }
 
 impl core::ops::Deref for Process {
@@ -296,11 +445,13 @@ impl core::fmt::Display for Process {
         let (size, unit) = humanized_size(inner.proc_vm.as_ref().map_or(0, |vm| vm.memory_usage()));
         write!(
             f,
-            " #{:-3} | #{:-3} | {:12} | {:7} | {:>5.1} {} | {:?}",
+            " #{:-3} | #{:-3} | {:12} | {:7} | {:>4} | {:>3} | {:>5.1} {} | {:?}",
             self.pid.0,
             inner.parent().map(|p| p.pid.0).unwrap_or(0),
             inner.name,
             inner.ticks_passed,
+            inner.sched.nice,
+            inner.sched.level,
             size,
             unit,
             inner.status
@@ -1,45 +1,141 @@
-use core::sync::atomic::{AtomicU16, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicU16, AtomicU64, Ordering};
 
-use crate::proc::ProcessId;
-use alloc::{string::String, vec::Vec};
+use crate::drivers::acpi;
+use crate::proc::sched::{self, Scheduler};
+use crate::proc::{ProcessContext, ProcessId};
+use crate::time;
+use alloc::{boxed::Box, string::String, vec, vec::Vec};
+use spin::{Mutex, Once};
 use x86::cpuid::CpuId;
+use x86_64::VirtAddr;
 
-const MAX_CPU_COUNT: usize = 4;
+/// Size of the stack used by the idle loop of each CPU
+const IDLE_STACK_SIZE: usize = 0x4000;
 
-#[allow(clippy::declare_interior_mutable_const)]
-const EMPTY: Processor = Processor::new(); // means no process
+/// `idle_since` of a processor running a process
+const NOT_IDLE: u64 = u64::MAX;
 
-static PROCESSORS: [Processor; MAX_CPU_COUNT] = [EMPTY; MAX_CPU_COUNT];
+/// Indexed by APIC ID, sized by the CPUs listed in the ACPI MADT
+static PROCESSORS: Once<Vec<Processor>> = Once::new();
 
-/// Returns the current processor based on the current APIC ID
-fn current() -> &'static Processor {
-    let cpuid = CpuId::new()
+pub fn init() {
+    let count = acpi::max_apic_id() as usize + 1;
+    PROCESSORS.call_once(|| {
+        (0..count as u32)
+            .map(|id| Processor::new(acpi::cpus().iter().any(|cpu| cpu.apic_id == id)))
+            .collect()
+    });
+
+    // the BSP is running the kernel process
+    set_online();
+}
+
+#[inline]
+fn processors() -> &'static [Processor] {
+    PROCESSORS.get().expect("Processors have not been initialized")
+}
+
+/// APIC ID of the current processor
+#[inline]
+pub fn current_id() -> usize {
+    CpuId::new()
         .get_feature_info()
         .unwrap()
-        .initial_local_apic_id() as usize;
+        .initial_local_apic_id() as usize
+}
+
+/// Returns the current processor based on the current APIC ID
+#[inline]
+pub fn current() -> &'static Processor {
+    &processors()[current_id()]
+}
+
+/// The processors taking processes, with their APIC ID
+pub fn online() -> impl Iterator<Item = (usize, &'static Processor)> {
+    processors().iter().enumerate().filter(|(_, p)| p.is_online())
+}
 
-    &PROCESSORS[cpuid]
+/// Bit `n` is set if the CPU with APIC ID `n` is online,
+/// in the same layout as the affinity mask of a process.
+pub fn online_mask() -> u64 {
+    online().fold(0, |mask, (id, _)| mask | 1u64.checked_shl(id as u32).unwrap_or(0))
 }
 
 pub fn print_processors() -> String {
-    alloc::format!(
-        "CPUs   : {}\n",
-        PROCESSORS
-            .iter()
-            .enumerate()
-            .filter(|(_, p)| !p.is_free())
-            .map(|(i, p)| alloc::format!("[{}: {}]", i, p.get_pid().unwrap()))
-            .collect::<Vec<_>>()
-            .join(", ")
-    )
+    let now = time::monotonic_ns();
+
+    let mut output = String::new();
+    for (i, p) in online() {
+        let state = match p.get_pid() {
+            Some(pid) => alloc::format!("#{}", pid),
+            None => String::from("idle"),
+        };
+
+        let uptime = now.saturating_sub(p.online_since.load(Ordering::Relaxed));
+        let idle = p.idle_ns(now) as f64 / uptime.max(1) as f64 * 100.0;
+
+        output += &alloc::format!(
+            "CPU {:<2} : {:<5} | {:>5.1}% idle | Queue: {:?}\n",
+            i,
+            state,
+            idle,
+            p.ready_queue.lock().pids()
+        );
+    }
+
+    output
+}
+
+/// Number of CPUs that have started scheduling
+pub fn online_count() -> usize {
+    online().count()
 }
 
-/// Processor holds the current process id
-pub struct Processor(AtomicU16);
+/// Processor holds the current process id and the processes ready to run on it
+pub struct Processor {
+    pid: AtomicU16,
+    online: AtomicBool,
+    ready_queue: Mutex<Box<dyn Scheduler>>,
+    /// Monotonic nanoseconds when the idle loop was entered, `NOT_IDLE` if running a process
+    idle_since: AtomicU64,
+    /// Nanoseconds spent in the idle loop before `idle_since`
+    idle_ns: AtomicU64,
+    online_since: AtomicU64,
+    /// Enters `idle_loop` on a dedicated stack, restored when no process is ready.
+    /// `None` if there is no CPU with this APIC ID.
+    idle: Option<ProcessContext>,
+}
 
 impl Processor {
-    pub const fn new() -> Self {
-        Self(AtomicU16::new(0))
+    pub fn new(present: bool) -> Self {
+        let idle = present.then(|| {
+            let stack = vec![0u8; IDLE_STACK_SIZE].leak();
+            // aligned as if `idle_loop` was called
+            let stack_top = VirtAddr::from_ptr(stack.as_ptr()) + IDLE_STACK_SIZE as u64;
+            let mut context = ProcessContext::default();
+            context.init_kernel_frame(
+                VirtAddr::new(idle_loop as *const () as u64),
+                stack_top.align_down(16u64) - 8u64,
+            );
+            context
+        });
+
+        Self {
+            pid: AtomicU16::new(0),
+            online: AtomicBool::new(false),
+            ready_queue: Mutex::new(sched::new_scheduler()),
+            idle_since: AtomicU64::new(NOT_IDLE),
+            idle_ns: AtomicU64::new(0),
+            online_since: AtomicU64::new(0),
+            idle,
+        }
+    }
+}
+
+/// Runs on a CPU when no process is ready, until the next interrupt reschedules.
+extern "C" fn idle_loop() -> ! {
+    loop {
+        x86_64::instructions::interrupts::enable_and_hlt();
     }
 }
 
@@ -53,20 +149,83 @@ pub fn get_pid() -> ProcessId {
     current().get_pid().expect("No current process")
 }
 
+/// The current process id, `None` if the CPU is idle
+#[inline]
+pub fn try_get_pid() -> Option<ProcessId> {
+    current().get_pid()
+}
+
+/// Marks the current CPU as idle and switches to its idle loop.
+pub fn enter_idle(context: &mut ProcessContext) {
+    let processor = current();
+    processor.pid.store(0, Ordering::Relaxed);
+    let _ = processor.idle_since.compare_exchange(
+        NOT_IDLE,
+        time::monotonic_ns(),
+        Ordering::Relaxed,
+        Ordering::Relaxed,
+    );
+    processor
+        .idle
+        .as_ref()
+        .expect("No idle context for current processor")
+        .restore(context);
+}
+
+/// Called by each processor when it is ready to schedule.
+pub fn set_online() {
+    let processor = current();
+    processor
+        .online_since
+        .store(time::monotonic_ns(), Ordering::Relaxed);
+    processor.online.store(true, Ordering::Relaxed);
+}
+
 impl Processor {
     #[inline]
     pub fn is_free(&self) -> bool {
-        self.0.load(Ordering::Relaxed) == 0
+        self.pid.load(Ordering::Relaxed) == 0
+    }
+
+    #[inline]
+    pub fn is_online(&self) -> bool {
+        self.online.load(Ordering::Relaxed)
     }
 
+    /// Sets the running process and ends the idle period if any.
     #[inline]
     pub fn set_pid(&self, pid: ProcessId) {
-        self.0.store(pid.0, Ordering::Relaxed);
+        self.pid.store(pid.0, Ordering::Relaxed);
+
+        let since = self.idle_since.swap(NOT_IDLE, Ordering::Relaxed);
+        if since != NOT_IDLE {
+            let idle = time::monotonic_ns().saturating_sub(since);
+            self.idle_ns.fetch_add(idle, Ordering::Relaxed);
+        }
+    }
+
+    #[inline]
+    pub fn ready_queue(&self) -> &Mutex<Box<dyn Scheduler>> {
+        &self.ready_queue
+    }
+
+    /// Processes queued or running on this processor
+    pub fn load(&self) -> usize {
+        self.ready_queue.lock().len() + self.get_pid().is_some() as usize
+    }
+
+    /// Nanoseconds spent in the idle loop until `now`
+    pub fn idle_ns(&self, now: u64) -> u64 {
+        let idle = self.idle_ns.load(Ordering::Relaxed);
+        match self.idle_since.load(Ordering::Relaxed) {
+            NOT_IDLE => idle,
+            since => idle + now.saturating_sub(since),
+        }
     }
 
     #[inline]
     pub fn get_pid(&self) -> Option<ProcessId> {
-        let pid = self.0.load(Ordering::Relaxed);
+        let pid = self.pid.load(Ordering::Relaxed);
         if pid == 0 {
             None
         } else {
@@ -0,0 +1,144 @@
+//! Multi-level feedback queue
+//!
+//! - A process runs from the highest level its nice value allows.
+//! - Using up the time slice of a level moves it one level down,
+//!   and the slice doubles with each level.
+//! - Waking up after being blocked moves it one level up,
+//!   so I/O-bound processes stay responsive.
+//! - Every `BOOST_INTERVAL` ticks all processes go back to their highest level,
+//!   so CPU-bound ones are not starved.
+//! - A negative nice value lengthens the time slices.
+//!
+//! reference: https://pages.cs.wisc.edu/~remzi/OSTEP/cpu-sched-mlfq.pdf
+
+use super::*;
+use alloc::collections::VecDeque;
+
+const LEVELS: usize = 4;
+/// Time slice of each level in ticks
+const QUANTUM: [u32; LEVELS] = [1, 2, 4, 8];
+/// Ticks between priority boosts of a CPU
+const BOOST_INTERVAL: u32 = 100;
+
+#[derive(Clone, Copy)]
+struct Entry {
+    pid: ProcessId,
+    /// The level to boost to
+    top: u8,
+}
+
+pub struct Mlfq {
+    queues: [VecDeque<Entry>; LEVELS],
+    since_boost: u32,
+}
+
+/// The highest level a process may be at, nice 0 to 4 is the top level
+fn top_level(nice: i8) -> u8 {
+    (nice.max(0) as usize * LEVELS / (NICE_MAX as usize + 1)) as u8
+}
+
+fn quantum(info: &SchedInfo) -> u32 {
+    let scale = 1 + (-(info.nice.min(0) as i32)) as u32 / 10;
+    QUANTUM[info.level as usize] * scale
+}
+
+impl Mlfq {
+    pub fn new() -> Self {
+        Self {
+            queues: Default::default(),
+            since_boost: 0,
+        }
+    }
+
+    fn boost(&mut self) {
+        let entries: Vec<Entry> = self
+            .queues
+            .iter_mut()
+            .flat_map(|queue| queue.drain(..))
+            .collect();
+
+        for entry in entries {
+            self.queues[entry.top as usize].push_back(entry);
+        }
+    }
+}
+
+impl Default for Mlfq {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Scheduler for Mlfq {
+    fn name(&self) -> &'static str {
+        "mlfq"
+    }
+
+    fn enqueue(&mut self, pid: ProcessId, info: &mut SchedInfo, reason: Enqueue) {
+        let top = top_level(info.nice);
+
+        match reason {
+            Enqueue::New => info.level = top,
+            Enqueue::Preempted if info.slice_ticks >= quantum(info) => info.level += 1,
+            Enqueue::Woken => info.level = info.level.saturating_sub(1),
+            _ => {}
+        }
+
+        info.level = info.level.clamp(top, LEVELS as u8 - 1);
+        info.slice_ticks = 0;
+
+        self.queues[info.level as usize].push_back(Entry { pid, top });
+    }
+
+    fn dequeue(&mut self) -> Option<Queued> {
+        self.queues
+            .iter_mut()
+            .enumerate()
+            .find_map(|(level, queue)| {
+                queue.pop_front().map(|entry| Queued {
+                    pid: entry.pid,
+                    level: level as u8,
+                })
+            })
+    }
+
+    fn steal(&mut self, filter: &mut dyn FnMut(ProcessId) -> bool) -> Option<Queued> {
+        self.queues
+            .iter_mut()
+            .enumerate()
+            .rev()
+            .find_map(|(level, queue)| {
+                let index = queue.iter().rposition(|entry| filter(entry.pid))?;
+                queue.remove(index).map(|entry| Queued {
+                    pid: entry.pid,
+                    level: level as u8,
+                })
+            })
+    }
+
+    fn tick(&mut self, current: &mut SchedInfo) -> bool {
+        current.slice_ticks += 1;
+
+        self.since_boost += 1;
+        if self.since_boost >= BOOST_INTERVAL {
+            self.since_boost = 0;
+            self.boost();
+            current.level = top_level(current.nice);
+        }
+
+        let level = current.level as usize;
+        current.slice_ticks >= quantum(current)
+            || self.queues[..level].iter().any(|queue| !queue.is_empty())
+    }
+
+    fn pids(&self) -> Vec<ProcessId> {
+        self.queues
+            .iter()
+            .flat_map(|queue| queue.iter().map(|entry| entry.pid))
+            .collect()
+    }
+
+    fn len(&self) -> usize {
+        self.queues.iter().map(|queue| queue.len()).sum()
+    }
+}
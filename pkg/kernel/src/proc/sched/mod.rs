@@ -0,0 +1,128 @@
+//! Scheduling policies
+//!
+//! Each CPU has its own run queue implementing `Scheduler`, the policy is
+//! selected once at boot with `sched=rr` or `sched=mlfq` on the kernel
+//! command line. The state a policy keeps for a process lives in the process
+//! as `SchedInfo`, so it follows the process when it is stolen by another CPU.
+
+mod mlfq;
+mod rr;
+
+use super::ProcessId;
+use alloc::{boxed::Box, vec::Vec};
+use spin::Once;
+
+pub use mlfq::Mlfq;
+pub use rr::RoundRobin;
+
+/// Lowest and highest nice values, as on Linux
+pub const NICE_MIN: i8 = -20;
+pub const NICE_MAX: i8 = 19;
+
+/// Per-process scheduling state
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SchedInfo {
+    /// Set by the user, a lower value means a higher priority
+    pub nice: i8,
+    /// Current queue level, 0 is the highest priority
+    pub level: u8,
+    /// Ticks used in the current time slice
+    pub slice_ticks: u32,
+}
+
+/// Why a process is put into a run queue
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Enqueue {
+    /// Created by spawn or fork
+    New,
+    /// Preempted by the clock
+    Preempted,
+    /// Woken up after being blocked
+    Woken,
+    /// Gave up the CPU without blocking, or moved to another CPU
+    Yielded,
+}
+
+/// A process taken out of a run queue with the level it was queued at
+#[derive(Debug, Clone, Copy)]
+pub struct Queued {
+    pub pid: ProcessId,
+    pub level: u8,
+}
+
+/// A run queue with its scheduling policy
+pub trait Scheduler: Send {
+    fn name(&self) -> &'static str;
+
+    /// Queues a ready process, the policy may update its state
+    fn enqueue(&mut self, pid: ProcessId, info: &mut SchedInfo, reason: Enqueue);
+
+    /// Takes the process to run next
+    fn dequeue(&mut self) -> Option<Queued>;
+
+    /// Takes the queued process least likely to run soon that `filter` accepts,
+    /// used by an idle CPU to steal work.
+    fn steal(&mut self, filter: &mut dyn FnMut(ProcessId) -> bool) -> Option<Queued>;
+
+    /// Called on every clock tick with the state of the running process,
+    /// returns whether it should be preempted.
+    fn tick(&mut self, current: &mut SchedInfo) -> bool;
+
+    /// The queued processes in the order they would run
+    fn pids(&self) -> Vec<ProcessId>;
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Policy {
+    RoundRobin,
+    Mlfq,
+}
+
+impl Policy {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "rr" => Some(Self::RoundRobin),
+            "mlfq" => Some(Self::Mlfq),
+            _ => None,
+        }
+    }
+}
+
+static POLICY: Once<Policy> = Once::new();
+
+/// Selects the policy from the `sched=` option of the kernel command line,
+/// round-robin if not specified.
+pub fn init(cmdline: &str) {
+    let policy = match cmdline
+        .split_whitespace()
+        .find_map(|arg| arg.strip_prefix("sched="))
+    {
+        Some(name) => Policy::parse(name).unwrap_or_else(|| {
+            warn!("Unknown scheduling policy '{}', fallback to rr.", name);
+            Policy::RoundRobin
+        }),
+        None => Policy::RoundRobin,
+    };
+
+    POLICY.call_once(|| policy);
+    info!("Scheduler: {:?}", policy);
+}
+
+#[inline]
+pub fn policy() -> Policy {
+    *POLICY.get().unwrap_or(&Policy::RoundRobin)
+}
+
+/// Creates an empty run queue of the selected policy.
+pub fn new_scheduler() -> Box<dyn Scheduler> {
+    match policy() {
+        Policy::RoundRobin => Box::new(RoundRobin::new()),
+        Policy::Mlfq => Box::new(Mlfq::new()),
+    }
+}
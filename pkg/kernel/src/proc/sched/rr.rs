@@ -0,0 +1,55 @@
+use super::*;
+use alloc::collections::VecDeque;
+
+/// Round-robin with a time slice of one tick, nice values are ignored.
+pub struct RoundRobin {
+    queue: VecDeque<ProcessId>,
+}
+
+impl RoundRobin {
+    pub fn new() -> Self {
+        Self {
+            queue: VecDeque::new(),
+        }
+    }
+}
+
+impl Default for RoundRobin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Scheduler for RoundRobin {
+    fn name(&self) -> &'static str {
+        "rr"
+    }
+
+    fn enqueue(&mut self, pid: ProcessId, info: &mut SchedInfo, _reason: Enqueue) {
+        info.level = 0;
+        info.slice_ticks = 0;
+        self.queue.push_back(pid);
+    }
+
+    fn dequeue(&mut self) -> Option<Queued> {
+        self.queue.pop_front().map(|pid| Queued { pid, level: 0 })
+    }
+
+    fn steal(&mut self, filter: &mut dyn FnMut(ProcessId) -> bool) -> Option<Queued> {
+        let index = self.queue.iter().rposition(|pid| filter(*pid))?;
+        self.queue.remove(index).map(|pid| Queued { pid, level: 0 })
+    }
+
+    fn tick(&mut self, current: &mut SchedInfo) -> bool {
+        current.slice_ticks += 1;
+        true
+    }
+
+    fn pids(&self) -> Vec<ProcessId> {
+        self.queue.iter().copied().collect()
+    }
+
+    fn len(&self) -> usize {
+        self.queue.len()
+    }
+}
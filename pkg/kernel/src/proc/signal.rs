@@ -0,0 +1,209 @@
+//! POSIX-style signals
+//!
+//! A signal sent to a process is only recorded as pending, it is handled when
+//! the process is about to return to user mode, see `ProcessManager::handle_signal`.
+//! A handler is called on the user stack below a `SignalFrame` holding the
+//! interrupted context, and returns to the restorer registered with it,
+//! which calls `Sigreturn` to resume that context.
+
+use super::context::ProcessContextValue;
+use super::ProcessContext;
+use core::mem::size_of;
+use syscall_def::signal::*;
+use x86_64::VirtAddr;
+
+/// What a process does when it receives a signal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SigAction {
+    Default,
+    Ignore,
+    Handler { handler: VirtAddr, restorer: VirtAddr },
+}
+
+impl SigAction {
+    /// The value returned to the user by `Sigaction`
+    pub fn handler(&self) -> usize {
+        match self {
+            Self::Default => SIG_DFL,
+            Self::Ignore => SIG_IGN,
+            Self::Handler { handler, .. } => handler.as_u64() as usize,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DefaultAction {
+    Terminate,
+    Ignore,
+    Stop,
+    Continue,
+}
+
+pub fn default_action(sig: usize) -> DefaultAction {
+    match sig {
+        SIGCHLD => DefaultAction::Ignore,
+        SIGSTOP | SIGTSTP => DefaultAction::Stop,
+        SIGCONT => DefaultAction::Continue,
+        _ => DefaultAction::Terminate,
+    }
+}
+
+/// Exit code of a process terminated by `sig`, the same as shells report
+pub const fn exit_code(sig: usize) -> isize {
+    128 + sig as isize
+}
+
+/// Signals that can not be caught, blocked or ignored
+const UNCATCHABLE: u64 = sigbit(SIGKILL) | sigbit(SIGSTOP);
+
+/// Per-process signal state
+#[derive(Clone)]
+pub struct SignalState {
+    pending: u64,
+    blocked: u64,
+    actions: [SigAction; NSIG],
+}
+
+impl SignalState {
+    pub fn new() -> Self {
+        Self {
+            pending: 0,
+            blocked: 0,
+            actions: [SigAction::Default; NSIG],
+        }
+    }
+
+    /// The child of fork keeps the actions and the mask, but no pending signal
+    pub fn fork(&self) -> Self {
+        Self {
+            pending: 0,
+            ..self.clone()
+        }
+    }
+
+    /// Mark `sig` as pending, a stop discards a pending continue and vice versa.
+    pub fn raise(&mut self, sig: usize) {
+        match sig {
+            SIGSTOP | SIGTSTP => self.pending &= !sigbit(SIGCONT),
+            SIGCONT => self.pending &= !(sigbit(SIGSTOP) | sigbit(SIGTSTP)),
+            _ => {}
+        }
+
+        self.pending |= sigbit(sig);
+    }
+
+    /// Raise `sig` for a fault, it can not be blocked or ignored
+    /// since the faulting instruction would run again.
+    pub fn force(&mut self, sig: usize) {
+        if !self.is_deliverable(sig) {
+            self.blocked &= !sigbit(sig);
+            self.actions[sig] = SigAction::Default;
+        }
+
+        self.raise(sig);
+    }
+
+    /// Whether `sig` would be handled now, instead of staying pending
+    pub fn is_deliverable(&self, sig: usize) -> bool {
+        self.blocked & sigbit(sig) == 0 && self.actions[sig] != SigAction::Ignore
+    }
+
+    /// Take the lowest pending signal that is not blocked
+    pub fn take(&mut self) -> Option<usize> {
+        let ready = self.pending & !self.blocked;
+        if ready == 0 {
+            return None;
+        }
+
+        let sig = ready.trailing_zeros() as usize;
+        self.pending &= !sigbit(sig);
+        Some(sig)
+    }
+
+    pub fn action(&self, sig: usize) -> SigAction {
+        self.actions[sig]
+    }
+
+    /// Set the action of `sig` and return the old one,
+    /// `None` if the signal does not exist or can not be caught.
+    pub fn set_action(&mut self, sig: usize, action: SigAction) -> Option<SigAction> {
+        if sigbit(sig) == 0 || sigbit(sig) & UNCATCHABLE != 0 {
+            return None;
+        }
+
+        // an ignored signal is discarded, even if it is pending
+        if action == SigAction::Ignore
+            || (action == SigAction::Default && default_action(sig) == DefaultAction::Ignore)
+        {
+            self.pending &= !sigbit(sig);
+        }
+
+        Some(core::mem::replace(&mut self.actions[sig], action))
+    }
+
+    pub fn blocked(&self) -> u64 {
+        self.blocked
+    }
+
+    pub fn set_blocked(&mut self, mask: u64) {
+        self.blocked = mask & !UNCATCHABLE;
+    }
+}
+
+impl Default for SignalState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Saved on the user stack while a handler runs
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct SignalFrame {
+    /// Popped by the `ret` of the handler
+    restorer: u64,
+    sig: u64,
+    /// The mask before the handler is called
+    blocked: u64,
+    context: ProcessContextValue,
+}
+
+/// Leaf functions of the interrupted code may use it without moving `rsp`
+const RED_ZONE: u64 = 128;
+
+impl SignalFrame {
+    pub const SIZE: u64 = size_of::<Self>() as u64;
+
+    pub fn new(sig: usize, restorer: VirtAddr, blocked: u64, context: &ProcessContext) -> Self {
+        Self {
+            restorer: restorer.as_u64(),
+            sig: sig as u64,
+            blocked,
+            context: **context,
+        }
+    }
+
+    /// Where to put the frame below the user stack of `context`. The handler
+    /// is entered as if called, `rsp + 8` is aligned to 16 bytes.
+    pub fn address(context: &ProcessContext) -> VirtAddr {
+        let top = context
+            .stack_frame
+            .stack_pointer
+            .as_u64()
+            .wrapping_sub(RED_ZONE + Self::SIZE);
+        VirtAddr::new_truncate((top & !0xf).wrapping_sub(8))
+    }
+
+    /// The frame the handler returned from, its `ret` has popped the restorer.
+    pub fn address_on_return(context: &ProcessContext) -> VirtAddr {
+        VirtAddr::new_truncate(context.stack_frame.stack_pointer.as_u64().wrapping_sub(8))
+    }
+
+    pub fn blocked(&self) -> u64 {
+        self.blocked
+    }
+
+    pub fn context(&self) -> &ProcessContextValue {
+        &self.context
+    }
+}
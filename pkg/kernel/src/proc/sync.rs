@@ -53,6 +53,16 @@ impl Semaphore {
         }
     }
 
+    /// Remove the process from the wait queue, e.g. when its wait times out.
+    ///
+    /// Returns false if it is not waiting, i.e. it has been signaled already.
+    pub fn cancel_wait(&mut self, pid: ProcessId) -> bool {
+        match self.wait_queue.iter().position(|&waiter| waiter == pid) {
+            Some(index) => self.wait_queue.remove(index).is_some(),
+            None => false,
+        }
+    }
+
     /// Signal the semaphore (release/up/verhogen)
     ///
     /// if the wait queue is not empty, then pop a process from the wait queue
@@ -123,6 +133,13 @@ impl SemaphoreSet {
             SemaphoreResult::NotExist
         }
     }
+
+    /// Cancel the wait of `pid` on the semaphore, see `Semaphore::cancel_wait`
+    pub fn cancel_wait(&self, key: u32, pid: ProcessId) -> bool {
+        self.sems
+            .get(&SemaphoreId::new(key))
+            .is_some_and(|sem| sem.lock().cancel_wait(pid))
+    }
 }
 
 impl core::fmt::Display for Semaphore {
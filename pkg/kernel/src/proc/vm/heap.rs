@@ -7,7 +7,7 @@ use x86_64::{
     VirtAddr,
 };
 
-use super::{FrameAllocatorRef, MapperRef};
+use super::{unmap_pages, FrameAllocatorRef, MapperRef};
 
 // user process runtime heap
 // 0x100000000 bytes -> 4GiB
@@ -40,16 +40,21 @@ impl Heap {
         }
     }
 
+    /// The child of fork has its own heap end,
+    /// the pages are copied on write.
     pub fn fork(&self) -> Self {
         Self {
             base: self.base,
-            end: self.end.clone(),
+            end: Arc::new(AtomicU64::new(self.end.load(Ordering::Relaxed))),
         }
     }
 
+    /// Move the end of the heap to `new_end`, the heap may only grow
+    /// to `limit` bytes. Returns the new end, or the current one if it is None.
     pub fn brk(
         &self,
         new_end: Option<VirtAddr>,
+        limit: u64,
         mapper: MapperRef,
         alloc: FrameAllocatorRef,
     ) -> Option<VirtAddr> {
@@ -65,32 +70,33 @@ impl Heap {
             return None; // Invalid address
         }
 
-        let new_end_page = Page::containing_address(new_end);
+        // the pages of [base, end) are mapped
         let cur_end = self.end.load(Ordering::Relaxed);
-        let cur_end_page = Page::containing_address(VirtAddr::new(cur_end));
+        let cur_top = top_page(cur_end);
+        let new_top = top_page(new_end.as_u64());
 
         // NOTE: print the heap difference for debugging
         debug!("Heap end addr: {:#x} -> {:#x}", cur_end, new_end.as_u64());
-        debug!(
-            "Heap end page: {:#x} -> {:#x}",
-            cur_end_page.start_address().as_u64(),
-            new_end_page.start_address().as_u64()
-        );
 
         // FIXME: do the actual mapping or unmapping
-        if new_end_page > cur_end_page {
-            let start_addr = cur_end_page.start_address();
-            elf::map_range(
-                start_addr.as_u64(),
-                new_end_page - cur_end_page + 1,
-                mapper,
-                alloc,
-                true,
-            ).expect("Failed to map new heap pages");
-        } else if new_end_page < cur_end_page {
-            let page_range = Page::range_inclusive(new_end_page, cur_end_page);
-
-            elf::unmap_range(page_range, mapper, alloc, true).unwrap();
+        if new_top > cur_top {
+            if new_top.start_address() - self.base > limit {
+                debug!("Heap end addr {:#x} is over the heap limit", new_end.as_u64());
+                return None;
+            }
+
+            for page in Page::range(cur_top, new_top) {
+                let mapped = elf::map_range(page.start_address().as_u64(), 1, mapper, alloc, true);
+
+                // out of memory, the heap is left as it was
+                if mapped.is_err() {
+                    unmap_pages(Page::range(cur_top, page), mapper, alloc);
+                    return None;
+                }
+            }
+        } else if new_top < cur_top {
+            // the pages swapped out are not mapped
+            unmap_pages(Page::range(new_top, cur_top), mapper, alloc);
         }
 
         // FIXME: update the end address
@@ -115,19 +121,21 @@ impl Heap {
         let end_addr = self.end.swap(self.base.as_u64(), Ordering::Relaxed);
 
         let start_page = Page::containing_address(self.base);
-        let end_page = Page::containing_address(VirtAddr::new(end_addr));
-        let range = Page::range_inclusive(start_page, end_page);
-
-        elf::unmap_range(range, mapper, dealloc, true)?;
+        unmap_pages(Page::range(start_page, top_page(end_addr)), mapper, dealloc);
 
         Ok(())
     }
 
     pub fn memory_usage(&self) -> u64 {
-        self.end.load(Ordering::Relaxed) - self.base.as_u64()
+        top_page(self.end.load(Ordering::Relaxed)).start_address() - self.base
     }
 }
 
+/// The first page after the heap ending at `end`
+fn top_page(end: u64) -> Page {
+    Page::containing_address(VirtAddr::new(x86_64::align_up(end, crate::memory::PAGE_SIZE)))
+}
+
 impl core::fmt::Debug for Heap {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         f.debug_struct("Heap")
@@ -1,21 +1,27 @@
-use alloc::{format, vec::Vec};
+use alloc::{format, sync::Arc, vec::Vec};
 use boot::KernelPages;
+use core::ptr::copy_nonoverlapping;
 use x86_64::{
     structures::paging::{
-        mapper::{CleanUp, UnmapError},
+        mapper::{CleanUp, MappedFrame, TranslateResult, UnmapError},
         page::*,
+        page_table::PageTableEntry,
         *,
     },
     VirtAddr,
 };
-use xmas_elf::ElfFile;
-use crate::{humanized_size, memory::*};
+use spin::Mutex;
+use syscall_def::{mman::*, resource::*};
+use xmas_elf::{program, ElfFile};
+use crate::{humanized_size, memory::{swap, *}, resource::Resource};
 
 pub mod heap;
 pub mod stack;
+pub mod vma;
 
-use self::{heap::Heap, stack::Stack};
+use self::{heap::{Heap, HEAP_SIZE}, stack::{Stack, STACK_MAX_SIZE}, vma::*};
 
+use super::paging::{page_entry, COPY_ON_WRITE};
 use super::PageTableContext;
 
 // See the documentation for the `KernelPages` type
@@ -23,11 +29,69 @@ use super::PageTableContext;
 //
 // use boot::KernelPages;
 
+/// User addresses are in the lower half
+pub const USER_SPACE_END: u64 = 0x0000_8000_0000_0000;
+
+/// Where `Mmap` maps areas, between the heap and the stack
+pub const MMAP_START: u64 = 0x3000_0000_0000;
+pub const MMAP_END: u64 = stack::STACK_DEF_BOT;
+
 type MapperRef<'a> = &'a mut OffsetPageTable<'static>;
-type FrameAllocatorRef<'a> = &'a mut BootInfoFrameAllocator;
+type FrameAllocatorRef<'a> = &'a mut BuddyFrameAllocator;
+
+/// Limits of the memory of a process in bytes, see `SetRlimit`.
+/// The child of fork or spawn has the limits of its parent,
+/// and a limit can only be lowered.
+#[derive(Debug, Clone, Copy)]
+pub struct MemoryLimits {
+    pub stack: u64,
+    pub heap: u64,
+    /// The stack, the heap and the mapped areas in total
+    pub total: u64,
+}
+
+impl Default for MemoryLimits {
+    fn default() -> Self {
+        Self {
+            stack: STACK_MAX_SIZE,
+            heap: HEAP_SIZE,
+            total: RLIM_INFINITY,
+        }
+    }
+}
+
+impl MemoryLimits {
+    pub fn get(&self, resource: usize) -> Option<u64> {
+        match resource {
+            RLIMIT_STACK => Some(self.stack),
+            RLIMIT_DATA => Some(self.heap),
+            RLIMIT_AS => Some(self.total),
+            _ => None,
+        }
+    }
+
+    /// Lower the limit of `resource`, false if `limit` would raise it.
+    /// A process may not take back what its parent has limited it to.
+    pub fn set(&mut self, resource: usize, limit: u64) -> bool {
+        let current = match resource {
+            RLIMIT_STACK => &mut self.stack,
+            RLIMIT_DATA => &mut self.heap,
+            RLIMIT_AS => &mut self.total,
+            _ => return false,
+        };
+
+        if limit > *current {
+            return false;
+        }
+
+        *current = limit;
+        true
+    }
+}
 
 pub struct ProcessVm {
-    // page table is shared by parent and child
+    // page table is owned by the process,
+    // the pages are shared with the forked children until written
     pub(super) page_table: PageTableContext,
 
     // stack is pre-process allocated
@@ -36,10 +100,16 @@ pub struct ProcessVm {
     // heap is allocated by brk syscall
     pub(super) heap: Heap,
 
-    // code is hold by the first process
-    // these fields will be empty for other processes
+    // code of the kernel, the segments of user programs are in `vmas`
     pub(super) code: Vec<PageRangeInclusive>,
     pub(super) code_usage: u64,
+
+    // areas of the elf segments and mmap syscall,
+    // and how many of their pages are mapped
+    pub(super) vmas: VmaList,
+    pub(super) vma_pages: u64,
+
+    pub(super) limits: MemoryLimits,
 }
 
 impl ProcessVm {
@@ -50,6 +120,9 @@ impl ProcessVm {
             heap: Heap::empty(),
             code: Vec::new(),
             code_usage: 0,
+            vmas: VmaList::default(),
+            vma_pages: 0,
+            limits: MemoryLimits::default(),
         }
     }
 
@@ -84,93 +157,356 @@ impl ProcessVm {
     pub fn brk(&self, addr: Option<VirtAddr>) -> Option<VirtAddr> {
         self.heap.brk(
             addr,
+            self.limit_of(self.limits.heap, self.heap.memory_usage()),
             &mut self.page_table.mapper(),
-            &mut get_frame_alloc_for_sure(),
+            &mut get_frame_alloc_blocking(),
         )
     }
 
-    pub fn load_elf(&mut self, elf: &ElfFile) {
+    /// The size counted by the total limit
+    fn total_size(&self) -> u64 {
+        self.stack.memory_usage() + self.heap.memory_usage() + self.vmas.size()
+    }
+
+    /// How large a part of the memory of `size` bytes may grow to,
+    /// by its `limit` and the total limit
+    fn limit_of(&self, limit: u64, size: u64) -> u64 {
+        let others = self.total_size() - size;
+        limit.min(self.limits.total.saturating_sub(others))
+    }
+
+    /// Load `elf` read from `image`, the segments are paged in on access.
+    pub fn load_elf(&mut self, elf: &ElfFile, image: Image) -> Result<(), &'static str> {
+        self.load_elf_code(elf, &image)?;
+
         let mapper = &mut self.page_table.mapper();
+        let alloc = &mut *get_frame_alloc_blocking();
+
+        self.stack.init(mapper, alloc).map_err(|_| "Out of memory")
+    }
 
-        let alloc = &mut *get_frame_alloc_for_sure();
+    /// Record the `PT_LOAD` segments as areas backed by `image`,
+    /// the part of an area past the file size of its segment is the BSS.
+    fn load_elf_code(&mut self, elf: &ElfFile, image: &Image) -> Result<(), &'static str> {
+        for segment in elf.program_iter() {
+            if segment.get_type()? != program::Type::Load || segment.mem_size() == 0 {
+                continue;
+            }
+
+            let addr = segment.virtual_addr();
+            let end = addr
+                .checked_add(segment.mem_size())
+                .filter(|&end| end <= USER_SPACE_END)
+                .ok_or("Segment is out of user space")?;
+
+            // the first page holds what is before the segment in the file as well
+            let start = x86_64::align_down(addr, PAGE_SIZE);
+            let end = x86_64::align_up(end, PAGE_SIZE);
+            let offset = segment
+                .offset()
+                .checked_sub(addr - start)
+                .ok_or("Segment is not aligned to pages in the file")?;
+
+            if self.vmas.overlaps(start, end) {
+                return Err("Segments share a page");
+            }
 
-        self.load_elf_code(elf, mapper, alloc);
-        self.stack.init(mapper, alloc);
+            trace!("Segment: {:#x}..{:#x}, {:?}", start, end, segment.flags());
+
+            let backing = image.backing(
+                offset as usize,
+                (segment.file_size() + addr - start) as usize,
+            );
+            self.vmas.insert(Vma::new(start, end, segment_prot(segment.flags()), false, backing));
+        }
+
+        Ok(())
     }
 
-    fn load_elf_code(&mut self, elf: &ElfFile, mapper: MapperRef, alloc: FrameAllocatorRef) {
-        // FIXME: make the `load_elf` function return the code pages
-        self.code =
-            elf::load_elf(elf, *PHYSICAL_OFFSET.get().unwrap(), mapper, alloc, true).unwrap();
+    /// The child has the same memory layout as the parent,
+    /// with the pages copied on write. None if out of memory.
+    pub fn fork(&self) -> Option<Self> {
+        let (page_table, complete) = match self.page_table.fork()? {
+            Ok(page_table) => (page_table, true),
+            Err(page_table) => (page_table, false),
+        };
 
-        // FIXME: calculate code usage
-        self.code_usage = self.code.iter()
-            .map(|page_range| page_range.len() as u64)
-            .sum::<u64>()
-            * crate::memory::PAGE_SIZE as u64;
+        let child = Self {
+            page_table,
+            stack: self.stack.fork(),
+            heap: self.heap.fork(),
+            code: self.code.clone(),
+            code_usage: self.code_usage,
+            vmas: self.vmas.clone(),
+            vma_pages: self.vma_pages,
+            limits: self.limits,
+        };
+
+        // the pages shared with the part copied are released by dropping it
+        complete.then_some(child)
     }
 
-    pub fn fork(&self, stack_offset_count: u64) -> Self {
-        let owned_page_table = self.page_table.fork();
-        let mapper = &mut owned_page_table.mapper();
+    pub fn handle_page_fault(&mut self, addr: VirtAddr) -> bool {
+        let page = Page::containing_address(addr);
 
-        let alloc = &mut *get_frame_alloc_for_sure();
+        if let Some(entry) = self.page_table.page_entry(page).filter(|entry| swap::is_swapped(entry)) {
+            return swap_in(page, entry);
+        }
 
-        Self {
-            page_table: owned_page_table,
-            stack: self.stack.fork(mapper, alloc, stack_offset_count),
-            heap: self.heap.fork(),
+        let mapper = &mut self.page_table.mapper();
+        let vma = self.vmas.find(addr);
+
+        // a mapped page only faults on writing it
+        if let TranslateResult::Mapped {
+            frame: MappedFrame::Size4KiB(frame),
+            flags,
+            ..
+        } = mapper.translate(addr)
+        {
+            return flags.contains(COPY_ON_WRITE)
+                && vma.is_none_or(|vma| vma.is_writable())
+                && copy_on_write(page, frame, flags, mapper, &mut get_frame_alloc_blocking());
+        }
 
-            // do not share code info
-            code: Vec::new(),
-            code_usage: 0,
+        if let Some(vma) = vma {
+            let mapped = fault_in(vma, page, mapper);
+            self.vma_pages += mapped as u64;
+            return mapped;
         }
+
+        let limit = self.limit_of(self.limits.stack, self.stack.memory_usage());
+        self.stack
+            .handle_page_fault(addr, limit, mapper, &mut get_frame_alloc_blocking())
     }
 
-    pub fn handle_page_fault(&mut self, addr: VirtAddr) -> bool {
+    /// Swap out a page of the process, the first one from `start` on that is
+    /// not accessed since the last scan, and not shared with other processes.
+    /// The accessed pages met on the way are cleared for the next scan.
+    pub fn swap_out_page(&mut self, start: Page) -> Option<Page> {
+        // the other threads may be running with the pages on other CPUs
+        if self.page_table.using_count() > 1 {
+            return None;
+        }
+
+        let page = {
+            let alloc = get_frame_alloc_blocking();
+
+            // the flag may stay clear while the page is in the TLB,
+            // then it is swapped out and faulted in again at worst
+            self.page_table.find_user_page(start, |entry| {
+                let flags = entry.flags();
+
+                if !flags.contains(PageTableFlags::USER_ACCESSIBLE)
+                    || alloc.frame_refs(entry.frame().unwrap()) > 1
+                {
+                    return false;
+                }
+
+                if flags.contains(PageTableFlags::ACCESSED) {
+                    entry.set_flags(flags - PageTableFlags::ACCESSED);
+                    return false;
+                }
+
+                true
+            })?
+        };
+
+        // the page is written without the frame allocator locked
+        let entry = self.page_table.page_entry(page)?;
+        let frame = swap::swap_out(entry)?;
+        x86_64::instructions::tlb::flush(page.start_address());
+
+        unsafe { get_frame_alloc_blocking().deallocate_frame(frame) };
+
+        trace!("Swap out: {:#x}", page.start_address());
+        Some(page)
+    }
+
+    /// Map an area as `Mmap` does, of `file` from `offset` if it is not
+    /// anonymous. Only the range is reserved, the pages are mapped on access.
+    pub fn mmap(
+        &mut self,
+        addr: u64,
+        len: u64,
+        prot: usize,
+        flags: usize,
+        file: Option<(Arc<Mutex<Resource>>, usize)>,
+    ) -> Option<VirtAddr> {
+        if len == 0 || len > MMAP_END - MMAP_START || !is_valid_prot(prot) {
+            return None;
+        }
+
+        let len = x86_64::align_up(len, PAGE_SIZE);
+
+        // the areas replaced by `MAP_FIXED` are counted as well
+        if self.total_size() + len > self.limits.total {
+            debug!("Mmap: {:#x} bytes is over the memory limit", len);
+            return None;
+        }
+
+        // written pages are never written back to the file
+        let shared = match flags & (MAP_SHARED | MAP_PRIVATE) {
+            MAP_SHARED if file.is_some() && prot & PROT_WRITE == 0 => true,
+            MAP_PRIVATE => false,
+            _ => return None,
+        };
+
+        let backing = match file {
+            None => Backing::Anonymous,
+            Some((_, offset)) if !(offset as u64).is_multiple_of(PAGE_SIZE) => return None,
+            Some((file, offset)) => Backing::File {
+                file,
+                offset,
+                size: len as usize,
+            },
+        };
+
+        let start = if flags & MAP_FIXED != 0 {
+            let end = mmap_range(addr, len)?;
+            self.unmap_areas(addr, end);
+            addr
+        } else {
+            match mmap_range(addr, len) {
+                Some(end) if !self.vmas.overlaps(addr, end) => addr,
+                _ => self.vmas.find_free(len, MMAP_START, MMAP_END)?,
+            }
+        };
+
+        let end = start + len;
+        self.vmas.insert(Vma::new(start, end, prot, shared, backing));
+
+        trace!("Mmap: {:#x}..{:#x}, prot: {:#x}", start, end, prot);
+        Some(VirtAddr::new(start))
+    }
+
+    /// Unmap the areas in the range as `Munmap` does
+    pub fn munmap(&mut self, addr: u64, len: u64) -> bool {
+        let Some(end) = mmap_range(addr, len) else {
+            return false;
+        };
+
+        self.unmap_areas(addr, end);
+        true
+    }
+
+    /// Change the permission of the areas in the range as `Mprotect` does,
+    /// the range must be mapped without holes.
+    pub fn mprotect(&mut self, addr: u64, len: u64, prot: usize) -> bool {
+        let Some(end) = mmap_range(addr, len) else {
+            return false;
+        };
+
+        if !is_valid_prot(prot) || !self.vmas.covers(addr, end) {
+            return false;
+        }
+
+        let areas = self.vmas.remove(addr, end);
+        let allowed = prot & PROT_WRITE == 0 || areas.iter().all(|vma| !vma.is_shared());
         let mapper = &mut self.page_table.mapper();
-        let alloc = &mut *get_frame_alloc_for_sure();
 
-        self.stack.handle_page_fault(addr, mapper, alloc)
+        for mut vma in areas {
+            if allowed {
+                vma.set_prot(prot);
+                protect_pages(&vma, mapper);
+            }
+
+            self.vmas.insert(vma);
+        }
+
+        allowed
+    }
+
+    /// Unmap the pages of the areas in [start, end) and remove them
+    fn unmap_areas(&mut self, start: u64, end: u64) {
+        let removed = self.vmas.remove(start, end);
+        let mapper = &mut self.page_table.mapper();
+        let alloc = &mut *get_frame_alloc_blocking();
+
+        for vma in removed {
+            self.vma_pages -= unmap_pages(vma.pages(), mapper, alloc);
+        }
+    }
+
+    /// Whether `[start, start + len)` is user memory the process may access,
+    /// writable if `write`. Stack pages not mapped yet are mapped on the way,
+    /// so the kernel can access the range without faulting.
+    pub fn check_user_range(&mut self, start: VirtAddr, len: u64, write: bool) -> bool {
+        let Some(end) = start.as_u64().checked_add(len) else {
+            return false;
+        };
+
+        if len == 0 {
+            return true;
+        }
+
+        if end > USER_SPACE_END {
+            return false;
+        }
+
+        let pages = Page::<Size4KiB>::range_inclusive(
+            Page::containing_address(start),
+            Page::containing_address(VirtAddr::new(end - 1)),
+        );
+
+        pages.into_iter().all(|page| {
+            self.is_user_page(page, write)
+                || (self.handle_page_fault(page.start_address()) && self.is_user_page(page, write))
+        })
+    }
+
+    fn is_user_page(&self, page: Page, write: bool) -> bool {
+        match self.page_table.mapper().translate(page.start_address()) {
+            TranslateResult::Mapped { flags, .. } => {
+                flags.contains(PageTableFlags::USER_ACCESSIBLE)
+                    && (!write || flags.contains(PageTableFlags::WRITABLE))
+            }
+            _ => false,
+        }
     }
 
     pub(super) fn memory_usage(&self) -> u64 {
-        self.stack.memory_usage() + self.heap.memory_usage() + self.code_usage
+        self.stack.memory_usage()
+            + self.heap.memory_usage()
+            + self.code_usage
+            + self.vma_pages * PAGE_SIZE
     }
 
     pub(super) fn clean_up(&mut self) -> Result<(), UnmapError> {
         let mapper = &mut self.page_table.mapper();
-        let dealloc = &mut *get_frame_alloc_for_sure();
+        let dealloc = &mut *get_frame_alloc_blocking();
 
-        let start_count = dealloc.frames_recycled();
+        let start_count = dealloc.frames_free();
 
         // FIXME: implement the `clean_up` function for `Stack`
         self.stack.clean_up(mapper, dealloc)?;
 
-        if self.page_table.using_count() == 1 {
-            // free heap
-            // FIXME: implement the `clean_up` function for `Heap`
-            self.heap.clean_up(mapper, dealloc)?;
+        // free heap
+        // FIXME: implement the `clean_up` function for `Heap`
+        self.heap.clean_up(mapper, dealloc)?;
 
-            // free code
-            for page_range in self.code.iter() {
-                elf::unmap_range(*page_range, mapper, dealloc, true)?;
-            }
+        // free code, the frames shared with other processes are kept
+        for page_range in self.code.iter() {
+            elf::unmap_range(*page_range, mapper, dealloc, true)?;
+        }
+
+        // free mapped areas
+        for vma in self.vmas.iter() {
+            unmap_pages(vma.pages(), mapper, dealloc);
+        }
+        self.vma_pages = 0;
 
-            unsafe {
-                // free P1-P3
-                mapper.clean_up(dealloc);
+        unsafe {
+            // free P1-P3
+            mapper.clean_up(dealloc);
 
-                // free P4
-                dealloc.deallocate_frame(self.page_table.reg.addr);
-            }
+            // free P4
+            dealloc.deallocate_frame(self.page_table.reg.addr);
         }
 
         // NOTE: maybe print how many frames are recycled
-        //       **you may need to add some functions to `BootInfoFrameAllocator`**
-        let end_count = dealloc.frames_recycled();
+        let end_count = dealloc.frames_free();
         debug!(
-            "Recycled {}({:.3} MiB) frames, {}({:.3} MiB) frames in total.",
+            "Recycled {}({:.3} MiB) frames, {}({:.3} MiB) frames free in total.",
             end_count - start_count,
             ((end_count - start_count) * 4) as f32 / 1024.0,
             end_count,
@@ -181,6 +517,192 @@ impl ProcessVm {
     }
 }
 
+/// The `PROT_*` of a segment
+fn segment_prot(flags: program::Flags) -> usize {
+    let mut prot = PROT_NONE;
+
+    if flags.is_read() {
+        prot |= PROT_READ;
+    }
+
+    if flags.is_write() {
+        prot |= PROT_WRITE;
+    }
+
+    // W^X, the writable part of a segment is data
+    if flags.is_execute() && !flags.is_write() {
+        prot |= PROT_EXEC;
+    }
+
+    prot
+}
+
+/// Whether `prot` is known and not both writable and executable (W^X)
+fn is_valid_prot(prot: usize) -> bool {
+    prot & !(PROT_READ | PROT_WRITE | PROT_EXEC) == 0
+        && prot & (PROT_WRITE | PROT_EXEC) != PROT_WRITE | PROT_EXEC
+}
+
+/// The end of [addr, addr + len) rounded up to pages,
+/// if it is a valid range in the region of `Mmap`.
+fn mmap_range(addr: u64, len: u64) -> Option<u64> {
+    let end = addr.checked_add(len)?;
+
+    (len > 0 && addr.is_multiple_of(PAGE_SIZE) && addr >= MMAP_START && end <= MMAP_END)
+        .then(|| x86_64::align_up(end, PAGE_SIZE))
+}
+
+/// Map the page of `vma` on its first access
+fn fault_in(vma: &Vma, page: Page, mapper: MapperRef) -> bool {
+    if vma.prot() == PROT_NONE {
+        return false;
+    }
+
+    let Some(frame) = get_frame_alloc_blocking().allocate_frame() else {
+        error!("Failed to map {:#x}: out of memory", page.start_address());
+        return false;
+    };
+
+    // the file is read without the frame allocator locked
+    let filled = vma.fill(page, frame);
+    let alloc = &mut *get_frame_alloc_blocking();
+
+    let mapped = filled
+        && match unsafe { mapper.map_to(page, frame, vma.flags(), alloc) } {
+            Ok(flush) => {
+                flush.flush();
+                true
+            }
+            Err(err) => {
+                error!("Failed to map {:#x}: {:?}", page.start_address(), err);
+                false
+            }
+        };
+
+    if !mapped {
+        unsafe { alloc.deallocate_frame(frame) };
+    }
+
+    mapped
+}
+
+/// Update the mapped pages of `vma` to its permission, the swapped out ones
+/// as well. The pages shared by fork stay read-only until copied.
+fn protect_pages(vma: &Vma, mapper: MapperRef) {
+    for page in vma.pages() {
+        let Some(entry) = page_entry(mapper.level_4_table_mut(), page) else {
+            continue;
+        };
+
+        let flags = entry.flags();
+        let mut new_flags = vma.flags() | (flags & (PageTableFlags::ACCESSED | PageTableFlags::DIRTY));
+        if flags.contains(COPY_ON_WRITE) {
+            new_flags.remove(PageTableFlags::WRITABLE);
+            new_flags.insert(COPY_ON_WRITE);
+        }
+
+        if swap::is_swapped(entry) {
+            entry.set_flags((new_flags - PageTableFlags::PRESENT) | swap::SWAPPED);
+            continue;
+        }
+
+        if let Ok(flush) = unsafe { mapper.update_flags(page, new_flags) } {
+            flush.flush();
+        }
+    }
+}
+
+/// Unmap the mapped pages in `pages`, and free the slots of the swapped
+/// out ones, returns how many there were
+fn unmap_pages(pages: PageRange, mapper: MapperRef, dealloc: FrameAllocatorRef) -> u64 {
+    let mut count = 0;
+
+    for page in pages {
+        if let Ok((frame, flush)) = mapper.unmap(page) {
+            flush.flush();
+            unsafe { dealloc.deallocate_frame(frame) };
+            count += 1;
+        } else if page_entry(mapper.level_4_table_mut(), page).is_some_and(swap::release) {
+            count += 1;
+        }
+    }
+
+    count
+}
+
+/// Read the swapped out `page` of `entry` back
+fn swap_in(page: Page, entry: &mut PageTableEntry) -> bool {
+    let Some(frame) = get_frame_alloc_blocking().allocate_frame() else {
+        error!("Failed to swap in {:#x}: out of memory", page.start_address());
+        return false;
+    };
+
+    // the swap space is read without the frame allocator locked
+    if !swap::swap_in(entry, frame) {
+        unsafe { get_frame_alloc_blocking().deallocate_frame(frame) };
+        return false;
+    }
+
+    trace!("Swap in: {:#x}", page.start_address());
+    true
+}
+
+/// Make a page shared by fork writable, copying it
+/// if other processes still map it.
+fn copy_on_write(
+    page: Page,
+    frame: PhysFrame,
+    flags: PageTableFlags,
+    mapper: MapperRef,
+    alloc: FrameAllocatorRef,
+) -> bool {
+    let flags = (flags - COPY_ON_WRITE) | PageTableFlags::WRITABLE;
+
+    if alloc.frame_refs(frame) == 1 {
+        trace!("Copy on write: {:#x} is not shared", page.start_address());
+        return match unsafe { mapper.update_flags(page, flags) } {
+            Ok(flush) => {
+                flush.flush();
+                true
+            }
+            Err(err) => {
+                error!("Copy on write failed: {:?}", err);
+                false
+            }
+        };
+    }
+
+    let Some(copy) = alloc.allocate_frame() else {
+        error!("Copy on write failed: out of memory");
+        return false;
+    };
+
+    trace!("Copy on write: {:#x}", page.start_address());
+    unsafe {
+        copy_nonoverlapping(
+            physical_to_virtual(frame.start_address().as_u64()) as *const u8,
+            physical_to_virtual(copy.start_address().as_u64()) as *mut u8,
+            PAGE_SIZE as usize,
+        );
+    }
+
+    // the other processes keep the shared frame
+    let (frame, flush) = mapper.unmap(page).expect("Copy on write page is not mapped");
+    flush.flush();
+    unsafe { alloc.deallocate_frame(frame) };
+
+    match unsafe { mapper.map_to(page, copy, flags, alloc) } {
+        Ok(flush) => {
+            flush.flush();
+            true
+        }
+        Err(err) => {
+            error!("Copy on write failed: {:?}", err);
+            false
+        }
+    }
+}
+
 impl core::fmt::Debug for ProcessVm {
     fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
         let (size, unit) = humanized_size(self.memory_usage());
@@ -188,6 +710,7 @@ impl core::fmt::Debug for ProcessVm {
         f.debug_struct("ProcessVm")
             .field("stack", &self.stack)
             .field("heap", &self.heap)
+            .field("vmas", &self.vmas.len())
             .field("memory_usage", &format!("{} {}", size, unit))
             .field("page_table", &self.page_table)
             .finish()
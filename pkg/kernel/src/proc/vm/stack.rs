@@ -6,8 +6,7 @@ use x86_64::{
 
 use crate::proc::{KERNEL_PID, processor};
 
-use super::{FrameAllocatorRef, MapperRef};
-use core::ptr::copy_nonoverlapping;
+use super::{unmap_pages, FrameAllocatorRef, MapperRef};
 
 // 0xffff_ff00_0000_0000 is the kernel's address space
 pub const STACK_MAX: u64 = 0x4000_0000_0000;
@@ -66,16 +65,23 @@ impl Stack {
         }
     }
 
-    pub fn init(&mut self, mapper: MapperRef, alloc: FrameAllocatorRef) {
+    pub fn init(
+        &mut self,
+        mapper: MapperRef,
+        alloc: FrameAllocatorRef,
+    ) -> Result<(), MapToError<Size4KiB>> {
         debug_assert!(self.usage == 0, "Stack is not empty.");
 
-        self.range = elf::map_range(STACK_INIT_BOT, STACK_DEF_PAGE, mapper, alloc, true).unwrap();
+        self.range = elf::map_range(STACK_INIT_BOT, STACK_DEF_PAGE, mapper, alloc, true)?;
         self.usage = STACK_DEF_PAGE;
+        Ok(())
     }
 
+    /// Grow the stack down to `addr`, if it is not larger than `limit` bytes then
     pub fn handle_page_fault(
         &mut self,
         addr: VirtAddr,
+        limit: u64,
         mapper: MapperRef,
         alloc: FrameAllocatorRef,
     ) -> bool {
@@ -83,6 +89,13 @@ impl Stack {
             return false;
         }
 
+        let bot = self.range.start.start_address();
+        let pages = bot.as_u64().saturating_sub(addr.as_u64()).div_ceil(crate::memory::PAGE_SIZE);
+        if (self.usage + pages) * crate::memory::PAGE_SIZE > limit {
+            warn!("Grow stack failed: {:#x} is over the stack limit", addr);
+            return false;
+        }
+
         if let Err(m) = self.grow_stack(addr, mapper, alloc) {
             error!("Grow stack failed: {:?}", m);
             return false;
@@ -129,13 +142,17 @@ impl Stack {
         }
 
         // 调用 elf::map_range 分配和映射页面
-        elf::map_range(
+        // out of memory, the pages mapped on the way are unmapped
+        if let Err(err) = elf::map_range(
             page_range.start.start_address().as_u64(),
             page_range.count() as u64,
             mapper,
             alloc,
             user_access,
-        )?;
+        ) {
+            unmap_pages(Page::range(fault_page, current_bottom_page), mapper, alloc);
+            return Err(err);
+        }
 
         self.range.start = fault_page;
         self.usage += page_range.count() as u64;
@@ -147,61 +164,12 @@ impl Stack {
         self.usage * crate::memory::PAGE_SIZE
     }
 
-    // calculate parent's stack and child's stack offset
-    pub fn offset(&self, parent_stack: &Stack) -> u64 {
-        let parent_stack_bot = parent_stack.range.start.start_address().as_u64();
-        let cur_stack_bot = self.range.start.start_address().as_u64();
-        trace!("Current stack bot: {:#x}", cur_stack_bot);
-        trace!("Parent stack bot: {:#x}", parent_stack_bot);
-        cur_stack_bot - parent_stack_bot
-    }
-
-    /// Clone a range of memory
-    ///
-    /// - `src_addr`: the address of the source memory
-    /// - `dest_addr`: the address of the target memory
-    /// - `size`: the count of pages to be cloned
-    fn clone_range(&self, cur_addr: u64, dest_addr: u64, size: u64) {
-        trace!("Clone range: {:#x} -> {:#x}", cur_addr, dest_addr);
-        unsafe {
-            copy_nonoverlapping::<u64>(
-                cur_addr as *mut u64,
-                dest_addr as *mut u64,
-                (size * Size4KiB::SIZE / 8) as usize,
-            );
-        }
-    }
-
-    pub fn fork(
-        &self,
-        mapper: MapperRef,
-        alloc: FrameAllocatorRef,
-        stack_offset_count: u64,
-    ) -> Self {
-        // FIXME: alloc & map new stack for child (see instructions)
-
-        let mut new_stack_base = self.range.start.start_address().as_u64() - stack_offset_count * STACK_MAX_SIZE;
-
-        while elf::map_range(new_stack_base, self.usage, mapper, alloc, true).is_err()
-        {
-            trace!("Map thread stack to {:#x} failed.", new_stack_base);
-            new_stack_base -= STACK_MAX_SIZE; // stack grow down
-        }
-
-        // FIXME: copy the *entire stack* from parent to child
-        self.clone_range(
-            self.range.start.start_address().as_u64(),
-            new_stack_base,
-            self.usage,
-        );
-
-        // FIXME: return the new stack
+    /// The child of fork has the stack at the same address,
+    /// its pages are copied on write.
+    pub fn fork(&self) -> Self {
         Self {
-            range: Page::range(
-                Page::containing_address(VirtAddr::new(new_stack_base)),
-                Page::containing_address(VirtAddr::new(new_stack_base + self.usage * crate::memory::PAGE_SIZE)),
-            ),
-            usage: self.usage
+            range: self.range,
+            usage: self.usage,
         }
     }
 
@@ -217,11 +185,8 @@ impl Stack {
             return Ok(());
         }
 
-        let start_page = self.range.start;
-        let end_page = self.range.end - 1; // end is exclusive
-
-        let page_range = Page::range_inclusive(start_page, end_page);
-        elf::unmap_range(page_range, mapper, dealloc, true)?;
+        // the pages swapped out are not mapped
+        unmap_pages(self.range, mapper, dealloc);
 
         self.usage = 0;
 
@@ -0,0 +1,348 @@
+//! Virtual memory areas
+//!
+//! An area is a page aligned range of user memory with its permissions and
+//! what its pages hold. Its pages are only mapped on the first access, see
+//! `ProcessVm::handle_page_fault`, and are private to the process: pages of
+//! a file are read into new frames, and fork copies them on write.
+//!
+//! The segments of a program are areas as well, backed by the image it is
+//! loaded from, so only the pages it touches are read.
+
+use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
+use spin::Mutex;
+use syscall_def::mman::*;
+use x86_64::structures::paging::{page::PageRange, Page, PageTableFlags, PhysFrame};
+use x86_64::VirtAddr;
+
+use crate::memory::{physical_to_virtual, PAGE_SIZE};
+use crate::resource::Resource;
+
+/// What the pages of an area hold before they are written
+#[derive(Clone)]
+pub enum Backing {
+    /// Zero-filled pages
+    Anonymous,
+    /// Bytes in memory, the rest of the area is zero-filled
+    Memory(&'static [u8]),
+    /// `size` bytes of `file` from `offset`, the rest of the area is zero-filled
+    File {
+        file: Arc<Mutex<Resource>>,
+        offset: usize,
+        size: usize,
+    },
+}
+
+impl core::fmt::Debug for Backing {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Anonymous => write!(f, "Anonymous"),
+            Self::Memory(data) => write!(f, "Memory({:#x} bytes)", data.len()),
+            Self::File { offset, size, .. } => f
+                .debug_struct("File")
+                .field("offset", &format_args!("{:#x}", offset))
+                .field("size", &format_args!("{:#x}", size))
+                .finish(),
+        }
+    }
+}
+
+/// What a program is loaded from
+#[derive(Clone)]
+pub enum Image {
+    /// Loaded by the bootloader, see `boot::AppList`
+    Memory(&'static [u8]),
+    File(Arc<Mutex<Resource>>),
+}
+
+impl Image {
+    /// The backing of `size` bytes from `offset` of the image
+    pub fn backing(&self, offset: usize, size: usize) -> Backing {
+        match self {
+            Self::Memory(data) => {
+                let start = offset.min(data.len());
+                let end = offset.saturating_add(size).min(data.len());
+                Backing::Memory(&data[start..end])
+            }
+            Self::File(file) => Backing::File {
+                file: file.clone(),
+                offset,
+                size,
+            },
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Vma {
+    /// The range is [start, end)
+    start: u64,
+    end: u64,
+    /// `PROT_*` of `Mmap`
+    prot: usize,
+    /// Shared with the other mappings of the file, see `MAP_SHARED`
+    shared: bool,
+    backing: Backing,
+}
+
+impl Vma {
+    pub fn new(start: u64, end: u64, prot: usize, shared: bool, backing: Backing) -> Self {
+        debug_assert!(
+            start.is_multiple_of(PAGE_SIZE) && end.is_multiple_of(PAGE_SIZE) && start < end
+        );
+
+        Self {
+            start,
+            end,
+            prot,
+            shared,
+            backing,
+        }
+    }
+
+    pub fn start(&self) -> u64 {
+        self.start
+    }
+
+    pub fn end(&self) -> u64 {
+        self.end
+    }
+
+    pub fn prot(&self) -> usize {
+        self.prot
+    }
+
+    pub fn is_shared(&self) -> bool {
+        self.shared
+    }
+
+    pub fn set_prot(&mut self, prot: usize) {
+        self.prot = prot;
+    }
+
+    pub fn is_writable(&self) -> bool {
+        self.prot & PROT_WRITE != 0
+    }
+
+    pub fn pages(&self) -> PageRange {
+        Page::range(
+            Page::containing_address(VirtAddr::new(self.start)),
+            Page::containing_address(VirtAddr::new(self.end)),
+        )
+    }
+
+    /// The flags to map a page of the area with.
+    /// `PROT_NONE` pages are not accessible by the user,
+    /// and only pages of `PROT_EXEC` areas can be executed.
+    pub fn flags(&self) -> PageTableFlags {
+        let mut flags = PageTableFlags::PRESENT;
+
+        if self.prot & PROT_EXEC == 0 {
+            flags |= PageTableFlags::NO_EXECUTE;
+        }
+
+        if self.prot != PROT_NONE {
+            flags |= PageTableFlags::USER_ACCESSIBLE;
+        }
+
+        if self.is_writable() {
+            flags |= PageTableFlags::WRITABLE;
+        }
+
+        flags
+    }
+
+    /// Split the area at `addr`, `self` keeps [start, addr)
+    /// and [addr, end) is returned.
+    fn split_off(&mut self, addr: u64) -> Self {
+        debug_assert!(self.start < addr && addr < self.end);
+
+        let skipped = (addr - self.start) as usize;
+        let backing = match &self.backing {
+            Backing::Anonymous => Backing::Anonymous,
+            Backing::Memory(data) => Backing::Memory(&data[skipped.min(data.len())..]),
+            Backing::File { file, offset, size } => Backing::File {
+                file: file.clone(),
+                offset: offset + skipped,
+                size: size.saturating_sub(skipped),
+            },
+        };
+
+        let tail = Self::new(addr, self.end, self.prot, self.shared, backing);
+        self.end = addr;
+        tail
+    }
+
+    /// Fill `frame` with the content of `page`, before it is mapped.
+    pub fn fill(&self, page: Page, frame: PhysFrame) -> bool {
+        let buf = unsafe {
+            core::slice::from_raw_parts_mut(
+                physical_to_virtual(frame.start_address().as_u64()) as *mut u8,
+                PAGE_SIZE as usize,
+            )
+        };
+
+        let skipped = (page.start_address().as_u64() - self.start) as usize;
+        let (file, offset, size) = match &self.backing {
+            Backing::File { file, offset, size } => (file, offset, size),
+            Backing::Memory(data) => {
+                let data = &data[skipped.min(data.len())..];
+                let len = data.len().min(buf.len());
+                buf[..len].copy_from_slice(&data[..len]);
+                buf[len..].fill(0);
+                return true;
+            }
+            Backing::Anonymous => {
+                buf.fill(0);
+                return true;
+            }
+        };
+
+        let len = size.saturating_sub(skipped).min(buf.len());
+        let mut read = 0;
+
+        // the file may end before, the rest is zeroed as well
+        while read < len {
+            match file.lock().read_at(offset + skipped + read, &mut buf[read..len]) {
+                Some(0) => break,
+                Some(count) => read += count,
+                None => {
+                    warn!("Failed to read the mapped file at {:#x}", offset + skipped + read);
+                    return false;
+                }
+            }
+        }
+
+        buf[read..].fill(0);
+        true
+    }
+}
+
+impl core::fmt::Debug for Vma {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Vma")
+            .field("start", &format_args!("{:#x}", self.start))
+            .field("end", &format_args!("{:#x}", self.end))
+            .field("prot", &format_args!("{:#x}", self.prot))
+            .field("backing", &self.backing)
+            .finish()
+    }
+}
+
+/// The areas of a process, which do not overlap, by their start address
+#[derive(Clone, Default)]
+pub struct VmaList {
+    areas: BTreeMap<u64, Vma>,
+}
+
+impl VmaList {
+    pub fn iter(&self) -> impl Iterator<Item = &Vma> {
+        self.areas.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.areas.len()
+    }
+
+    /// Bytes of all the areas
+    pub fn size(&self) -> u64 {
+        self.iter().map(|vma| vma.end() - vma.start()).sum()
+    }
+
+    /// The area containing `addr`
+    pub fn find(&self, addr: VirtAddr) -> Option<&Vma> {
+        let addr = addr.as_u64();
+        self.areas
+            .range(..=addr)
+            .next_back()
+            .map(|(_, vma)| vma)
+            .filter(|vma| addr < vma.end)
+    }
+
+    /// Whether any area overlaps [start, end)
+    pub fn overlaps(&self, start: u64, end: u64) -> bool {
+        self.areas
+            .range(..end)
+            .next_back()
+            .is_some_and(|(_, vma)| vma.end > start)
+    }
+
+    /// Whether [start, end) is covered by areas without holes
+    pub fn covers(&self, start: u64, end: u64) -> bool {
+        let mut cursor = start;
+
+        for vma in self.areas.range(..end).map(|(_, vma)| vma) {
+            if vma.end <= cursor {
+                continue;
+            }
+
+            if vma.start > cursor {
+                return false;
+            }
+
+            cursor = vma.end;
+        }
+
+        cursor >= end
+    }
+
+    /// The first free range of `len` bytes in [low, high)
+    pub fn find_free(&self, len: u64, low: u64, high: u64) -> Option<u64> {
+        let mut cursor = low;
+
+        for vma in self.areas.range(..high).map(|(_, vma)| vma) {
+            if vma.end <= cursor {
+                continue;
+            }
+
+            if vma.start >= cursor + len {
+                break;
+            }
+
+            cursor = vma.end;
+        }
+
+        (cursor.checked_add(len)? <= high).then_some(cursor)
+    }
+
+    pub fn insert(&mut self, vma: Vma) {
+        debug_assert!(!self.overlaps(vma.start, vma.end));
+        self.areas.insert(vma.start, vma);
+    }
+
+    /// Take [start, end) out of the areas, the ones across its ends are split.
+    pub fn remove(&mut self, start: u64, end: u64) -> Vec<Vma> {
+        let starts: Vec<u64> = self
+            .areas
+            .range(..end)
+            .filter(|(_, vma)| vma.end > start)
+            .map(|(&addr, _)| addr)
+            .collect();
+
+        let mut removed = Vec::new();
+
+        for addr in starts {
+            let mut vma = self.areas.remove(&addr).unwrap();
+
+            if vma.start < start {
+                let tail = vma.split_off(start);
+                self.areas.insert(vma.start, vma);
+                vma = tail;
+            }
+
+            if vma.end > end {
+                let tail = vma.split_off(end);
+                self.areas.insert(tail.start, tail);
+            }
+
+            removed.push(vma);
+        }
+
+        removed
+    }
+}
+
+impl core::fmt::Debug for VmaList {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_list().entries(self.areas.values()).finish()
+    }
+}
@@ -0,0 +1,178 @@
+//! Application processor bring-up
+//!
+//! The APs listed in the ACPI MADT are started one by one with INIT-SIPI-SIPI.
+//! Each one begins in real mode at the trampoline copied into low memory,
+//! switches directly to long mode with the kernel page table and jumps to
+//! `ap_entry` on its own stack, where it loads its own GDT and TSS, enables
+//! the local APIC timer and waits in the idle loop to be scheduled.
+//!
+//! reference: https://wiki.osdev.org/Symmetric_Multiprocessing
+
+mod trampoline;
+
+use crate::drivers::acpi;
+use crate::memory::{physical_to_virtual, LOW_MEMORY_END, PAGE_SIZE};
+use crate::{interrupt, memory, proc, time};
+use alloc::vec;
+use boot::{BootInfo, MemoryType};
+use core::sync::atomic::{AtomicBool, Ordering};
+use trampoline::TrampolineData;
+use x86_64::registers::control::{Cr0, Cr3, Cr4};
+use x86_64::registers::model_specific::{Efer, EferFlags};
+use x86_64::structures::paging::{PageTable, PageTableFlags};
+use x86_64::VirtAddr;
+
+/// Size of the stack each AP starts with, it becomes the stack of the idle loop
+const AP_STACK_SIZE: usize = 0x10000;
+
+/// The SIPI vector is a page number, the memory above 0xA0000 is reserved for devices
+const TRAMPOLINE_LIMIT: u64 = 0xA0000;
+
+/// Delays of the INIT-SIPI-SIPI sequence in nanoseconds
+const INIT_DELAY: u64 = 10_000_000;
+const SIPI_DELAY: u64 = 200_000;
+const START_TIMEOUT: u64 = 100_000_000;
+
+/// Set by an AP when it has finished its initialization
+static AP_STARTED: AtomicBool = AtomicBool::new(false);
+
+/// Finds a free page in low memory for the trampoline,
+/// the frame allocator never hands out frames below `LOW_MEMORY_END`.
+fn find_trampoline_page(boot_info: &BootInfo) -> Option<u64> {
+    boot_info
+        .memory_map
+        .iter()
+        .filter(|region| region.ty == MemoryType::CONVENTIONAL)
+        .flat_map(|region| {
+            (0..region.page_count).map(move |i| region.phys_start + i * PAGE_SIZE)
+        })
+        // page zero holds the real mode IVT
+        .find(|&addr| addr >= PAGE_SIZE && addr + PAGE_SIZE <= TRAMPOLINE_LIMIT.min(LOW_MEMORY_END))
+}
+
+/// Clears NO_EXECUTE along the identity mapping of `addr` in the current page table,
+/// the AP runs the long mode part of the trampoline at its physical address.
+fn make_executable(addr: u64) -> bool {
+    let virt = VirtAddr::new(addr);
+    let indexes = [virt.p4_index(), virt.p3_index(), virt.p2_index(), virt.p1_index()];
+
+    let (frame, _) = Cr3::read();
+    let mut table = unsafe {
+        &mut *(physical_to_virtual(frame.start_address().as_u64()) as *mut PageTable)
+    };
+
+    for (level, index) in indexes.into_iter().enumerate() {
+        let entry = &mut table[index];
+        let flags = entry.flags();
+
+        if !flags.contains(PageTableFlags::PRESENT) {
+            return false;
+        }
+
+        entry.set_flags(flags - PageTableFlags::NO_EXECUTE);
+
+        if level == indexes.len() - 1 || flags.contains(PageTableFlags::HUGE_PAGE) {
+            break;
+        }
+
+        table = unsafe { &mut *(physical_to_virtual(entry.addr().as_u64()) as *mut PageTable) };
+    }
+
+    x86_64::instructions::tlb::flush_all();
+    true
+}
+
+/// Entry of the APs in long mode, on the stack given by the trampoline.
+extern "C" fn ap_entry(apic_id: u64) -> ! {
+    memory::gdt::init_ap();
+    interrupt::init_ap();
+    time::start_periodic();
+    proc::init_ap();
+
+    info!("CPU {} Started.", apic_id);
+    AP_STARTED.store(true, Ordering::Release);
+
+    loop {
+        x86_64::instructions::interrupts::enable_and_hlt();
+    }
+}
+
+fn wait_started(timeout: u64) -> bool {
+    let deadline = time::monotonic_ns() + timeout;
+    while time::monotonic_ns() < deadline {
+        if AP_STARTED.load(Ordering::Acquire) {
+            return true;
+        }
+        core::hint::spin_loop();
+    }
+    AP_STARTED.load(Ordering::Acquire)
+}
+
+fn start_ap(page: u64, apic_id: u32) -> bool {
+    let stack = vec![0u8; AP_STACK_SIZE].leak();
+    let stack_top = (stack.as_ptr() as u64 + AP_STACK_SIZE as u64) & !0xF;
+
+    // only the bits that can be set before entering long mode
+    let efer = Efer::read() & (EferFlags::LONG_MODE_ENABLE | EferFlags::NO_EXECUTE_ENABLE | EferFlags::SYSTEM_CALL_EXTENSIONS);
+
+    trampoline::write_data(
+        page,
+        TrampolineData {
+            cr3: Cr3::read().0.start_address().as_u64(),
+            stack_top,
+            entry: ap_entry as *const () as u64,
+            arg: apic_id as u64,
+            cr0: Cr0::read_raw(),
+            cr4: Cr4::read_raw(),
+            efer: efer.bits(),
+        },
+    );
+
+    AP_STARTED.store(false, Ordering::Release);
+
+    let vector = (page / PAGE_SIZE) as u8;
+
+    interrupt::send_init(apic_id);
+    time::busy_wait(INIT_DELAY);
+
+    interrupt::send_startup(apic_id, vector);
+    if wait_started(SIPI_DELAY) {
+        return true;
+    }
+
+    // the second SIPI is only needed if the first one was missed
+    interrupt::send_startup(apic_id, vector);
+    wait_started(START_TIMEOUT)
+}
+
+/// Starts all APs, must be called on the BSP after the process manager is initialized.
+pub fn init(boot_info: &'static BootInfo) {
+    let bsp = interrupt::cpu_id();
+    let aps = acpi::cpus().iter().filter(|cpu| cpu.apic_id != bsp);
+
+    if aps.clone().next().is_none() {
+        info!("SMP: single CPU.");
+        return;
+    }
+
+    let Some(page) = find_trampoline_page(boot_info) else {
+        warn!("No low memory for the AP trampoline, SMP disabled.");
+        return;
+    };
+
+    if Cr3::read().0.start_address().as_u64() > u32::MAX as u64 || !make_executable(page) {
+        warn!("Low memory is not identity mapped, SMP disabled.");
+        return;
+    }
+
+    trampoline::install(page);
+    trace!("AP trampoline at {:#x}", page);
+
+    for cpu in aps {
+        if !start_ap(page, cpu.apic_id) {
+            warn!("CPU {} did not start.", cpu.apic_id);
+        }
+    }
+
+    info!("SMP Initialized: {} CPU(s) online.", proc::online_count());
+}
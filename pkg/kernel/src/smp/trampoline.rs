@@ -0,0 +1,135 @@
+//! Real mode entry of the application processors
+//!
+//! The code is position independent within its page: in real mode it is
+//! addressed relative to CS, which the SIPI sets to the page, and in long
+//! mode RIP-relative. The GDT pointer and the far jump target are patched
+//! by the AP itself, the rest is passed in `TrampolineData` at the end.
+
+use crate::memory::physical_to_virtual;
+use core::ptr::addr_of;
+
+core::arch::global_asm!(
+    r#"
+    .pushsection .text
+    .balign 16
+    .code16
+    .global ap_trampoline_start
+ap_trampoline_start:
+    cli
+    cld
+    mov %cs, %ax
+    mov %ax, %ds
+    xor %ebx, %ebx
+    mov %ax, %bx
+    shl $4, %ebx
+
+    # linear addresses of the GDT and the long mode entry
+    lea (ap_trampoline_gdt - ap_trampoline_start)(%ebx), %eax
+    mov %eax, (ap_trampoline_gdtr - ap_trampoline_start + 2)
+    lea (ap_trampoline_long - ap_trampoline_start)(%ebx), %eax
+    mov %eax, (ap_trampoline_far - ap_trampoline_start)
+    lgdtl (ap_trampoline_gdtr - ap_trampoline_start)
+
+    # PAE
+    mov %cr4, %eax
+    or $0x20, %eax
+    mov %eax, %cr4
+
+    mov (ap_trampoline_data - ap_trampoline_start), %eax
+    mov %eax, %cr3
+
+    mov $0xC0000080, %ecx
+    mov (ap_trampoline_data - ap_trampoline_start + 48), %eax
+    xor %edx, %edx
+    wrmsr
+
+    # protected mode and paging at once
+    mov %cr0, %eax
+    or $0x80000001, %eax
+    mov %eax, %cr0
+
+    ljmpl *(ap_trampoline_far - ap_trampoline_start)
+
+    .code64
+ap_trampoline_long:
+    mov $0x10, %ax
+    mov %ax, %ds
+    mov %ax, %es
+    mov %ax, %ss
+    xor %ax, %ax
+    mov %ax, %fs
+    mov %ax, %gs
+
+    lea ap_trampoline_data(%rip), %rbx
+    mov 32(%rbx), %rax
+    mov %rax, %cr0
+    mov 40(%rbx), %rax
+    mov %rax, %cr4
+    mov 8(%rbx), %rsp
+    mov 24(%rbx), %rdi
+    mov 16(%rbx), %rax
+    call *%rax
+1:
+    hlt
+    jmp 1b
+
+    .balign 8
+ap_trampoline_gdt:
+    .quad 0
+    .quad 0x00AF9A000000FFFF
+    .quad 0x00CF92000000FFFF
+ap_trampoline_gdtr:
+    .word 23
+    .long 0
+ap_trampoline_far:
+    .long 0
+    .word 0x08
+
+    .balign 8
+    .global ap_trampoline_data
+ap_trampoline_data:
+    .fill 7, 8, 0
+    .global ap_trampoline_end
+ap_trampoline_end:
+    .popsection
+    "#,
+    options(att_syntax)
+);
+
+unsafe extern "C" {
+    static ap_trampoline_start: u8;
+    static ap_trampoline_data: u8;
+    static ap_trampoline_end: u8;
+}
+
+/// Passed from the BSP, the layout is used by the trampoline code
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct TrampolineData {
+    pub cr3: u64,
+    pub stack_top: u64,
+    pub entry: u64,
+    pub arg: u64,
+    pub cr0: u64,
+    pub cr4: u64,
+    pub efer: u64,
+}
+
+/// Copies the trampoline code to the physical `page`.
+pub fn install(page: u64) {
+    let start = addr_of!(ap_trampoline_start);
+    let len = addr_of!(ap_trampoline_end) as usize - start as usize;
+
+    unsafe {
+        core::ptr::copy_nonoverlapping(start, physical_to_virtual(page) as *mut u8, len);
+    }
+}
+
+/// Writes the data for the next AP to start.
+pub fn write_data(page: u64, data: TrampolineData) {
+    let offset = addr_of!(ap_trampoline_data) as usize - addr_of!(ap_trampoline_start) as usize;
+
+    unsafe {
+        ((physical_to_virtual(page) as usize + offset) as *mut TrampolineData).write_volatile(data);
+    }
+}
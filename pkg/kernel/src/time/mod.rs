@@ -0,0 +1,166 @@
+//! Kernel time keeping
+//!
+//! The local APIC timer and the TSC are calibrated against the PIT at boot.
+//! The TSC is the monotonic clock source and the wall clock is the RTC time
+//! read at boot plus the monotonic time. The APIC timer drives the scheduler
+//! tick at the configured frequency, or fires once at a deadline in one-shot mode.
+
+mod pit;
+pub mod timer;
+
+use crate::drivers::rtc;
+use crate::interrupt::{self, TimerMode};
+use chrono::{DateTime, Utc};
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+
+pub use timer::{add_timer, cancel_timer, TimerCallback, TimerId};
+
+pub const NANOS_PER_SEC: u64 = 1_000_000_000;
+
+/// Used when the boot config does not specify a tick frequency
+const DEFAULT_TICK_FREQUENCY: u64 = 100;
+/// A tick shorter than this leaves no time for the processes
+const MAX_TICK_FREQUENCY: u64 = 10_000;
+
+/// Length of the calibration window, limited by the 16-bit PIT counter
+const CALIBRATION_MS: u64 = 50;
+
+/// TSC cycles per second
+static TSC_FREQUENCY: AtomicU64 = AtomicU64::new(0);
+/// APIC timer counts per second, after the divider
+static TIMER_FREQUENCY: AtomicU64 = AtomicU64::new(0);
+/// Scheduler ticks per second
+static TICK_FREQUENCY: AtomicU64 = AtomicU64::new(DEFAULT_TICK_FREQUENCY);
+
+/// TSC value when the boot time was read
+static BOOT_TSC: AtomicU64 = AtomicU64::new(0);
+/// Nanoseconds since the Unix epoch at `BOOT_TSC`
+static BOOT_TIME: AtomicU64 = AtomicU64::new(0);
+
+#[inline]
+fn rdtsc() -> u64 {
+    unsafe { core::arch::x86_64::_rdtsc() }
+}
+
+/// `value * numer / denom` without overflowing in the intermediate product
+#[inline]
+fn mul_div(value: u64, numer: u64, denom: u64) -> u64 {
+    (value as u128 * numer as u128 / denom.max(1) as u128).min(u64::MAX as u128) as u64
+}
+
+/// Measures the TSC and APIC timer frequencies against the PIT.
+///
+/// Must be called with interrupts disabled.
+fn calibrate() -> (u64, u64) {
+    pit::start_countdown(CALIBRATION_MS);
+    interrupt::set_timer(TimerMode::OneShot, u32::MAX);
+    let tsc_start = rdtsc();
+
+    while !pit::countdown_done() {
+        core::hint::spin_loop();
+    }
+
+    let tsc_end = rdtsc();
+    let timer_elapsed = u32::MAX - interrupt::timer_count();
+    interrupt::stop_timer();
+
+    let tsc_frequency = mul_div(tsc_end - tsc_start, 1000, CALIBRATION_MS);
+    let timer_frequency = mul_div(timer_elapsed as u64, 1000, CALIBRATION_MS);
+
+    (tsc_frequency, timer_frequency)
+}
+
+pub fn init(boot_info: &'static boot::BootInfo) {
+    let tick_frequency = match boot_info.tick_frequency {
+        0 => DEFAULT_TICK_FREQUENCY,
+        hz => hz.min(MAX_TICK_FREQUENCY),
+    };
+
+    let (tsc_frequency, timer_frequency) = calibrate();
+
+    TSC_FREQUENCY.store(tsc_frequency, Ordering::Relaxed);
+    TIMER_FREQUENCY.store(timer_frequency, Ordering::Relaxed);
+    TICK_FREQUENCY.store(tick_frequency, Ordering::Relaxed);
+
+    let now = rtc::read_time().unwrap_or_else(|| {
+        warn!("Failed to read time from RTC, fallback to epoch.");
+        DateTime::UNIX_EPOCH
+    });
+
+    BOOT_TSC.store(rdtsc(), Ordering::Relaxed);
+    BOOT_TIME.store(
+        now.timestamp_nanos_opt().unwrap_or_default() as u64,
+        Ordering::Relaxed,
+    );
+
+    start_periodic();
+
+    info!(
+        "TSC: {} MHz, APIC Timer: {} MHz, Tick: {} Hz",
+        tsc_frequency / 1_000_000,
+        timer_frequency / 1_000_000,
+        tick_frequency
+    );
+    info!("Time Initialized: {}", now.format("%Y-%m-%d %H:%M:%S UTC"));
+}
+
+/// Starts the scheduler tick on the current CPU.
+pub fn start_periodic() {
+    let count = TIMER_FREQUENCY.load(Ordering::Relaxed) / tick_frequency();
+    interrupt::set_timer(TimerMode::Periodic, count.clamp(1, u32::MAX as u64) as u32);
+}
+
+/// Fires a single timer interrupt on the current CPU after `ns` nanoseconds,
+/// replacing the periodic tick.
+pub fn set_oneshot(ns: u64) {
+    let count = mul_div(ns, TIMER_FREQUENCY.load(Ordering::Relaxed), NANOS_PER_SEC);
+    interrupt::set_timer(TimerMode::OneShot, count.clamp(1, u32::MAX as u64) as u32);
+}
+
+/// Scheduler ticks per second
+#[inline]
+pub fn tick_frequency() -> u64 {
+    TICK_FREQUENCY.load(Ordering::Relaxed)
+}
+
+#[inline]
+pub fn ticks_to_ns(ticks: u64) -> u64 {
+    mul_div(ticks, NANOS_PER_SEC, tick_frequency())
+}
+
+/// Converts nanoseconds to ticks, rounded up.
+#[inline]
+pub fn ns_to_ticks(ns: u64) -> u64 {
+    (ns as u128 * tick_frequency() as u128).div_ceil(NANOS_PER_SEC as u128) as u64
+}
+
+/// Nanoseconds elapsed since boot, never goes backwards.
+pub fn monotonic_ns() -> u64 {
+    let cycles = rdtsc().saturating_sub(BOOT_TSC.load(Ordering::Relaxed));
+    mul_div(cycles, NANOS_PER_SEC, TSC_FREQUENCY.load(Ordering::Relaxed))
+}
+
+/// Spins for `ns` nanoseconds, for the short delays of device initialization.
+pub fn busy_wait(ns: u64) {
+    let deadline = monotonic_ns() + ns;
+    while monotonic_ns() < deadline {
+        core::hint::spin_loop();
+    }
+}
+
+/// Time elapsed since boot, never goes backwards.
+pub fn monotonic() -> Duration {
+    Duration::from_nanos(monotonic_ns())
+}
+
+/// Time elapsed since the Unix epoch.
+pub fn realtime() -> Duration {
+    Duration::from_nanos(BOOT_TIME.load(Ordering::Relaxed)) + monotonic()
+}
+
+/// The current date and time, e.g. for file timestamps.
+pub fn now() -> DateTime<Utc> {
+    let time = realtime();
+    DateTime::from_timestamp(time.as_secs() as i64, time.subsec_nanos()).unwrap_or_default()
+}
@@ -0,0 +1,54 @@
+//! Programmable Interval Timer, only used as a reference clock for calibration
+//!
+//! Channel 2 is used because its gate can be controlled and its output
+//! can be polled through the keyboard controller port B, no interrupt needed.
+//!
+//! reference: https://wiki.osdev.org/Programmable_Interval_Timer
+//! reference: https://wiki.osdev.org/APIC_timer#Initializing
+
+use x86_64::instructions::port::Port;
+
+/// The input clock of the PIT in Hz
+pub const PIT_FREQUENCY: u64 = 1_193_182;
+
+const CHANNEL2_DATA: u16 = 0x42;
+const MODE_COMMAND: u16 = 0x43;
+const PORT_B: u16 = 0x61;
+
+/// Port B: channel 2 gate
+const GATE2: u8 = 1 << 0;
+/// Port B: connect channel 2 output to the speaker
+const SPEAKER: u8 = 1 << 1;
+/// Port B: channel 2 output, read only
+const OUT2: u8 = 1 << 5;
+
+/// Channel 2, lobyte/hibyte access, mode 0 (interrupt on terminal count), binary
+const CHANNEL2_ONESHOT: u8 = 0b1011_0000;
+
+/// Starts a countdown of `ms` milliseconds on channel 2, at most 54 ms.
+pub fn start_countdown(ms: u64) {
+    let count = (PIT_FREQUENCY * ms / 1000).min(u16::MAX as u64) as u16;
+
+    let mut port_b = Port::<u8>::new(PORT_B);
+    let mut command = Port::<u8>::new(MODE_COMMAND);
+    let mut data = Port::<u8>::new(CHANNEL2_DATA);
+
+    unsafe {
+        // disable the speaker and the gate while programming
+        let value = port_b.read() & !(SPEAKER | GATE2);
+        port_b.write(value);
+
+        command.write(CHANNEL2_ONESHOT);
+        data.write(count as u8);
+        data.write((count >> 8) as u8);
+
+        // the rising edge of the gate starts counting
+        port_b.write(value | GATE2);
+    }
+}
+
+/// Whether the countdown started by `start_countdown` has reached zero.
+#[inline]
+pub fn countdown_done() -> bool {
+    unsafe { Port::<u8>::new(PORT_B).read() & OUT2 != 0 }
+}
@@ -0,0 +1,162 @@
+//! Kernel timers
+//!
+//! Timers are kept ordered by their deadline in monotonic nanoseconds,
+//! the clock interrupt runs the expired ones and, when the CPU is idle,
+//! the APIC timer is programmed in one-shot mode to the earliest deadline
+//! instead of ticking periodically.
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+/// Called from the clock interrupt with interrupts disabled,
+/// so it must not block.
+pub type TimerCallback = Box<dyn FnOnce() + Send>;
+
+/// Handle of a registered timer, used to cancel it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TimerId {
+    deadline: u64,
+    seq: u64,
+}
+
+impl TimerId {
+    /// Monotonic nanoseconds when the timer expires
+    #[inline]
+    pub fn deadline(&self) -> u64 {
+        self.deadline
+    }
+}
+
+/// Pending timers, ordered by deadline and then by registration order.
+static TIMERS: Mutex<BTreeMap<TimerId, TimerCallback>> = Mutex::new(BTreeMap::new());
+static NEXT_SEQ: AtomicU64 = AtomicU64::new(0);
+
+/// CPUs with the periodic tick stopped, bit `n` is the CPU with APIC ID `n`.
+/// CPUs with a larger APIC ID never stop their tick.
+static TICKLESS: AtomicU64 = AtomicU64::new(0);
+
+#[inline]
+fn cpu_bit() -> u64 {
+    1u64.checked_shl(crate::interrupt::cpu_id()).unwrap_or(0)
+}
+
+/// Registers `callback` to run at `deadline` monotonic nanoseconds.
+pub fn add_timer_at(deadline: u64, callback: TimerCallback) -> TimerId {
+    let id = TimerId {
+        deadline,
+        seq: NEXT_SEQ.fetch_add(1, Ordering::Relaxed),
+    };
+
+    without_interrupts(|| {
+        TIMERS.lock().insert(id, callback);
+
+        // the one-shot timer may be armed for a later deadline,
+        // other CPUs are either ticking or will be woken up to reschedule
+        if is_tickless() {
+            program_idle();
+        }
+    });
+
+    id
+}
+
+/// Registers `callback` to run after `timeout`.
+pub fn add_timer(timeout: Duration, callback: TimerCallback) -> TimerId {
+    let timeout = timeout.as_nanos().min(u64::MAX as u128) as u64;
+    add_timer_at(super::monotonic_ns().saturating_add(timeout), callback)
+}
+
+/// Removes a pending timer, returns false if it has already run.
+pub fn cancel_timer(id: TimerId) -> bool {
+    without_interrupts(|| TIMERS.lock().remove(&id).is_some())
+}
+
+/// The earliest pending deadline
+pub fn next_deadline() -> Option<u64> {
+    without_interrupts(|| TIMERS.lock().keys().next().map(TimerId::deadline))
+}
+
+pub fn pending_timers() -> usize {
+    without_interrupts(|| TIMERS.lock().len())
+}
+
+/// Runs the callbacks of all expired timers.
+///
+/// The callbacks are taken out before running,
+/// so they are free to register new timers.
+pub fn run_expired() {
+    let now = super::monotonic_ns();
+
+    let expired = without_interrupts(|| {
+        let mut timers = TIMERS.lock();
+        let pending = timers.split_off(&TimerId {
+            deadline: now.saturating_add(1),
+            seq: 0,
+        });
+        core::mem::replace(&mut *timers, pending)
+    });
+
+    expired.into_values().for_each(|callback| callback());
+}
+
+/// Programs the APIC timer for the next deadline,
+/// or stops it when no timer is pending.
+fn program_idle() {
+    match TIMERS.lock().keys().next() {
+        Some(id) => super::set_oneshot(id.deadline.saturating_sub(super::monotonic_ns())),
+        None => crate::interrupt::stop_timer(),
+    }
+}
+
+/// Stops the periodic tick on the current CPU,
+/// the next interrupt comes from the earliest timer or a device.
+pub fn enter_idle() {
+    let bit = cpu_bit();
+    if bit == 0 {
+        return;
+    }
+
+    without_interrupts(|| {
+        TICKLESS.fetch_or(bit, Ordering::Relaxed);
+        program_idle();
+    })
+}
+
+/// Restarts the periodic tick of the current CPU if it was stopped by `enter_idle`.
+pub fn exit_idle() {
+    let bit = cpu_bit();
+    if TICKLESS.fetch_and(!bit, Ordering::Relaxed) & bit != 0 {
+        super::start_periodic();
+    }
+}
+
+/// Restarts the tick of the current CPU and makes the other
+/// tickless CPUs reschedule, e.g. after a process becomes ready.
+pub fn wake_idle() {
+    exit_idle();
+
+    let others = TICKLESS.load(Ordering::Relaxed) & !cpu_bit();
+    (0..u64::BITS)
+        .filter(|id| others & (1 << id) != 0)
+        .for_each(crate::interrupt::reschedule);
+}
+
+/// Makes the CPU with APIC ID `id` reschedule soon,
+/// e.g. after a process is queued on it.
+pub fn wake_cpu(id: u32) {
+    if id == crate::interrupt::cpu_id() {
+        exit_idle();
+    } else if TICKLESS.load(Ordering::Relaxed) & 1u64.checked_shl(id).unwrap_or(0) != 0 {
+        crate::interrupt::reschedule(id);
+    }
+}
+
+/// Whether the current CPU has stopped its tick
+#[inline]
+pub fn is_tickless() -> bool {
+    TICKLESS.load(Ordering::Relaxed) & cpu_bit() != 0
+}
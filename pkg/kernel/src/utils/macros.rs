@@ -1,3 +1,5 @@
+use crate::drivers::display::get_console;
+use crate::drivers::display::CONSOLE;
 use crate::drivers::serial::get_serial;
 use crate::drivers::serial::SERIAL;
 use core::fmt::*;
@@ -24,6 +26,17 @@ macro_rules! guard_access_fn {
                     stringify!($mutex has not been initialized or lockable)
                 )
             }
+
+            $(#[$meta])*
+            #[inline(never)]
+            #[allow(non_snake_case, dead_code)]
+            /// Spins while the lock is held by another CPU,
+            /// it never returns if this CPU holds the lock.
+            $v fn [< $fn _blocking >]<'a>() -> spin::MutexGuard<'a, $ty> {
+                $mutex.get().expect(
+                    stringify!($mutex has not been initialized)
+                ).lock()
+            }
         }
     };
 }
@@ -61,6 +74,10 @@ pub fn print_internal(args: Arguments) {
         if let Some(mut serial) = get_serial() {
             serial.write_fmt(args).unwrap();
         }
+        // mirror the output to the framebuffer console
+        if let Some(mut console) = get_console() {
+            console.write_fmt(args).unwrap();
+        }
     });
 }
 
@@ -69,6 +86,9 @@ pub fn print_internal(args: Arguments) {
 fn panic(info: &core::panic::PanicInfo) -> ! {
     // force unlock serial for panic output
     unsafe { SERIAL.get().unwrap().force_unlock() };
+    if let Some(console) = CONSOLE.get() {
+        unsafe { console.force_unlock() };
+    }
 
     error!("ERROR: panic!\n\n{:#?}", info);
     loop {}
@@ -1,8 +1,9 @@
-use alloc::{collections::btree_map::BTreeMap, string::String};
+use alloc::{collections::btree_map::BTreeMap, string::String, sync::Arc};
 use spin::Mutex;
-use storage::FileHandle;
+use storage::{FileHandle, SeekFrom};
 
-use crate::input::try_pop_key;
+use crate::drivers::tty;
+use syscall_def::WOULD_BLOCK;
 
 #[derive(Debug, Clone)]
 pub enum StdIO {
@@ -13,13 +14,17 @@ pub enum StdIO {
 
 #[derive(Debug)]
 pub struct ResourceSet {
-    pub handles: BTreeMap<u8, Mutex<Resource>>,
+    /// Shared with the file mappings of `Mmap`, which outlive the descriptor
+    pub handles: BTreeMap<u8, Arc<Mutex<Resource>>>,
+    /// Status flags of each descriptor, see `Fcntl`
+    pub flags: BTreeMap<u8, usize>,
 }
 
 impl Default for ResourceSet {
     fn default() -> Self {
         let mut res = Self {
             handles: BTreeMap::new(),
+            flags: BTreeMap::new(),
         };
 
         res.open(Resource::Console(StdIO::Stdin));
@@ -33,22 +38,50 @@ impl Default for ResourceSet {
 impl ResourceSet {
     pub fn open(&mut self, res: Resource) -> u8 {
         let fd = self.handles.len() as u8;
-        self.handles.insert(fd, Mutex::new(res));
+        self.handles.insert(fd, Arc::new(Mutex::new(res)));
         fd
     }
 
     pub fn close(&mut self, fd: u8) -> bool {
+        self.flags.remove(&fd);
         self.handles.remove(&fd).is_some()
     }
 
+    pub fn get(&self, fd: u8) -> Option<Arc<Mutex<Resource>>> {
+        self.handles.get(&fd).cloned()
+    }
+
+    pub fn get_flags(&self, fd: u8) -> Option<usize> {
+        self.handles
+            .contains_key(&fd)
+            .then(|| self.flags.get(&fd).copied().unwrap_or(0))
+    }
+
+    pub fn set_flags(&mut self, fd: u8, flags: usize) -> bool {
+        if !self.handles.contains_key(&fd) {
+            return false;
+        }
+
+        self.flags.insert(fd, flags);
+        true
+    }
+
+    /// Returns `WOULD_BLOCK` if the console has no input yet
     pub fn read(&self, fd: u8, buf: &mut [u8]) -> isize {
-        if let Some(count) = self.handles.get(&fd).and_then(|h| h.lock().read(buf)) {
-            count as isize
-        } else {
-            -1
+        match self.handles.get(&fd).map(|h| h.lock().read(buf)) {
+            Some(Ok(count)) => count as isize,
+            Some(Err(ReadError::WouldBlock)) => WOULD_BLOCK as isize,
+            _ => -1,
         }
     }
 
+    /// Whether `fd` is the console
+    pub fn is_console(&self, fd: u8) -> bool {
+        self.handles
+            .get(&fd)
+            .is_some_and(|h| matches!(*h.lock(), Resource::Console(_)))
+    }
+
     pub fn write(&self, fd: u8, buf: &[u8]) -> isize {
         if let Some(count) = self.handles.get(&fd).and_then(|h| h.lock().write(buf)) {
             count as isize
@@ -65,33 +98,46 @@ pub enum Resource {
     File(FileHandle),
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadError {
+    /// Nothing has been typed on the console yet
+    WouldBlock,
+    Failed,
+}
+
 impl Resource {
-    pub fn read(&mut self, buf: &mut [u8]) -> Option<usize> {
+    pub fn read(&mut self, buf: &mut [u8]) -> Result<usize, ReadError> {
         match self {
             Resource::Console(stdio) => match stdio {
-                StdIO::Stdin => {
-                    // FIXME: just read from kernel input buffer
-                    if let Some(k) = try_pop_key() {
-                        let c = k as u8;
-                        buf[0] = c;
-                        Some(1)
-                    } else {
-                        Some(0)
-                    }
-                }
-                _ => None,
+                StdIO::Stdin => tty::read(buf).ok_or(ReadError::WouldBlock),
+                _ => Err(ReadError::Failed),
             },
-            Resource::Null => Some(0),
+            Resource::Null => Ok(0),
             Resource::File(file) => {
                 // Read from file handle
-                match file.read(buf) {
-                    Ok(count) => Some(count),   
-                    Err(_) => None,
-                }
+                file.read(buf).map_err(|_| ReadError::Failed)
             }
         }
     }
 
+    /// Read a file at `offset`, its position is kept
+    pub fn read_at(&mut self, offset: usize, buf: &mut [u8]) -> Option<usize> {
+        let Resource::File(file) = self else {
+            return None;
+        };
+
+        let pos = file.seek(SeekFrom::Current(0)).ok()?;
+        file.seek(SeekFrom::Start(offset)).ok()?;
+        let count = file.read(buf);
+        file.seek(SeekFrom::Start(pos)).ok()?;
+
+        count.ok()
+    }
+
+    pub fn is_file(&self) -> bool {
+        matches!(self, Resource::File(_))
+    }
+
     pub fn write(&mut self, buf: &[u8]) -> Option<usize> {
         match self {
             Resource::Console(stdio) => match *stdio {
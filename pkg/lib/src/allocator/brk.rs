@@ -1,21 +1,73 @@
+//! The heap of the process, grown with `sys_brk` when it runs out.
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr::{null_mut, NonNull};
 use linked_list_allocator::LockedHeap;
 
 use crate::*;
 
-const HEAP_SIZE: usize = 8 * 1024 - 8; // 8 KiB
+/// The heap grows by multiples of it, 16 KiB
+const GROW_SIZE: usize = 16 * 1024;
+
+pub struct BrkAllocator(LockedHeap);
 
 #[global_allocator]
-static ALLOCATOR: LockedHeap = LockedHeap::empty();
+static ALLOCATOR: BrkAllocator = BrkAllocator(LockedHeap::empty());
 
 pub fn init() {
-    let heap_start = sys_brk(None).unwrap();
-    let heap_end = heap_start + HEAP_SIZE;
+    let mut heap = ALLOCATOR.0.lock();
+    assert!(grow(&mut heap, GROW_SIZE), "Failed to allocate heap");
+}
+
+/// Move the end of the heap by at least `size` bytes
+fn grow(heap: &mut linked_list_allocator::Heap, size: usize) -> bool {
+    let size = size.div_ceil(GROW_SIZE) * GROW_SIZE;
+
+    let start = if heap.size() == 0 {
+        match sys_brk(None) {
+            Some(start) => start,
+            None => return false,
+        }
+    } else {
+        heap.top() as usize
+    };
+
+    if sys_brk(Some(start + size)) != Some(start + size) {
+        return false;
+    }
+
+    unsafe {
+        if heap.size() == 0 {
+            heap.init(start as *mut u8, size);
+        } else {
+            heap.extend(size);
+        }
+    }
+
+    true
+}
+
+unsafe impl GlobalAlloc for BrkAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let mut heap = self.0.lock();
+
+        if let Ok(ptr) = heap.allocate_first_fit(layout) {
+            return ptr.as_ptr();
+        }
 
-    let ret = sys_brk(Some(heap_end)).expect("Failed to allocate heap");
+        // the free space at the end may be merged with the new one,
+        // so this is enough even if it is not all used
+        if !grow(&mut heap, layout.size() + layout.align()) {
+            return null_mut();
+        }
 
-    assert!(ret == heap_end, "Failed to allocate heap");
+        heap.allocate_first_fit(layout)
+            .map_or(null_mut(), NonNull::as_ptr)
+    }
 
-    unsafe { ALLOCATOR.lock().init(heap_start as *mut u8, HEAP_SIZE) };
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { self.0.lock().deallocate(NonNull::new_unchecked(ptr), layout) };
+    }
 }
 
 #[cfg(not(test))]
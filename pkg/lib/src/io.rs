@@ -1,6 +1,8 @@
 use crate::*;
 use alloc::string::{String, ToString};
 use alloc::vec;
+use alloc::vec::Vec;
+use syscall_def::{F_SETFL, O_NONBLOCK};
 
 pub struct Stdin;
 pub struct Stdout;
@@ -11,40 +13,38 @@ impl Stdin {
         Self
     }
 
+    /// Reads a line without the newline, the console edits and echoes it.
+    /// Blocks until the line is entered, or returns what is typed before
+    /// the end of input (Ctrl-D).
     pub fn read_line(&self) -> String {
-        // FIXME: allocate string
-        // FIXME: read from input buffer
-        //       - maybe char by char?
-        // FIXME: handle backspace / enter...
-        // FIXME: return string
-        let mut buf = String::new();
-        let mut char_buf = [0u8; 1];
-        loop {
-            if let Some(n) = sys_read(0, &mut char_buf) {
-                if n == 0 {
-                    continue;
-                }
-                let ch = char_buf[0] as char;
-                match ch {
-                    '\n' | '\r' => {
-                        self::print!("\n");
-                        break;
-                    }
-                    '\x08' | '\x7f' => {
-                        if !buf.is_empty() {
-                            self::print!("\x08\x20\x08");
-                            buf.pop();
-                        }
-                    }
-                    _ => {
-                        buf.push(ch);
-                        self::print!("{}", ch);
-                    }
-                }
+        let mut line = Vec::new();
+        let mut buf = [0u8; 128];
+
+        while let Some(n) = sys_read(0, &mut buf) {
+            if n == 0 {
+                break;
+            }
+
+            line.extend_from_slice(&buf[..n]);
+            if line.last() == Some(&b'\n') {
+                line.pop();
+                break;
             }
         }
 
-        buf
+        String::from_utf8_lossy(&line).into_owned()
+    }
+
+    /// Reads what has been typed, a whole line at most in canonical mode.
+    /// `Some(0)` is the end of input, `None` if non-blocking and nothing is typed.
+    pub fn read(&self, buf: &mut [u8]) -> Option<usize> {
+        sys_read(0, buf)
+    }
+
+    /// Makes `read` return at once when nothing is typed.
+    pub fn set_nonblocking(&self, nonblocking: bool) -> bool {
+        let flags = if nonblocking { O_NONBLOCK } else { 0 };
+        sys_fcntl(0, F_SETFL, flags).is_some()
     }
 }
 
@@ -11,7 +11,12 @@ extern crate syscall_def;
 #[macro_use]
 pub mod io;
 pub mod allocator;
+pub mod mman;
+pub mod resource;
+pub mod signal;
 pub mod sync;
+pub mod time;
+pub mod tty;
 pub extern crate alloc;
 
 mod syscall;
@@ -23,6 +28,7 @@ pub use chrono::*;
 pub use io::*;
 pub use sync::*;
 pub use syscall::*;
+pub use time::*;
 
 pub fn init() {
     #[cfg(feature = "brk_alloc")]
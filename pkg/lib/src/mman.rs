@@ -0,0 +1,15 @@
+//! Memory mappings, see `sys_mmap`
+
+use crate::*;
+
+pub use syscall_def::mman::*;
+
+/// Maps `len` bytes of zero-filled private memory
+pub fn map_anonymous(len: usize, prot: usize) -> Option<*mut u8> {
+    sys_mmap(0, len, prot, MAP_PRIVATE | MAP_ANONYMOUS, 0, 0).map(|addr| addr as *mut u8)
+}
+
+/// Maps `len` bytes of the file `fd` from `offset` read-only
+pub fn map_file(fd: u8, len: usize, offset: usize) -> Option<*const u8> {
+    sys_mmap(0, len, PROT_READ, MAP_PRIVATE, fd, offset).map(|addr| addr as *const u8)
+}
@@ -0,0 +1,3 @@
+//! Limits of the memory of the process, see `sys_get_rlimit` and `sys_set_rlimit`
+
+pub use syscall_def::resource::*;
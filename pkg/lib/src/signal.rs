@@ -0,0 +1,54 @@
+//! Signals, see `sys_kill` and `sys_sigaction`
+//!
+//! A handler is called with the signal number on the current stack, and the
+//! signal is blocked until it returns.
+
+use crate::*;
+use syscall_def::Syscall;
+
+pub use syscall_def::signal::*;
+
+// the handler returns here, the kernel resumes the interrupted code
+core::arch::global_asm!(
+    ".pushsection .text",
+    ".global __sigreturn_restorer",
+    "__sigreturn_restorer:",
+    "    mov rax, {sigreturn}",
+    "    int 0x80",
+    "    ud2",
+    ".popsection",
+    sigreturn = const Syscall::Sigreturn as usize,
+);
+
+unsafe extern "C" {
+    fn __sigreturn_restorer();
+}
+
+/// A signal handler, called with the signal number
+pub type SigHandler = extern "C" fn(usize);
+
+/// Calls `handler` when `sig` is received, returns false if it can not be caught.
+pub fn set_handler(sig: usize, handler: SigHandler) -> bool {
+    let restorer = __sigreturn_restorer as *const () as usize;
+    sys_sigaction(sig, handler as *const () as usize, restorer).is_some()
+}
+
+/// Discards `sig` from now on.
+pub fn ignore(sig: usize) -> bool {
+    sys_sigaction(sig, SIG_IGN, 0).is_some()
+}
+
+/// Restores the default action of `sig`.
+pub fn reset(sig: usize) -> bool {
+    sys_sigaction(sig, SIG_DFL, 0).is_some()
+}
+
+/// Keeps the signals in `set` pending until unblocked, returns the old mask.
+pub fn block(set: u64) -> u64 {
+    sys_sigprocmask(SIG_BLOCK, set).unwrap_or_default()
+}
+
+/// Returns the old mask, pending signals in `set` are handled at once.
+pub fn unblock(set: u64) -> u64 {
+    sys_sigprocmask(SIG_UNBLOCK, set).unwrap_or_default()
+}
@@ -64,6 +64,12 @@ impl Semaphore {
         sys_sem_wait(self.key);
     }
 
+    /// Returns false if the semaphore is not acquired before `timeout`.
+    #[inline(always)]
+    pub fn wait_timeout(&self, timeout: core::time::Duration) -> bool {
+        sys_sem_timed_wait(self.key, timeout)
+    }
+
     #[inline(always)]
     pub fn remove(&self) -> bool {
         sys_remove_sem(self.key)
@@ -1,4 +1,12 @@
-use syscall_def::Syscall;
+use core::time::Duration;
+use syscall_def::mman::{MmapArgs, MAP_FAILED};
+use syscall_def::{Syscall, NO_TIMEOUT, STOPPED, TIMED_OUT, WUNTRACED};
+
+/// Timeouts are passed in nanoseconds, `NO_TIMEOUT` is never reached
+#[inline(always)]
+fn timeout_ns(timeout: Duration) -> usize {
+    timeout.as_nanos().min(NO_TIMEOUT as u128 - 1) as usize
+}
 
 #[inline(always)]
 pub fn sys_write(fd: u8, buf: &[u8]) -> Option<usize> {
@@ -41,27 +49,37 @@ pub fn sys_wait_pid(pid: u16) -> isize {
     //         // return 0;
     //     }
     // }
-    syscall!(Syscall::WaitPid, pid as u64) as isize
+    syscall!(Syscall::WaitPid, pid as u64, NO_TIMEOUT, 0) as isize
 }
 
+/// Waits for the process to exit or stop, `None` if it has stopped.
 #[inline(always)]
-pub fn sys_list_app() {
-    syscall!(Syscall::ListApp);
+pub fn sys_wait_pid_untraced(pid: u16) -> Option<isize> {
+    match syscall!(Syscall::WaitPid, pid as u64, NO_TIMEOUT, WUNTRACED) {
+        STOPPED => None,
+        // the exit code is kept, so this returns at once
+        _ => Some(sys_wait_pid(pid)),
+    }
 }
 
+/// Waits for the process to exit, `None` if it is still running after `timeout`.
 #[inline(always)]
-pub fn sys_stat() {
-    syscall!(Syscall::Stat);
+pub fn sys_wait_pid_timeout(pid: u16, timeout: Duration) -> Option<isize> {
+    match syscall!(Syscall::WaitPid, pid as u64, timeout_ns(timeout), 0) {
+        TIMED_OUT => None,
+        // the exit code is kept, so this returns at once
+        _ => Some(sys_wait_pid(pid)),
+    }
 }
 
 #[inline(always)]
-pub fn sys_allocate(layout: &core::alloc::Layout) -> *mut u8 {
-    syscall!(Syscall::Allocate, layout as *const _) as *mut u8
+pub fn sys_list_app() {
+    syscall!(Syscall::ListApp);
 }
 
 #[inline(always)]
-pub fn sys_deallocate(ptr: *mut u8, layout: &core::alloc::Layout) -> usize {
-    syscall!(Syscall::Deallocate, ptr, layout as *const _)
+pub fn sys_stat() {
+    syscall!(Syscall::Stat);
 }
 
 #[inline(always)]
@@ -80,9 +98,16 @@ pub fn sys_exit(code: isize) -> ! {
     unreachable!("This process should be terminated by now.")
 }
 
+/// Returns the pid of the child to the parent and 0 to the child,
+/// `None` if there is not enough memory to fork.
 #[inline(always)]
-pub fn sys_fork() -> u16 {
-    syscall!(Syscall::Fork) as u16
+pub fn sys_fork() -> Option<u16> {
+    let ret = syscall!(Syscall::Fork) as isize;
+    if ret.is_negative() {
+        None
+    } else {
+        Some(ret as u16)
+    }
 }
 
 #[inline(always)]
@@ -105,6 +130,23 @@ pub fn sys_sem_wait(key: u32) {
     syscall!(Syscall::Sem, 3, key as usize, 0);
 }
 
+/// Returns false if the semaphore is not acquired before `timeout`.
+#[inline(always)]
+pub fn sys_sem_timed_wait(key: u32, timeout: Duration) -> bool {
+    syscall!(Syscall::Sem, 4, key as usize, timeout_ns(timeout)) == 0
+}
+
+#[inline(always)]
+pub fn sys_sleep(ms: u64) {
+    syscall!(Syscall::Sleep, ms);
+}
+
+#[inline(always)]
+pub fn sys_nanosleep(duration: Duration) -> bool {
+    let ts = [duration.as_secs(), duration.subsec_nanos() as u64];
+    syscall!(Syscall::Nanosleep, ts.as_ptr() as u64) == 0
+}
+
 #[inline(always)]
 pub fn sys_list_dir(path: &str) {
     syscall!(Syscall::ListDir, path.as_ptr() as u64, path.len() as u64);
@@ -127,4 +169,146 @@ pub fn sys_brk(addr: Option<usize>) -> Option<usize> {
         BRK_FAILED => None,
         ret => Some(ret),
     }
-}
\ No newline at end of file
+}
+
+/// Maps `len` bytes of `fd` from `offset`, or zero-filled memory with
+/// `MAP_ANONYMOUS`, returns the address. See `syscall_def::mman`.
+#[inline(always)]
+pub fn sys_mmap(
+    addr: usize,
+    len: usize,
+    prot: usize,
+    flags: usize,
+    fd: u8,
+    offset: usize,
+) -> Option<usize> {
+    let args = MmapArgs {
+        addr,
+        len,
+        prot,
+        flags,
+        fd: fd as usize,
+        offset,
+    };
+
+    match syscall!(Syscall::Mmap, &args as *const MmapArgs as usize) {
+        MAP_FAILED => None,
+        ret => Some(ret),
+    }
+}
+
+/// Unmaps the pages in the range, `addr` must be page aligned.
+#[inline(always)]
+pub fn sys_munmap(addr: usize, len: usize) -> bool {
+    syscall!(Syscall::Munmap, addr, len) == 0
+}
+
+/// Changes the protection of the mapped pages in the range.
+#[inline(always)]
+pub fn sys_mprotect(addr: usize, len: usize, prot: usize) -> bool {
+    syscall!(Syscall::Mprotect, addr, len, prot) == 0
+}
+
+#[inline(always)]
+pub fn sys_time() -> u64 {
+    syscall!(Syscall::Time) as u64
+}
+
+#[inline(always)]
+pub fn sys_clock_get_time(clock: usize) -> Option<core::time::Duration> {
+    let mut ts = [0u64; 2];
+    match syscall!(Syscall::ClockGetTime, clock, ts.as_mut_ptr() as u64) {
+        0 => Some(core::time::Duration::new(ts[0], ts[1] as u32)),
+        _ => None,
+    }
+}
+
+/// Restricts the process to the CPUs in `mask`, bit `n` is the CPU with APIC ID `n`.
+/// `pid` 0 is the current process.
+#[inline(always)]
+pub fn sys_set_affinity(pid: u16, mask: u64) -> bool {
+    syscall!(Syscall::SetAffinity, pid as u64, mask) == 0
+}
+
+#[inline(always)]
+pub fn sys_get_affinity(pid: u16) -> Option<u64> {
+    let mut mask = 0u64;
+    match syscall!(Syscall::GetAffinity, pid as u64, &mut mask as *mut u64 as u64) {
+        0 => Some(mask),
+        _ => None,
+    }
+}
+
+/// The limit of `resource` of the current process in bytes,
+/// see `syscall_def::resource`.
+#[inline(always)]
+pub fn sys_get_rlimit(resource: usize) -> Option<u64> {
+    let mut limit = 0u64;
+    match syscall!(Syscall::GetRlimit, resource, &mut limit as *mut u64 as u64) {
+        0 => Some(limit),
+        _ => None,
+    }
+}
+
+/// Lowers the limit of `resource` of the current process in bytes, it is
+/// inherited by the children forked or spawned after. Raising it fails.
+#[inline(always)]
+pub fn sys_set_rlimit(resource: usize, limit: u64) -> bool {
+    syscall!(Syscall::SetRlimit, resource, limit) == 0
+}
+
+/// Sets the nice value of the process, from -20 (highest priority) to 19.
+/// `pid` 0 is the current process.
+#[inline(always)]
+pub fn sys_set_priority(pid: u16, nice: i8) -> bool {
+    syscall!(Syscall::SetPriority, pid as u64, nice as i64 as u64) == 0
+}
+
+#[inline(always)]
+pub fn sys_get_priority(pid: u16) -> Option<i8> {
+    match syscall!(Syscall::GetPriority, pid as u64) {
+        usize::MAX => None,
+        ret => Some((20 - ret as isize) as i8),
+    }
+}
+
+/// Sends `sig` to the process, signal 0 only checks that it exists.
+#[inline(always)]
+pub fn sys_kill(pid: u16, sig: usize) -> bool {
+    syscall!(Syscall::Kill, pid as u64, sig) == 0
+}
+
+/// Sets the handler of `sig` to `SIG_DFL`, `SIG_IGN` or a function returning
+/// to `restorer`, returns the old handler. See `signal::set_handler`.
+#[inline(always)]
+pub fn sys_sigaction(sig: usize, handler: usize, restorer: usize) -> Option<usize> {
+    match syscall!(Syscall::Sigaction, sig, handler, restorer) {
+        usize::MAX => None,
+        old => Some(old),
+    }
+}
+
+/// Changes the blocked signals, `how` is `SIG_BLOCK`, `SIG_UNBLOCK` or
+/// `SIG_SETMASK`. Returns the old mask.
+#[inline(always)]
+pub fn sys_sigprocmask(how: usize, set: u64) -> Option<u64> {
+    match syscall!(Syscall::Sigprocmask, how, set) {
+        usize::MAX => None,
+        old => Some(old as u64),
+    }
+}
+
+/// Sends a `syscall_def::tty` request to the console, see `tty`.
+#[inline(always)]
+pub fn sys_ioctl(fd: u8, request: usize, arg: usize) -> bool {
+    syscall!(Syscall::Ioctl, fd as u64, request, arg) == 0
+}
+
+/// Gets or sets the status flags of `fd`, only `O_NONBLOCK` can be set.
+#[inline(always)]
+pub fn sys_fcntl(fd: u8, cmd: usize, flags: usize) -> Option<usize> {
+    match syscall!(Syscall::Fcntl, fd as u64, cmd, flags) {
+        usize::MAX => None,
+        ret => Some(ret),
+    }
+}
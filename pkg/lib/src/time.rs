@@ -0,0 +1,23 @@
+use crate::*;
+use core::time::Duration;
+
+/// Wall clock time, may jump when the system time changes
+pub const CLOCK_REALTIME: usize = 0;
+/// Time since boot, never goes backwards
+pub const CLOCK_MONOTONIC: usize = 1;
+
+/// The current date and time in UTC.
+pub fn now() -> DateTime<Utc> {
+    let time = sys_clock_get_time(CLOCK_REALTIME).unwrap_or_default();
+    DateTime::from_timestamp(time.as_secs() as i64, time.subsec_nanos()).unwrap_or_default()
+}
+
+/// Time elapsed since boot.
+pub fn uptime() -> Duration {
+    sys_clock_get_time(CLOCK_MONOTONIC).unwrap_or_default()
+}
+
+/// Blocks the current process for at least `duration`.
+pub fn sleep(duration: Duration) {
+    sys_nanosleep(duration);
+}
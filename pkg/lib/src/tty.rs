@@ -0,0 +1,29 @@
+//! Console modes and the foreground process, see `sys_ioctl`
+
+use crate::*;
+
+pub use syscall_def::tty::*;
+
+/// The current mode flags of the console
+pub fn mode() -> u64 {
+    let mut mode = DEFAULT_MODE;
+    sys_ioctl(0, TCGETS, &mut mode as *mut u64 as usize);
+    mode
+}
+
+/// Sets the mode flags, `0` is raw mode: no editing, echo or signals.
+pub fn set_mode(mode: u64) -> bool {
+    sys_ioctl(0, TCSETS, mode as usize)
+}
+
+/// The process receiving Ctrl-C and Ctrl-Z
+pub fn foreground() -> Option<u16> {
+    let mut pid = 0u16;
+    sys_ioctl(0, TIOCGPGRP, &mut pid as *mut u16 as usize);
+    (pid != 0).then_some(pid)
+}
+
+/// Sets the process receiving Ctrl-C and Ctrl-Z, `None` for no process.
+pub fn set_foreground(pid: Option<u16>) -> bool {
+    sys_ioctl(0, TIOCSPGRP, pid.unwrap_or(0) as usize)
+}
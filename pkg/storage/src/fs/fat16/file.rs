@@ -107,10 +107,32 @@ impl Read for File {
     }
 }
 
-// NOTE: `Seek` trait is not required for this lab
 impl Seek for File {
     fn seek(&mut self, pos: SeekFrom) -> FsResult<usize> {
-        unimplemented!()
+        let offset = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(delta) => self.length().checked_add_signed(delta),
+            SeekFrom::Current(delta) => self.offset.checked_add_signed(delta),
+        }
+        .ok_or(FsError::InvalidOffset)?;
+
+        // 从第一个 cluster 沿 FAT 找到 offset 所在的 cluster
+        let cluster_size = self.handle.bpb.sectors_per_cluster() as usize
+            * self.handle.bpb.bytes_per_sector() as usize;
+        let mut cluster = self.entry.cluster;
+
+        for _ in 0..offset / cluster_size {
+            match self.handle.next_cluster(&cluster) {
+                Ok(next) => cluster = next,
+                Err(err) if offset < self.length() => return Err(err),
+                // nothing is read past the end of the file
+                Err(_) => break,
+            }
+        }
+
+        self.offset = offset;
+        self.current_cluster = cluster;
+        Ok(offset)
     }
 }
 
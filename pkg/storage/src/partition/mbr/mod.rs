@@ -24,6 +24,26 @@ where
     _block: PhantomData<B>,
 }
 
+impl<T, B> MbrTable<T, B>
+where
+    T: BlockDevice<B> + Clone,
+    B: BlockTrait,
+{
+    /// The first partition of `partition_type`, whether it is active or not
+    pub fn find_partition(&self, partition_type: u8) -> Option<Partition<T, B>> {
+        self.partitions
+            .iter()
+            .find(|part| part.partition_type() == partition_type && part.total_lba() != 0)
+            .map(|part| {
+                Partition::new(
+                    self.inner.clone(),
+                    part.begin_lba() as usize,
+                    part.total_lba() as usize,
+                )
+            })
+    }
+}
+
 impl<T, B> PartitionTable<T, B> for MbrTable<T, B>
 where
     T: BlockDevice<B> + Clone,
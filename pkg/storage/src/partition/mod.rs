@@ -65,7 +65,7 @@ where
     B: BlockTrait,
 {
     fn block_count(&self) -> FsResult<usize> {
-        self.inner.block_count()
+        Ok(self.size)
     }
 
     fn read_block(&self, offset: usize, block: &mut B) -> FsResult {
@@ -85,10 +85,7 @@ where
             return Err(FsError::InvalidOffset);
         }
 
-        // FIXME: calculate the block offset for inner device
-        // FIXME: write to the inner device
-        // let block_offset = self.offset + offset * B::size();
-        // self.inner.write_block(block_offset, block)
-        todo!("Writing to partition is not implemented yet");
+        let block_offset = self.offset + offset;
+        self.inner.write_block(block_offset, block)
     }
 }
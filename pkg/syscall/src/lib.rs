@@ -3,6 +3,30 @@
 use num_enum::FromPrimitive;
 
 pub mod macros;
+pub mod mman;
+pub mod resource;
+pub mod signal;
+pub mod tty;
+
+/// Returned by `WaitPid` and the timed semaphore wait when the timeout expires
+pub const TIMED_OUT: usize = isize::MIN as usize;
+/// Returned by `WaitPid` with a timeout or `WUNTRACED` when the process has
+/// exited, its exit code is then returned by `WaitPid` without them
+pub const EXITED: usize = 0;
+/// Passed as the timeout to wait forever
+pub const NO_TIMEOUT: usize = usize::MAX;
+/// Returned by a read on a non-blocking descriptor with nothing to read
+pub const WOULD_BLOCK: usize = -11isize as usize;
+
+/// `Fcntl` commands and the flag they get and set
+pub const F_GETFL: usize = 3;
+pub const F_SETFL: usize = 4;
+pub const O_NONBLOCK: usize = 0o4000;
+
+/// `WaitPid` option to also return when the process is stopped
+pub const WUNTRACED: usize = 2;
+/// Returned by `WaitPid` with `WUNTRACED` when the process is stopped
+pub const STOPPED: usize = isize::MIN as usize + 1;
 
 #[repr(usize)]
 #[derive(Clone, Debug, FromPrimitive)]
@@ -12,7 +36,16 @@ pub enum Syscall {
     Open = 2,
     Close = 3,
 
+    Mmap = 9,
+    Mprotect = 10,
+    Munmap = 11,
     Brk = 12,
+    Sigaction = 13,
+    Sigprocmask = 14,
+    Sigreturn = 15,
+    Ioctl = 16,
+
+    Nanosleep = 35,
 
     GetPid = 39,
 
@@ -20,13 +53,27 @@ pub enum Syscall {
     Spawn = 59,
     Exit = 60,
     WaitPid = 61,
+    Kill = 62,
 
     Sem = 66,
 
+    Fcntl = 72,
+
+    GetRlimit = 97,
+
+    GetPriority = 140,
+    SetPriority = 141,
+
+    SetRlimit = 160,
+
+    Time = 201,
+    SetAffinity = 203,
+    GetAffinity = 204,
+    ClockGetTime = 228,
+
+    Sleep = 65530,
     ListApp = 65531,
     Stat = 65532,
-    Allocate = 65533,
-    Deallocate = 65534,
 
     ListDir = 114514,
 
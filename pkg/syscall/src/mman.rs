@@ -0,0 +1,35 @@
+//! Protection and flags of `Mmap` and `Mprotect`,
+//! named and numbered after their Linux counterparts.
+
+/// The pages can not be accessed
+pub const PROT_NONE: usize = 0;
+pub const PROT_READ: usize = 0x1;
+pub const PROT_WRITE: usize = 0x2;
+pub const PROT_EXEC: usize = 0x4;
+
+/// Shared with the other mappings of the file, only read-only ones are supported
+pub const MAP_SHARED: usize = 0x01;
+/// Written pages are private to the process
+pub const MAP_PRIVATE: usize = 0x02;
+/// Map at exactly `addr`, replacing the mappings there
+pub const MAP_FIXED: usize = 0x10;
+/// Not backed by a file, the pages are zero-filled
+pub const MAP_ANONYMOUS: usize = 0x20;
+
+/// Returned by `Mmap` on failure
+pub const MAP_FAILED: usize = usize::MAX;
+
+/// The arguments of `Mmap`, passed by pointer since a syscall only takes three
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct MmapArgs {
+    /// Where to map, only a hint without `MAP_FIXED`, `0` for anywhere
+    pub addr: usize,
+    pub len: usize,
+    pub prot: usize,
+    pub flags: usize,
+    /// Ignored with `MAP_ANONYMOUS`
+    pub fd: usize,
+    /// Offset in the file, page aligned
+    pub offset: usize,
+}
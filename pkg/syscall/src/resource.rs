@@ -0,0 +1,12 @@
+//! Resources of `GetRlimit` and `SetRlimit`,
+//! named and numbered after their Linux counterparts.
+
+/// The size of the heap grown by `Brk`
+pub const RLIMIT_DATA: usize = 2;
+/// The size of the stack
+pub const RLIMIT_STACK: usize = 3;
+/// The size of the stack, the heap and the mapped areas in total
+pub const RLIMIT_AS: usize = 9;
+
+/// No limit, the stack and the heap are still limited by their regions
+pub const RLIM_INFINITY: u64 = u64::MAX;
@@ -0,0 +1,37 @@
+//! Signal numbers and constants shared by the kernel and the user library,
+//! the numbers are the same as on Linux x86_64.
+
+pub const SIGINT: usize = 2;
+pub const SIGILL: usize = 4;
+pub const SIGBUS: usize = 7;
+pub const SIGFPE: usize = 8;
+pub const SIGKILL: usize = 9;
+pub const SIGSEGV: usize = 11;
+pub const SIGTERM: usize = 15;
+pub const SIGCHLD: usize = 17;
+pub const SIGCONT: usize = 18;
+pub const SIGSTOP: usize = 19;
+/// Stop typed at the terminal, unlike SIGSTOP it can be caught
+pub const SIGTSTP: usize = 20;
+
+/// Signals are numbered from 1 to `NSIG - 1`
+pub const NSIG: usize = 64;
+
+/// Handler values of `Sigaction`
+pub const SIG_DFL: usize = 0;
+pub const SIG_IGN: usize = 1;
+
+/// `how` of `Sigprocmask`
+pub const SIG_BLOCK: usize = 0;
+pub const SIG_UNBLOCK: usize = 1;
+pub const SIG_SETMASK: usize = 2;
+
+/// The bit of `sig` in a signal set
+#[inline]
+pub const fn sigbit(sig: usize) -> u64 {
+    if sig == 0 || sig >= NSIG {
+        0
+    } else {
+        1 << sig
+    }
+}
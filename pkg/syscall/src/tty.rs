@@ -0,0 +1,21 @@
+//! Requests and mode flags of `Ioctl` on the console,
+//! named and numbered after their Linux counterparts.
+
+/// Get the mode flags, arg: `*mut u64`
+pub const TCGETS: usize = 0x5401;
+/// Set the mode flags, arg: `u64`
+pub const TCSETS: usize = 0x5402;
+/// Get the foreground process, arg: `*mut u16`
+pub const TIOCGPGRP: usize = 0x540F;
+/// Set the foreground process, 0 for none, arg: `u16`
+pub const TIOCSPGRP: usize = 0x5410;
+
+/// Ctrl-C, Ctrl-Z send signals to the foreground process
+pub const ISIG: u64 = 0o1;
+/// Input is edited and read line by line, Ctrl-D ends it
+pub const ICANON: u64 = 0o2;
+/// Input is echoed
+pub const ECHO: u64 = 0o10;
+
+/// The mode of a shell, raw mode is `0`
+pub const DEFAULT_MODE: u64 = ISIG | ICANON | ECHO;
@@ -2,6 +2,7 @@
 
 import os
 import shutil
+import struct
 import subprocess
 import argparse
 
@@ -13,6 +14,8 @@ parser.add_argument('-i', '--intdbg', action='store_true',
                     help='Enable interrupt output for qemu')
 parser.add_argument('-m', '--memory', default='96M',
                     help='Set memory size for qemu, default is 96M')
+parser.add_argument('-s', '--smp', type=int, default=1,
+                    help='Set number of CPUs for qemu, default is 1')
 parser.add_argument('-o', '--output', default='-nographic',
                     help='Set output for qemu, default is -nographic')
 parser.add_argument('-p', '--profile', type=str, choices=['release', 'debug'],
@@ -23,6 +26,10 @@ parser.add_argument('--dry-run', action='store_true', help='Enable dry run')
 parser.add_argument('--bios', type=str,
                     default=os.path.join('assets', 'OVMF.fd'), help='Set BIOS path')
 parser.add_argument('--boot', type=str, default='esp', help='Set boot path')
+parser.add_argument('--virtio', action='store_true',
+                    help='Attach the disk as a virtio-blk device instead of IDE')
+parser.add_argument('--swap', type=str, default='16M',
+                    help='Set size of the swap disk for qemu, 0 to disable, default is 16M')
 parser.add_argument('--debug-listen', type=str, default='0.0.0.0:1234',
                     help='Set listen address for gdbserver')
 
@@ -84,8 +91,18 @@ def qemu(output: str = '-nographic', memory: str = '96M', debug: bool = False, i
     if qemu_exe is None:
         raise Exception('qemu-system-x86_64 not found in PATH')
 
+    drive = 'format=raw,file=fat:esp'
+    if args.virtio:
+        drive += ',if=virtio'
+
     qemu_args = [qemu_exe, '-bios', args.bios, '-net', 'none', *output.split(),
-                 '-m', memory, '-drive', 'format=raw,file=fat:esp', '-snapshot']
+                 '-m', memory, '-smp', str(args.smp), '-drive', drive, '-snapshot']
+
+    # the kernel looks for the swap partition on the second IDE disk
+    swap_size = parse_size(args.swap)
+    if swap_size > 0:
+        make_swap('swap.img', swap_size)
+        qemu_args += ['-drive', 'format=raw,file=swap.img,if=ide,index=1']
 
     if debug:
         qemu_args += ['-gdb', f'tcp:{args.debug_listen}', '-S']
@@ -95,6 +112,41 @@ def qemu(output: str = '-nographic', memory: str = '96M', debug: bool = False, i
     execute_command(qemu_args)
 
 
+def parse_size(size: str) -> int:
+    units = {'K': 1 << 10, 'M': 1 << 20, 'G': 1 << 30}
+
+    if size[-1:].upper() in units:
+        return int(size[:-1]) * units[size[-1].upper()]
+
+    return int(size)
+
+
+def make_swap(path: str, size: int):
+    # a disk with a swap partition (type 0x82) from sector 2048
+    start = 2048
+    sectors = size // 512
+
+    if sectors <= start:
+        raise Exception(f'swap size {size} is too small')
+
+    if os.path.exists(path) and os.path.getsize(path) == size:
+        return
+
+    if args.dry_run:
+        debug('Would create', f'{path} of {size} bytes')
+        return
+
+    mbr = bytearray(512)
+    mbr[0x1BE:0x1CE] = struct.pack(
+        '<B3sB3sII', 0, bytes(3), 0x82, bytes(3), start, sectors - start)
+    mbr[0x1FE:0x200] = b'\x55\xaa'
+
+    debug('Creating', f'{path} of {size} bytes')
+    with open(path, 'wb') as f:
+        f.write(mbr)
+        f.truncate(size)
+
+
 def copy_to_esp(src: str, dst: str):
     dst = os.path.join(os.getcwd(), args.boot, dst)
 